@@ -0,0 +1,82 @@
+//! Actisense ASCII logs on the host, on top of `shared_core::format`.
+//!
+//! ```text
+//! 10:00:00.123 R 09F80123 01 02 03 04 05 06 07 08
+//! ```
+//!
+//! Lines only carry the time of day, to the millisecond. The reader turns it
+//! into a running timestamp: a step back of more than half a day is taken as
+//! midnight, anything shorter as lines slightly out of order.
+
+use std::io::{self, BufRead};
+
+use shared_core::format::parse_actisense;
+
+use crate::capture::wire::TimestampedFrame;
+
+const DAY_MS: u64 = 24 * 3600 * 1000;
+
+/// Reads a log line by line, `R` and `T` lines alike. Blank lines are skipped;
+/// a malformed line is an [`io::ErrorKind::InvalidData`] error naming it,
+/// after which reading can go on.
+pub struct ActisenseReader<R> {
+    inner: R,
+    line: Vec<u8>,
+    line_number: u64,
+    /// Days gone by since the first line.
+    days: u64,
+    last_ms: Option<u64>,
+}
+
+impl<R: BufRead> ActisenseReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            line: Vec::new(),
+            line_number: 0,
+            days: 0,
+            last_ms: None,
+        }
+    }
+
+    /// Microseconds since the midnight before the first line.
+    fn timestamp_us(&mut self, time_ms: u32) -> u64 {
+        let time_ms = time_ms as u64;
+        if let Some(last) = self.last_ms {
+            if last > time_ms + DAY_MS / 2 {
+                self.days += 1;
+            }
+        }
+        self.last_ms = Some(time_ms);
+        (self.days * DAY_MS + time_ms) * 1000
+    }
+}
+
+impl<R: BufRead> Iterator for ActisenseReader<R> {
+    type Item = io::Result<TimestampedFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.inner.read_until(b'\n', &mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+            self.line_number += 1;
+            if self.line.trim_ascii().is_empty() {
+                continue;
+            }
+            return Some(match parse_actisense(&self.line) {
+                Ok(line) => {
+                    let timestamp_us = self.timestamp_us(line.time_ms);
+                    Ok(TimestampedFrame::from_can_frame(&line.frame, timestamp_us))
+                }
+                Err(e) => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {e:?}", self.line_number),
+                )),
+            });
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! canboat `analyzer -json` output, decoded with korri-n2k.
+//!
+//! ```text
+//! {"timestamp":"2024-06-01T10:00:00.123Z","prio":2,"src":35,"dst":255,
+//!  "pgn":129025,"description":"Position, Rapid Update",
+//!  "fields":{"latitude":47.1,"longitude":-2.3}}
+//! ```
+//!
+//! One line per message, fast packets reassembled. Field names are the ones
+//! of the korri-n2k structs rather than canboat's, repeating groups are lists
+//! named after their array. Unavailable values are left out, as canboat does.
+//!
+//! Lookups carry their name, as canboat prints them, or their number when
+//! korri-n2k has no name for it. Messages korri-n2k has no struct for, or
+//! cannot decode, carry their payload in hex under `data`.
+
+use chrono::{DateTime, NaiveDate, SecondsFormat, TimeDelta};
+use korri_n2k::core::{FieldDescriptor, FieldKind, PgnValue};
+use serde_json::{json, Map, Value};
+
+use crate::messages::Message;
+use crate::pgn::{decode, descriptor, lookup_name, pgn_name};
+use crate::signalk::widen;
+
+/// The JSON object of `message`, which `timestamp_us` is Unix time.
+pub fn analyzer_json(message: &Message) -> Value {
+    let descriptor = descriptor(message.pgn);
+    let description = descriptor.map_or(pgn_name(message.pgn), |d| d.description);
+
+    let decoded = decode(message.pgn, &message.payload).and_then(Result::ok);
+    let fields = match (descriptor, decoded) {
+        (Some(descriptor), Some(decoded)) => {
+            let mut fields = Map::new();
+            let mut index = 0;
+            while index < descriptor.fields.len() {
+                let set = descriptor
+                    .repeating_field_sets
+                    .iter()
+                    .find(|set| set.start_field_index == index);
+                if let Some(set) = set {
+                    let group = &descriptor.fields[index..index + set.size];
+                    let count = decoded.repetitive_count(set.array_id).unwrap_or(0);
+                    let list: Vec<Value> = (0..count)
+                        .map(|i| {
+                            let mut entry = Map::new();
+                            for field in group {
+                                let value = decoded.repetitive_field(set.array_id, i, field.id);
+                                insert(&mut entry, field, value);
+                            }
+                            Value::Object(entry)
+                        })
+                        .collect();
+                    fields.insert(set.array_id.to_string(), Value::Array(list));
+                    index += set.size;
+                } else {
+                    let field = &descriptor.fields[index];
+                    insert(&mut fields, field, decoded.field(field.id));
+                    index += 1;
+                }
+            }
+            fields
+        }
+        _ => {
+            let mut fields = Map::new();
+            fields.insert("data".to_string(), json!(hex(&message.payload)));
+            fields
+        }
+    };
+
+    let timestamp = DateTime::from_timestamp_micros(message.timestamp_us as i64)
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Millis, true);
+    json!({
+        "timestamp": timestamp,
+        "prio": message.priority,
+        "src": message.source,
+        "dst": message.destination,
+        "pgn": message.pgn,
+        "description": description,
+        "fields": fields,
+    })
+}
+
+/// Adds `field` under its struct name, unless reserved or unavailable.
+fn insert(fields: &mut Map<String, Value>, field: &FieldDescriptor, value: Option<PgnValue>) {
+    if matches!(field.kind, FieldKind::Reserved | FieldKind::Spare) {
+        return;
+    }
+    if let Some(value) = value.and_then(|value| field_value(field, value)) {
+        fields.insert(field_name(field.id), value);
+    }
+}
+
+/// `CogReference` → `cog_reference`, keywords suffixed as the structs have
+/// them.
+fn field_name(id: &str) -> String {
+    let mut name = String::with_capacity(id.len() + 4);
+    let mut previous = None;
+    for c in id.chars() {
+        if c.is_ascii_uppercase() {
+            if previous.is_some_and(|p: char| p.is_ascii_lowercase() || p.is_ascii_digit()) {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+        } else {
+            name.push(c);
+        }
+        previous = Some(c);
+    }
+    if matches!(name.as_str(), "type" | "override") {
+        name.push_str("_field");
+    }
+    name
+}
+
+fn field_value(field: &FieldDescriptor, value: PgnValue) -> Option<Value> {
+    if let PgnValue::Bytes(bytes) = value {
+        let bytes = &bytes.data[..bytes.len];
+        return Some(match field.kind {
+            FieldKind::StringFix
+            | FieldKind::StringLz
+            | FieldKind::StringLau
+            | FieldKind::Decimal => json!(text(bytes)),
+            _ => json!(hex(bytes)),
+        });
+    }
+    let value = number(value)?;
+
+    if unavailable(field, &value) {
+        return None;
+    }
+    Some(match (&field.kind, value) {
+        (FieldKind::Date, value) => json!(date(value.as_f64())?),
+        (FieldKind::Time, value) => json!(time(value.as_f64())),
+        (FieldKind::Lookup, Number::Unsigned(v)) => field
+            .enum_direct_name
+            .and_then(|lookup| lookup_name(lookup, v))
+            .map_or_else(|| json!(v), |name| json!(name)),
+        (_, Number::Unsigned(v)) => json!(v),
+        (_, Number::Signed(v)) => json!(v),
+        (_, Number::Float(v)) => json!(v),
+    })
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Number {
+    Unsigned(u64),
+    Signed(i64),
+    Float(f64),
+}
+
+/// A numeric value, `None` for bytes and ignored fields.
+pub(crate) fn number(value: PgnValue) -> Option<Number> {
+    Some(match value {
+        PgnValue::Bytes(_) | PgnValue::Ignored => return None,
+        PgnValue::U64(v) => Number::Unsigned(v),
+        PgnValue::U32(v) => Number::Unsigned(v as u64),
+        PgnValue::U16(v) => Number::Unsigned(v as u64),
+        PgnValue::U8(v) => Number::Unsigned(v as u64),
+        PgnValue::I64(v) => Number::Signed(v),
+        PgnValue::I32(v) => Number::Signed(v as i64),
+        PgnValue::I16(v) => Number::Signed(v as i64),
+        PgnValue::I8(v) => Number::Signed(v as i64),
+        PgnValue::F64(v) => Number::Float(v),
+        PgnValue::F32(v) => Number::Float(widen(v)),
+    })
+}
+
+impl Number {
+    pub(crate) fn as_f64(self) -> f64 {
+        match self {
+            Self::Unsigned(v) => v as f64,
+            Self::Signed(v) => v as f64,
+            Self::Float(v) => v,
+        }
+    }
+}
+
+/// Whether the raw value is the largest of the field, "unavailable" in
+/// NMEA 2000. Lookups are left alone: their largest value is often named.
+pub(crate) fn unavailable(field: &FieldDescriptor, value: &Number) -> bool {
+    if matches!(
+        field.kind,
+        FieldKind::Lookup
+            | FieldKind::IndirectLookup
+            | FieldKind::BitLookup
+            | FieldKind::IsoName
+            | FieldKind::Float
+    ) {
+        return false;
+    }
+    let Some(bits @ 2..=64) = field.bits_length else {
+        return false;
+    };
+    let signed = field.is_signed.unwrap_or(false);
+    let max = if signed {
+        (1u64 << (bits - 1)) - 1
+    } else {
+        u64::MAX >> (64 - bits)
+    };
+    let resolution = field.resolution.unwrap_or(1.0) as f64;
+    let raw = match value {
+        Number::Unsigned(v) if resolution == 1.0 => return *v == max,
+        Number::Signed(v) if resolution == 1.0 => return *v == max as i64,
+        _ => (value.as_f64() / resolution).round(),
+    };
+    // A 32-bit raw value does not survive an f32 exactly.
+    (raw - max as f64).abs() <= max as f64 * f32::EPSILON as f64
+}
+
+/// Days since 1970-01-01 → `2024.06.01`, as canboat writes dates.
+pub(crate) fn date(days: f64) -> Option<String> {
+    let date = NaiveDate::from_ymd_opt(1970, 1, 1)? + TimeDelta::try_days(days as i64)?;
+    Some(date.format("%Y.%m.%d").to_string())
+}
+
+/// Seconds since midnight → `10:00:00`, with the fraction when there is one.
+pub(crate) fn time(seconds: f64) -> String {
+    let units = (seconds * 10_000.0).round() as u64;
+    let whole = units / 10_000;
+    let mut time = format!(
+        "{:02}:{:02}:{:02}",
+        whole / 3600,
+        whole / 60 % 60,
+        whole % 60
+    );
+    if !units.is_multiple_of(10_000) {
+        time.push_str(&format!(".{:04}", units % 10_000));
+    }
+    time
+}
+
+/// Text fields without their padding: NULs, 0xFF, `@` and trailing spaces.
+pub(crate) fn text(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|&b| b == 0 || b == 0xFF)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end])
+        .trim_end_matches(['@', ' '])
+        .to_string()
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
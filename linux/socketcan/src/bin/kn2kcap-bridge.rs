@@ -0,0 +1,383 @@
+//! Bridge the ESP32-S3 sniffer's USB stream onto a SocketCAN interface, so
+//! candump, Wireshark and the other tools see it as a bus.
+//!
+//! ```text
+//! sudo ip link add dev vcan0 type vcan && sudo ip link set up vcan0
+//! kn2kcap-bridge /dev/ttyACM0 vcan0
+//! kn2kcap-bridge /dev/ttyACM0 -          # candump lines on stdout instead
+//! ```
+//!
+//! Frames are written in the order the target captured them. A full
+//! interface queue is waited out rather than skipped, up to the send timeout
+//! of the boards. The log on stderr gives the target's counters every
+//! `--stats-every` snapshots and as soon as one of its loss counters moves,
+//! every sequence gap on the link, and resyncs on console output. When the
+//! port goes away, unplugged or reset, it is opened again once it is back.
+//! SIGTERM or Ctrl-C stops the bridge with its totals.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use korri_n2k::protocol::transport::CAN_SEND_TIMEOUT_MS;
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::sys::termios::{self, SetArg};
+use socketcan::{CanSocket, Socket};
+use socketcan_receiver::candump::{to_socketcan, write_candump, write_frame_with_deadline};
+use socketcan_receiver::capture::{
+    wire::{encode_version_request, VERSION},
+    CaptureReader, Event, Header, Integrity, Metadata, StatsSnapshot, TimestampedFrame,
+};
+
+/// Between two attempts at opening a missing port.
+const REOPEN_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Parser)]
+#[command(about = "Bridge a KN2KCAP serial stream onto a SocketCAN interface")]
+struct Args {
+    /// Serial port of the sniffer.
+    #[arg(default_value = "/dev/ttyACM0")]
+    port: String,
+    /// CAN interface to write to, or `-` for candump lines on stdout.
+    #[arg(default_value = "vcan0")]
+    interface: String,
+    /// Log the target's counters every N snapshots, one a second. Losses are
+    /// logged as they happen whatever this is.
+    #[arg(long, value_name = "N", default_value_t = 60)]
+    stats_every: u32,
+    /// KN2KCAP wire version asked of the target.
+    #[arg(long, value_name = "N", default_value_t = VERSION)]
+    wire_version: u8,
+    /// Stop when the port closes rather than wait for it to come back.
+    #[arg(long)]
+    once: bool,
+}
+
+enum Output {
+    Bus(CanSocket),
+    /// `-`: candump lines, host time.
+    Candump(io::Stdout),
+}
+
+struct Bridge {
+    port: String,
+    output: Output,
+    stats_every: u32,
+    integrity: Integrity,
+    header: Option<Header>,
+    metadata: Option<Metadata>,
+    /// Snapshots since the last one logged.
+    snapshots: u32,
+    last_stats: Option<StatsSnapshot>,
+    /// Lost on the target in the sessions before the current one.
+    target_lost: u64,
+    written: u64,
+    /// Frames the interface would not take in time, or SocketCAN cannot carry.
+    dropped: u64,
+}
+
+impl Bridge {
+    fn handle(&mut self, event: Event) -> Result<()> {
+        // A restart goes through `new_session`, which reads the window first.
+        if !matches!(event, Event::Restart) {
+            self.integrity.observe(&event);
+        }
+        match event {
+            Event::Frame(frame) => self.write(&frame)?,
+            Event::Header(header) => {
+                if self.header != Some(header) {
+                    eprintln!(
+                        "{}: wire version {}, bus at {} bit/s",
+                        self.port, header.version, header.bitrate
+                    );
+                    self.header = Some(header);
+                }
+            }
+            Event::Metadata(metadata) => {
+                if self.metadata != Some(metadata) {
+                    eprintln!("{}: {}", self.port, describe_target(&metadata));
+                    self.metadata = Some(metadata);
+                }
+            }
+            Event::Stats(stats) => self.stats(stats),
+            Event::LinkLoss(lost) => {
+                eprintln!(
+                    "warning: {}: {lost} record(s) lost on the USB link",
+                    self.port
+                );
+            }
+            Event::BadBatch(error) => {
+                eprintln!(
+                    "warning: {}: batch discarded ({error:?}), its records are lost",
+                    self.port
+                );
+            }
+            Event::Restart => {
+                eprintln!("{}: target restarted", self.port);
+                self.new_session();
+            }
+            Event::BadStats(_) => {
+                eprintln!("warning: {}: implausible counters discarded", self.port);
+            }
+            Event::Resync { skipped, text } => {
+                let what = if text { "console output" } else { "noise" };
+                eprintln!("{}: {skipped} bytes of {what} skipped", self.port);
+            }
+        }
+        Ok(())
+    }
+
+    /// The target counters start over, after a restart or on a reopened
+    /// port: what the last session lost is kept aside.
+    fn new_session(&mut self) {
+        self.target_lost += self
+            .integrity
+            .window()
+            .map_or(0, |window| window.lost() as u64);
+        self.integrity.observe(&Event::Restart);
+        self.last_stats = None;
+        self.snapshots = 0;
+    }
+
+    fn stats(&mut self, stats: StatsSnapshot) {
+        let losing = self.last_stats.is_some_and(|last| {
+            stats.lost() > last.lost()
+                || stats.bus_off > last.bus_off
+                || stats.soft_errors > last.soft_errors
+        });
+        let due = self.last_stats.is_none() || self.snapshots + 1 >= self.stats_every;
+        self.last_stats = Some(stats);
+        if !(losing || due) {
+            self.snapshots += 1;
+            return;
+        }
+        self.snapshots = 0;
+        let prefix = if losing { "warning: " } else { "" };
+        eprintln!(
+            "{prefix}{}: target {} frames, {} lost (channel {}, overrun {}, sink {}), \
+             {} bus off, {} errors, peak depth {}, backlog {}",
+            self.port,
+            stats.frames_rx,
+            stats.lost(),
+            stats.channel_drops,
+            stats.hw_overruns,
+            stats.sink_drops,
+            stats.bus_off,
+            stats.soft_errors,
+            stats.max_channel_depth,
+            stats.max_backlog_run
+        );
+    }
+
+    fn write(&mut self, frame: &TimestampedFrame) -> Result<()> {
+        match &mut self.output {
+            Output::Candump(stdout) => {
+                let now_us = SystemTime::now().duration_since(UNIX_EPOCH)?.as_micros() as u64;
+                let frame = TimestampedFrame {
+                    timestamp_us: now_us,
+                    ..*frame
+                };
+                write_candump(&mut stdout.lock(), "can0", &frame)?;
+            }
+            Output::Bus(socket) => {
+                let Some(can) = to_socketcan(frame) else {
+                    self.dropped += 1;
+                    return Ok(());
+                };
+                // Waiting on a full queue keeps the frames in order.
+                let deadline = Instant::now() + Duration::from_millis(CAN_SEND_TIMEOUT_MS as u64);
+                if !write_frame_with_deadline(socket, &can, deadline).context("CAN write error")? {
+                    if self.dropped == 0 {
+                        eprintln!("warning: interface queue full, frames dropped");
+                    }
+                    self.dropped += 1;
+                    return Ok(());
+                }
+            }
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    fn totals(&self) {
+        let target_lost = self.target_lost
+            + self
+                .integrity
+                .window()
+                .map_or(0, |window| window.lost() as u64);
+        eprintln!(
+            "bridged {} frames, {} dropped, {} lost on the USB link, {target_lost} lost on the target",
+            self.written, self.dropped, self.integrity.link_lost
+        );
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    if args.stats_every == 0 {
+        bail!("--stats-every must be above 0");
+    }
+    let output = if args.interface == "-" {
+        Output::Candump(io::stdout())
+    } else {
+        let socket = CanSocket::open(&args.interface)
+            .with_context(|| format!("cannot open {}", args.interface))?;
+        Output::Bus(socket)
+    };
+    catch_signals()?;
+
+    let mut bridge = Bridge {
+        port: args.port.clone(),
+        output,
+        stats_every: args.stats_every,
+        integrity: Integrity::new(),
+        header: None,
+        metadata: None,
+        snapshots: 0,
+        last_stats: None,
+        target_lost: 0,
+        written: 0,
+        dropped: 0,
+    };
+    let path = Path::new(&args.port);
+    let mut waiting = false;
+    while !STOPPED.load(Ordering::Relaxed) {
+        let port = match open_port(path, args.wire_version) {
+            Ok(port) => port,
+            Err(e) if args.once => return Err(e),
+            Err(e) => {
+                if !waiting {
+                    eprintln!("waiting for {}: {e:#}", args.port);
+                    waiting = true;
+                }
+                pause(REOPEN_DELAY);
+                continue;
+            }
+        };
+        waiting = false;
+        eprintln!("{}: bridging to {}", args.port, args.interface);
+
+        // A new session: the sequence numbers start over.
+        for event in CaptureReader::new(Interruptible(port)) {
+            match event {
+                Ok(event) => bridge.handle(event)?,
+                Err(e) => {
+                    eprintln!("warning: {}: {e}", args.port);
+                    break;
+                }
+            }
+        }
+        if args.once || STOPPED.load(Ordering::Relaxed) {
+            break;
+        }
+        eprintln!("{} closed, waiting for it to come back", args.port);
+        bridge.header = None;
+        bridge.metadata = None;
+        bridge.new_session();
+        pause(REOPEN_DELAY);
+    }
+    bridge.totals();
+    Ok(())
+}
+
+/// Opens the serial port in raw mode and asks the target for
+/// `wire_version`.
+///
+/// A port left in canonical mode mangles binary data and eventually blocks the
+/// target on write.
+fn open_port(path: &Path, wire_version: u8) -> Result<File> {
+    let metadata = fs::metadata(path)?;
+    if !metadata.file_type().is_char_device() {
+        bail!("not a serial port; n2k-replay plays capture files");
+    }
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    let raw = termios::tcgetattr(&file).and_then(|mut attrs| {
+        termios::cfmakeraw(&mut attrs);
+        termios::tcsetattr(&file, SetArg::TCSANOW, &attrs)
+    });
+    if raw.is_err() {
+        eprintln!("warning: could not set {} to raw mode", path.display());
+    }
+    if (&file)
+        .write_all(&encode_version_request(wire_version))
+        .is_err()
+    {
+        eprintln!(
+            "warning: could not ask {} for wire version {wire_version}",
+            path.display()
+        );
+    }
+    Ok(file)
+}
+
+/// `firmware 0.1.0 | chip 24:0a:c4:12:34:56 | listen-only`.
+fn describe_target(metadata: &Metadata) -> String {
+    let [major, minor, patch] = metadata.firmware;
+    let chip = metadata
+        .chip_id
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(":");
+    format!(
+        "firmware {major}.{minor}.{patch} | chip {chip} | {}",
+        metadata.mode_name()
+    )
+}
+
+/// Sleeps `duration`, or less when a signal stops the bridge.
+fn pause(duration: Duration) {
+    let until = Instant::now() + duration;
+    while !STOPPED.load(Ordering::Relaxed) && Instant::now() < until {
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_stop(_: nix::libc::c_int) {
+    STOPPED.store(true, Ordering::Relaxed);
+}
+
+/// SIGINT and SIGTERM stop the bridge. No `SA_RESTART`, so a blocked read
+/// returns `EINTR` instead of resuming.
+fn catch_signals() -> Result<()> {
+    let action = SigAction::new(
+        SigHandler::Handler(on_stop),
+        SaFlags::empty(),
+        SigSet::empty(),
+    );
+    // SAFETY: the handler only stores to an atomic.
+    unsafe {
+        signal::sigaction(Signal::SIGINT, &action)?;
+        signal::sigaction(Signal::SIGTERM, &action)?;
+    }
+    Ok(())
+}
+
+/// Turns an interrupted read into the end of the stream.
+struct Interruptible<R>(R);
+
+impl<R: Read> Read for Interruptible<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if STOPPED.load(Ordering::Relaxed) {
+            return Ok(0);
+        }
+        match self.0.read(buf) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                if STOPPED.load(Ordering::Relaxed) {
+                    Ok(0)
+                } else {
+                    Err(e)
+                }
+            }
+            other => other,
+        }
+    }
+}
@@ -0,0 +1,858 @@
+//! Decode the ESP32-S3 sniffer capture stream and check its integrity.
+//!
+//! ```text
+//! kn2kcap capture.bin              # recorded file
+//! kn2kcap capture.bin --csv        # CSV output
+//! kn2kcap capture.bin --quiet      # report only
+//! kn2kcap capture.bin --candump > capture.log   # for canplayer
+//! kn2kcap capture.bin --pcapng > capture.pcapng  # for Wireshark
+//! kn2kcap capture.bin --ebl > capture.ebl        # for Actisense NMEA Reader
+//! kn2kcap capture.bin --json       # decoded messages, fast packets reassembled
+//! kn2kcap log.ebl                  # Actisense EBL log, same outputs
+//! kn2kcap /dev/ttyACM0             # live, asks for the latest wire version
+//! kn2kcap /dev/ttyACM0 --wire-version 1   # fixed 24-byte records
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, IsTerminal, Read, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use korri_n2k::protocol::transport::can_id::CanId;
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::sys::termios::{self, SetArg};
+
+use shared_core::format::EblCounters;
+use socketcan_receiver::analyzer::analyzer_json;
+use socketcan_receiver::candump::write_candump;
+use socketcan_receiver::capture::integrity::IMPLAUSIBLE_GAP;
+use socketcan_receiver::capture::{
+    wire::{encode_version_request, BACKLOG_LIMIT, CHANNEL_DEPTH, FLAG_EXTENDED, VERSION},
+    CaptureReader, Event, Integrity, Metadata, StatsSnapshot, Verdict,
+};
+use socketcan_receiver::ebl::{EblReader, EblWriter};
+use socketcan_receiver::messages::MessageAssembler;
+use socketcan_receiver::pcapng::PcapngWriter;
+use socketcan_receiver::pgn::{pgn_name, BROADCAST};
+
+#[derive(Parser)]
+#[command(about = "Decode a KN2KCAP capture (file or serial port) and check its integrity")]
+struct Args {
+    /// Capture file or serial port. A `.ebl` file is read as an Actisense
+    /// EBL log.
+    source: PathBuf,
+    /// CSV output.
+    #[arg(long)]
+    csv: bool,
+    /// Report only, no frame listing.
+    #[arg(long)]
+    quiet: bool,
+    /// `candump -l` output on the given interface name, for canplayer.
+    /// Timestamps stay target uptime.
+    #[arg(
+        long,
+        value_name = "IFACE",
+        num_args = 0..=1,
+        default_missing_value = "can0",
+        conflicts_with = "csv"
+    )]
+    candump: Option<String>,
+    /// pcapng output (SocketCAN link type), for Wireshark. Target counters
+    /// become interface statistics.
+    #[arg(long, conflicts_with_all = ["csv", "candump"])]
+    pcapng: bool,
+    /// Actisense EBL output, for NMEA Reader. Timestamps stay target uptime,
+    /// so the log is dated from 1970.
+    #[arg(long, conflicts_with_all = ["csv", "candump", "pcapng"])]
+    ebl: bool,
+    /// canboat `analyzer -json` lines, decoded with korri-n2k. Timestamps
+    /// stay target uptime.
+    #[arg(long, conflicts_with_all = ["csv", "candump", "pcapng", "ebl"])]
+    json: bool,
+    /// KN2KCAP wire version asked of a live target. The target falls back
+    /// to the closest one it speaks; files are read whatever their version.
+    #[arg(long, value_name = "N", default_value_t = VERSION)]
+    wire_version: u8,
+}
+
+/// A KN2KCAP capture or an EBL log, as capture events. An EBL log only has
+/// frames: no header, counters nor sequence numbers.
+enum Input<R> {
+    Capture(CaptureReader<R>),
+    Ebl(EblReader<R>),
+}
+
+impl<R: Read> Input<R> {
+    fn has_synced(&self) -> bool {
+        match self {
+            Self::Capture(reader) => reader.has_synced(),
+            Self::Ebl(reader) => {
+                let counters = reader.counters();
+                counters.frames + counters.time_records > 0
+            }
+        }
+    }
+
+    fn bytes_read(&self) -> u64 {
+        match self {
+            Self::Capture(reader) => reader.bytes_read(),
+            Self::Ebl(reader) => reader.bytes_read(),
+        }
+    }
+
+    fn ebl_counters(&self) -> Option<EblCounters> {
+        match self {
+            Self::Capture(_) => None,
+            Self::Ebl(reader) => Some(reader.counters()),
+        }
+    }
+}
+
+impl<R: Read> Iterator for Input<R> {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Capture(reader) => reader.next(),
+            Self::Ebl(reader) => reader.next().map(|frame| frame.map(Event::Frame)),
+        }
+    }
+}
+
+/// What the listing saw, on top of the integrity totals.
+#[derive(Default)]
+struct Summary {
+    frames: u64,
+    non_n2k: u64,
+    /// Start of the current run of timestamps, which starts over after a
+    /// target restart or a step back of the clock.
+    first_us: Option<u64>,
+    last_us: u64,
+    /// Duration of the runs before the current one.
+    earlier_us: u64,
+    sources: BTreeMap<u8, u64>,
+    pgns: BTreeMap<u32, u64>,
+    /// Reassembles fast packets, for the counters and `--json`.
+    messages: MessageAssembler,
+}
+
+impl Summary {
+    /// Takes a frame's timestamp and returns the start of its run.
+    fn stamp(&mut self, timestamp_us: u64) -> u64 {
+        if self.first_us.is_some_and(|first| timestamp_us < first) {
+            self.restart();
+        }
+        let first = *self.first_us.get_or_insert(timestamp_us);
+        self.last_us = self.last_us.max(timestamp_us);
+        first
+    }
+
+    /// Closes the current run: the next timestamp starts a new one.
+    fn restart(&mut self) {
+        if let Some(first) = self.first_us.take() {
+            self.earlier_us += self.last_us - first;
+        }
+        self.last_us = 0;
+    }
+
+    fn duration_s(&self) -> f64 {
+        let current = self.first_us.map_or(0, |first| self.last_us - first);
+        (self.earlier_us + current) as f64 / 1e6
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let source = open_source(&args.source, args.wire_version)?;
+    catch_interrupt()?;
+
+    let source = Interruptible(source);
+    let mut reader = if args.source.extension().is_some_and(|ext| ext == "ebl") {
+        Input::Ebl(EblReader::new(source))
+    } else {
+        Input::Capture(CaptureReader::new(source))
+    };
+    let mut integrity = Integrity::new();
+    let mut summary = Summary::default();
+    let mut previous_us: Option<u64> = None;
+    let mut header_shown = false;
+    let listing = !args.quiet;
+
+    let stdout = io::stdout();
+    let colour_out = stdout.is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    let mut out = BufWriter::new(stdout.lock());
+
+    let mut pcapng = if args.pcapng && listing {
+        Some(PcapngWriter::new(BufWriter::new(io::stdout()), "kn2kcap")?)
+    } else {
+        None
+    };
+    let mut ebl = (args.ebl && listing).then(|| EblWriter::new(BufWriter::new(io::stdout())));
+
+    if args.csv && listing {
+        writeln!(
+            out,
+            "timestamp_us,delta_us,priority,pgn,source,destination,len,data"
+        )?;
+    }
+
+    for event in &mut reader {
+        let event = event.context("reading the capture")?;
+        integrity.observe(&event);
+        if let Some(pcapng) = &mut pcapng {
+            pcapng.write_event(&event)?;
+        }
+
+        if let Event::Restart = event {
+            summary.restart();
+            previous_us = None;
+        }
+        let Event::Frame(frame) = event else {
+            continue;
+        };
+        if let (Some(interface), true) = (&args.candump, listing) {
+            // Every frame, N2K or not: the log is for any CAN tool.
+            write_candump(&mut out, interface, &frame)?;
+        }
+        if let Some(ebl) = &mut ebl {
+            ebl.write_frame(&frame)?;
+        }
+        let Some(can) = frame.to_can_frame() else {
+            summary.non_n2k += 1;
+            continue;
+        };
+
+        let id = CanId(frame.id);
+        let pgn = id.pgn();
+        let source = id.source_address();
+        let destination = id.destination().unwrap_or(BROADCAST);
+
+        summary.frames += 1;
+        *summary.sources.entry(source).or_default() += 1;
+        *summary.pgns.entry(pgn).or_default() += 1;
+        let first_us = summary.stamp(frame.timestamp_us);
+        let message = summary.messages.push(&can, frame.timestamp_us);
+        if let (Some(message), true) = (message, args.json && listing) {
+            writeln!(out, "{}", analyzer_json(&message))?;
+        }
+
+        if !listing || args.candump.is_some() || pcapng.is_some() || ebl.is_some() || args.json {
+            continue;
+        }
+
+        let delta = previous_us.and_then(|p| frame.timestamp_us.checked_sub(p));
+        previous_us = Some(frame.timestamp_us);
+        let data = &can.data[..can.len];
+
+        if args.csv {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{}",
+                frame.timestamp_us,
+                delta.unwrap_or(0),
+                id.priority(),
+                pgn,
+                source,
+                destination,
+                data.len(),
+                hex(data, "")
+            )?;
+            continue;
+        }
+
+        if !header_shown {
+            writeln!(
+                out,
+                "{}",
+                paint(
+                    &format!(
+                        "{:>12} {:>9}  {:>6}  {:<26} {:>3} {:>4}  data",
+                        "t (s)", "d (us)", "PGN", "name", "src", "dst"
+                    ),
+                    DIM,
+                    colour_out
+                )
+            )?;
+            header_shown = true;
+        }
+
+        let elapsed = (frame.timestamp_us - first_us) as f64 / 1e6;
+        let delta = delta.map_or("—".to_string(), |d| format!("+{d}"));
+        let destination = if destination == BROADCAST {
+            "GLOB".to_string()
+        } else {
+            destination.to_string()
+        };
+        writeln!(
+            out,
+            "{elapsed:>12.6} {delta:>9}  {pgn:>6}  {} {source:>3} {destination:>4}  {}",
+            paint(&format!("{:<26}", pgn_name(pgn)), DIM, colour_out),
+            hex(data, " ")
+        )?;
+    }
+
+    // Without this flush the report (stderr, unbuffered) comes out before the
+    // listing as soon as stdout is redirected.
+    out.flush()?;
+    drop(out);
+    if let Some(mut pcapng) = pcapng {
+        pcapng.flush()?;
+    }
+    if let Some(mut ebl) = ebl {
+        ebl.flush()?;
+    }
+
+    let report = Report {
+        integrity: &integrity,
+        summary: &summary,
+        synced: reader.has_synced(),
+        bytes_read: reader.bytes_read(),
+        ebl: reader.ebl_counters(),
+        colour: io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+    };
+    eprintln!();
+    eprintln!("{}", report.render());
+    Ok(())
+}
+
+/// Opens a file or a serial port, forcing raw mode on a port and asking the
+/// target for `wire_version`.
+///
+/// A port left in canonical mode mangles binary data and eventually blocks the
+/// target on write.
+fn open_source(path: &Path, wire_version: u8) -> Result<File> {
+    let metadata = fs::metadata(path).with_context(|| format!("cannot open {}", path.display()))?;
+    if !metadata.file_type().is_char_device() {
+        return File::open(path).with_context(|| format!("cannot open {}", path.display()));
+    }
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("cannot open {}", path.display()))?;
+
+    let raw = termios::tcgetattr(&file).and_then(|mut attrs| {
+        termios::cfmakeraw(&mut attrs);
+        termios::tcsetattr(&file, SetArg::TCSANOW, &attrs)
+    });
+    if raw.is_err() {
+        eprintln!("warning: could not set {} to raw mode", path.display());
+    }
+    // Taken into account from the target's next batch; what is already
+    // queued comes out in the previous version, which the reader follows.
+    if (&file)
+        .write_all(&encode_version_request(wire_version))
+        .is_err()
+    {
+        eprintln!(
+            "warning: could not ask {} for wire version {wire_version}",
+            path.display()
+        );
+    }
+    Ok(file)
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: nix::libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Ctrl-C ends a live capture: the listing stops and the report still prints.
+/// No `SA_RESTART`, so a blocked read returns `EINTR` instead of resuming.
+fn catch_interrupt() -> Result<()> {
+    let action = SigAction::new(
+        SigHandler::Handler(on_interrupt),
+        SaFlags::empty(),
+        SigSet::empty(),
+    );
+    // SAFETY: the handler only stores to an atomic.
+    unsafe { signal::sigaction(Signal::SIGINT, &action) }?;
+    Ok(())
+}
+
+/// Turns an interrupted read into the end of the stream.
+struct Interruptible<R>(R);
+
+impl<R: Read> Read for Interruptible<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            return Ok(0);
+        }
+        match self.0.read(buf) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                if INTERRUPTED.load(Ordering::Relaxed) {
+                    Ok(0)
+                } else {
+                    Err(e)
+                }
+            }
+            other => other,
+        }
+    }
+}
+
+fn hex(data: &[u8], separator: &str) -> String {
+    data.iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+// ----------------------------------------------------------------------- report
+
+const BOLD_RED: &str = "1;31";
+const BOLD_GREEN: &str = "1;32";
+const DIM: &str = "2";
+const RED: &str = "31";
+const YELLOW: &str = "33";
+const CYAN: &str = "36";
+
+const LABEL_WIDTH: usize = 13;
+const PGN_ROWS: usize = 12;
+
+fn paint(text: &str, code: &str, enabled: bool) -> String {
+    if enabled {
+        format!("\x1b[{code}m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
+}
+
+struct Report<'a> {
+    integrity: &'a Integrity,
+    summary: &'a Summary,
+    synced: bool,
+    bytes_read: u64,
+    /// Set for an EBL log, which has no integrity data to check.
+    ebl: Option<EblCounters>,
+    colour: bool,
+}
+
+impl Report<'_> {
+    fn render(&self) -> String {
+        let lines = if self.synced {
+            let mut lines = self.capture();
+            lines.push(String::new());
+            match self.ebl {
+                Some(counters) => lines.extend(self.ebl_lines(&counters)),
+                None => lines.extend(self.integrity_lines()),
+            }
+            lines
+        } else {
+            self.unsynced()
+        };
+        lines.join("\n")
+    }
+
+    fn paint(&self, text: &str, code: &str) -> String {
+        paint(text, code, self.colour)
+    }
+
+    fn rule(&self, title: &str) -> String {
+        let fill = "─".repeat(60usize.saturating_sub(title.len()));
+        self.paint(&format!("── {title} {fill}"), CYAN)
+    }
+
+    fn field(label: &str, value: impl AsRef<str>) -> String {
+        format!("  {label:<LABEL_WIDTH$}{}", value.as_ref())
+    }
+
+    fn unsynced(&self) -> Vec<String> {
+        let mut lines = vec![self.rule("Capture"), String::new()];
+        let missing = if self.ebl.is_some() {
+            "EBL record"
+        } else {
+            "session header"
+        };
+        lines.push(format!(
+            "  {} bytes read, no {missing} found.",
+            self.bytes_read
+        ));
+        lines.push(String::new());
+        if self.bytes_read == 0 {
+            lines.push(format!(
+                "{} Is the target running, on this port?",
+                self.paint("  Empty stream.", RED)
+            ));
+        } else {
+            lines.push(format!(
+                "{} Things to check:",
+                self.paint("  Data is not in the expected format.", RED)
+            ));
+            lines.push("    - port read without raw mode -> use `just capture`".into());
+            lines.push("    - another program writing to this port".into());
+            lines.push("    - wrong port, or outdated firmware".into());
+        }
+        lines
+    }
+
+    fn capture(&self) -> Vec<String> {
+        let summary = self.summary;
+        let mut lines = vec![self.rule("Capture"), String::new()];
+
+        if summary.frames == 0 {
+            lines.push(Self::field("frames", "none"));
+            return lines;
+        }
+
+        let seconds = summary.duration_s();
+        let mut parts = vec![
+            format!("{seconds:.2} s"),
+            format!("{} frames", summary.frames),
+        ];
+        // Below one second, an extrapolated rate would be meaningless.
+        if seconds >= 1.0 {
+            parts.push(format!("{:.0} frames/s", summary.frames as f64 / seconds));
+        }
+        if let Some(bitrate) = self.integrity.bitrate {
+            parts.push(format!("bus {bitrate} bit/s"));
+        }
+        lines.push(Self::field("duration", parts.join(" | ")));
+
+        let sources = summary
+            .sources
+            .iter()
+            .map(|(src, count)| format!("{src} {}", self.paint(&format!("({count})"), DIM)))
+            .collect::<Vec<_>>()
+            .join("  ");
+        lines.push(Self::field("sources", sources));
+
+        let fast = summary.messages.fast_packets();
+        if fast.messages + fast.errors() > 0 {
+            let mut text = format!("{} messages reassembled", fast.messages);
+            let errors = [
+                (fast.expired, "expired"),
+                (fast.restarted, "restarted"),
+                (fast.bad_frames, "bad frames"),
+            ]
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, name)| format!("{name} {count}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+            if !errors.is_empty() {
+                text += &format!(
+                    " | {}",
+                    self.paint(&format!("incomplete: {errors}"), YELLOW)
+                );
+            }
+            let reordered = [
+                (fast.out_of_order, "out of order"),
+                (fast.duplicates, "duplicates"),
+            ]
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, name)| format!("{name} {count}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+            if !reordered.is_empty() {
+                text += &format!(" | {}", self.paint(&reordered, DIM));
+            }
+            lines.push(Self::field("fast packets", text));
+        }
+
+        let transport = summary.messages.transport();
+        if transport.messages + transport.timeouts + transport.aborts > 0 {
+            let mut text = format!("{} messages over ISO TP", transport.messages);
+            let errors = [
+                (transport.timeouts, "timeouts"),
+                (transport.aborts, "aborts"),
+                (transport.bad_sequence, "bad sequence"),
+            ]
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, name)| format!("{name} {count}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+            if !errors.is_empty() {
+                text += &format!(" | {}", self.paint(&errors, YELLOW));
+            }
+            lines.push(Self::field("transport", text));
+        }
+        lines.push(String::new());
+
+        lines.push(self.paint(
+            &format!(
+                "  {:>6}  {:<26}{:>8}  {:>6}",
+                "PGN", "name", "frames", "share"
+            ),
+            DIM,
+        ));
+        let mut pgns: Vec<_> = summary.pgns.iter().collect();
+        pgns.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (pgn, count) in pgns.iter().take(PGN_ROWS) {
+            let share = 100.0 * **count as f64 / summary.frames as f64;
+            lines.push(format!(
+                "  {pgn:>6}  {:<26}{count:>8}  {share:>5.1}%",
+                pgn_name(**pgn)
+            ));
+        }
+        if pgns.len() > PGN_ROWS {
+            lines.push(self.paint(
+                &format!("  ... and {} more PGNs", pgns.len() - PGN_ROWS),
+                DIM,
+            ));
+        }
+        lines
+    }
+
+    fn ebl_lines(&self, counters: &EblCounters) -> Vec<String> {
+        let mut lines = vec![self.rule("EBL log"), String::new()];
+        lines.push(Self::field(
+            "records",
+            format!(
+                "{} frames | {} time records",
+                counters.frames, counters.time_records
+            ),
+        ));
+        if counters.other_records > 0 {
+            lines.push(Self::field(
+                "skipped",
+                self.paint(
+                    &format!("{} records of other types", counters.other_records),
+                    DIM,
+                ),
+            ));
+        }
+        if counters.framing_errors + counters.noise_bytes > 0 {
+            lines.push(Self::field(
+                "errors",
+                self.paint(
+                    &format!(
+                        "{} bad records, {} bytes outside any record",
+                        counters.framing_errors, counters.noise_bytes
+                    ),
+                    YELLOW,
+                ),
+            ));
+        } else {
+            lines.push(Self::field("errors", "none"));
+        }
+        lines
+    }
+
+    fn integrity_lines(&self) -> Vec<String> {
+        let integrity = self.integrity;
+        let mut lines = vec![self.rule("Integrity"), String::new()];
+
+        if let Some(version) = integrity.version {
+            let mut text = format!("KN2KCAP v{version}");
+            if let Some(metadata) = &integrity.metadata {
+                text = format!("{} | {text}", describe_target(metadata));
+            }
+            lines.push(Self::field("device", text));
+        }
+
+        if integrity.restarts > 0 {
+            lines.push(Self::field(
+                "target",
+                self.paint(
+                    &format!(
+                        "restarted {}x during the capture - counters and sequence \
+                         numbers reset, so only the last session is measured",
+                        integrity.restarts
+                    ),
+                    YELLOW,
+                ),
+            ));
+        }
+
+        if integrity.resyncs > 0 {
+            let detail = if integrity.noise_text > 0 {
+                format!(
+                    "{} bytes of console or bootloader output",
+                    integrity.noise_bytes
+                )
+            } else {
+                "stream truncated or interrupted".to_string()
+            };
+            lines.push(Self::field(
+                "resyncs",
+                self.paint(&format!("{} - {detail}", integrity.resyncs), YELLOW),
+            ));
+        }
+        if integrity.bad_batches > 0 {
+            lines.push(Self::field(
+                "batches",
+                self.paint(
+                    &format!("{} failed their CRC, discarded", integrity.bad_batches),
+                    RED,
+                ),
+            ));
+        }
+        if integrity.bad_stats > 0 {
+            lines.push(Self::field(
+                "snapshots",
+                self.paint(
+                    &format!("{} corrupted, discarded", integrity.bad_stats),
+                    YELLOW,
+                ),
+            ));
+        }
+        if self.summary.non_n2k > 0 {
+            lines.push(Self::field(
+                "non-N2K",
+                self.paint(
+                    &format!(
+                        "{} standard or remote frames, not decoded",
+                        self.summary.non_n2k
+                    ),
+                    YELLOW,
+                ),
+            ));
+        }
+
+        if integrity.link_gaps > 0 {
+            lines.push(Self::field(
+                "USB link",
+                self.paint(
+                    &format!(
+                        "{} records lost in {} gaps",
+                        integrity.link_lost, integrity.link_gaps
+                    ),
+                    RED,
+                ),
+            ));
+            if integrity.worst_gap > IMPLAUSIBLE_GAP {
+                lines.push(Self::field(
+                    "",
+                    self.paint(
+                        &format!("gap of {}: too large for a real loss,", integrity.worst_gap),
+                        DIM,
+                    ),
+                ));
+                lines.push(Self::field(
+                    "",
+                    self.paint("the stream is likely corrupted rather than truncated", DIM),
+                ));
+            }
+        } else {
+            lines.push(Self::field("USB link", "intact, no sequence gap"));
+        }
+
+        let (Some(last), Some(window)) = (integrity.last_stats, integrity.window()) else {
+            lines.push(Self::field("counters", "no snapshot received"));
+            return lines;
+        };
+
+        lines.push(Self::field("window", self.describe(&window)));
+        lines.push(Self::field("since boot", self.describe(&last)));
+
+        let earlier = last.sink_drops - window.sink_drops;
+        if earlier > 0 {
+            lines.push(Self::field(
+                "",
+                self.paint(
+                    &format!(
+                        "including {earlier} USB losses before the capture - the \
+                         target was writing with no reader, which is expected"
+                    ),
+                    DIM,
+                ),
+            ));
+        }
+
+        lines.push(Self::field(
+            "headroom",
+            format!(
+                "channel {}/{CHANNEL_DEPTH} | burst {}/{BACKLOG_LIMIT}",
+                last.max_channel_depth, last.max_backlog_run
+            ),
+        ));
+
+        lines.push(String::new());
+        lines.push(Self::field(
+            "VERDICT",
+            match integrity.verdict() {
+                Verdict::Incomplete => self.paint("capture INCOMPLETE", BOLD_RED),
+                Verdict::TooShort => self.paint(
+                    "window too short - capture for more than two seconds",
+                    YELLOW,
+                ),
+                Verdict::Complete => self.paint("capture complete", BOLD_GREEN),
+            },
+        ));
+
+        if last.max_backlog_run >= 24 {
+            lines.push(String::new());
+            lines.push(self.paint(
+                &format!(
+                    "  ! bursts close to the {BACKLOG_LIMIT} limit: frames may have been\n    \
+                     lost without being counted."
+                ),
+                YELLOW,
+            ));
+        }
+
+        lines
+    }
+
+    fn describe(&self, counters: &StatsSnapshot) -> String {
+        let mut text = format!("{} received", counters.frames_rx);
+
+        let dropped = counters.lost();
+        if dropped > 0 {
+            let detail = [
+                (counters.channel_drops, "channel"),
+                (counters.hw_overruns, "overrun"),
+                (counters.sink_drops, "USB"),
+            ]
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, name)| format!("{name} {count}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+            text += &format!(
+                " | {}",
+                self.paint(&format!("{dropped} lost ({detail})"), RED)
+            );
+        } else {
+            text += " | no loss";
+        }
+
+        let extra = [
+            (counters.soft_errors, "errors"),
+            (counters.bus_off, "bus-off"),
+        ]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, name)| format!("{name} {count}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+        if !extra.is_empty() {
+            text += &format!(" | {}", self.paint(&extra, YELLOW));
+        }
+        text
+    }
+}
+
+/// `firmware 0.1.0 | chip 24:0a:c4:12:34:56 | listen-only | accept all`.
+fn describe_target(metadata: &Metadata) -> String {
+    let [major, minor, patch] = metadata.firmware;
+    let chip = metadata
+        .chip_id
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(":");
+    let filter = &metadata.filter;
+    let filter = if filter.accepts_all() {
+        "accept all".to_string()
+    } else {
+        let width = if filter.flags & FLAG_EXTENDED != 0 {
+            8
+        } else {
+            3
+        };
+        format!("filter {:0width$x}/{:0width$x}", filter.id, filter.mask)
+    };
+    format!(
+        "firmware {major}.{minor}.{patch} | chip {chip} | {} | {filter}",
+        metadata.mode_name()
+    )
+}
@@ -0,0 +1,370 @@
+//! TCP gateway for a SocketCAN bus, in place of a commercial WiFi gateway:
+//! clients receive the bus as NGT-1, Actisense ASCII or YDWG RAW, and what
+//! they send goes onto the bus.
+//!
+//! ```text
+//! n2k-gateway can0                                  # YDWG RAW on port 1457
+//! n2k-gateway can0 --listen ngt1:60001 --listen actisense:60002,pgn=129025+129026
+//! n2k-gateway can0 --listen ngt1:60001 --source 42  # NGT-1 0x94 sent as 42
+//! n2k-gateway can0 --read-only
+//! ```
+//!
+//! SignalK: an "Actisense NGT-1 (canboat)" TCP connection to the NGT-1 port.
+//! OpenCPN or a YDWG app: TCP to the YDWG port.
+//!
+//! Every client has its own queue: one that cannot keep up loses frames, the
+//! others do not wait, and one whose queue stays full is disconnected.
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use korri_n2k::protocol::transport::{can_frame::CanFrame, CAN_SEND_TIMEOUT_MS};
+use socketcan::{CanSocket, EmbeddedFrame, ExtendedId, Socket};
+use socketcan_receiver::candump::{from_socketcan, write_frame_with_deadline};
+use socketcan_receiver::gateway::{ClientReader, Filter, GatewayFormat, Inbound};
+use socketcan_receiver::messages::{Message, MessageAssembler};
+
+/// A client that cannot take a write within this delay is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+/// A client whose queue has been full for this long is dropped too: it is not
+/// reading at all.
+const STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Parser)]
+#[command(about = "Serve a SocketCAN bus to TCP clients as NGT-1, Actisense ASCII or YDWG RAW")]
+struct Args {
+    /// CAN interface (can0, vcan0...).
+    #[arg(default_value = "can0")]
+    interface: String,
+    /// `FORMAT:PORT[,FILTER]`, repeatable: format ngt1, actisense or ydwg,
+    /// filter as `pgn=129025+129026,src=35`. Defaults to ydwg:1457.
+    #[arg(long = "listen", value_name = "SPEC")]
+    listeners: Vec<String>,
+    /// Listening address.
+    #[arg(long, default_value = "0.0.0.0")]
+    bind: String,
+    /// Source address of NGT-1 0x94 messages, which leave it to the gateway.
+    /// Without it they are refused.
+    #[arg(long)]
+    source: Option<u8>,
+    /// Never write to the bus.
+    #[arg(long)]
+    read_only: bool,
+    /// Items queued per client before it loses some.
+    #[arg(long, default_value_t = 4096)]
+    queue: usize,
+}
+
+struct Listener {
+    format: GatewayFormat,
+    port: u16,
+    filter: Filter,
+}
+
+fn parse_listener(spec: &str) -> Result<Listener> {
+    let (head, filter) = spec.split_once(',').unwrap_or((spec, ""));
+    let Some((format, port)) = head.split_once(':') else {
+        bail!("expected FORMAT:PORT, got `{head}`");
+    };
+    Ok(Listener {
+        format: format.parse().map_err(anyhow::Error::msg)?,
+        port: port.parse().with_context(|| format!("bad port `{port}`"))?,
+        filter: Filter::parse(filter).map_err(anyhow::Error::msg)?,
+    })
+}
+
+struct Client {
+    peer: String,
+    format: GatewayFormat,
+    filter: Arc<Mutex<Filter>>,
+    queue: SyncSender<Arc<Vec<u8>>>,
+    dropped: Arc<AtomicU64>,
+    full_since: Option<Instant>,
+    /// To hang up on a stalled client, whose threads then end.
+    stream: TcpStream,
+    /// Set by the reading thread when the client hangs up. Its entry holds
+    /// the last sender of the queue: dropping it ends the writing thread.
+    closed: Arc<AtomicBool>,
+}
+
+impl Client {
+    fn is_open(&self) -> bool {
+        !self.closed.load(Ordering::Relaxed)
+    }
+}
+
+type Clients = Arc<Mutex<Vec<Client>>>;
+
+/// What a client thread needs to put frames on the bus.
+#[derive(Clone)]
+struct BusWriter {
+    socket: Option<Arc<Mutex<CanSocket>>>,
+    source: Option<u8>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let specs = if args.listeners.is_empty() {
+        vec!["ydwg:1457".to_string()]
+    } else {
+        args.listeners.clone()
+    };
+    let listeners = specs
+        .iter()
+        .map(|spec| parse_listener(spec).with_context(|| format!("bad listener `{spec}`")))
+        .collect::<Result<Vec<_>>>()?;
+
+    let socket = CanSocket::open(&args.interface)
+        .with_context(|| format!("cannot open {}", args.interface))?;
+    // A socket of its own for writing: the reading one then sees the frames
+    // clients send, as every other device on the bus does.
+    let writer = BusWriter {
+        socket: if args.read_only {
+            None
+        } else {
+            let socket = CanSocket::open(&args.interface)
+                .with_context(|| format!("cannot open {}", args.interface))?;
+            socket.set_write_timeout(Duration::from_millis(CAN_SEND_TIMEOUT_MS as u64))?;
+            Some(Arc::new(Mutex::new(socket)))
+        },
+        source: args.source,
+    };
+
+    let clients = Clients::default();
+    for listener in listeners {
+        let tcp = TcpListener::bind((args.bind.as_str(), listener.port))
+            .with_context(|| format!("cannot listen on {}:{}", args.bind, listener.port))?;
+        eprintln!(
+            "{} -> {} on {} (filter {})",
+            args.interface,
+            listener.format,
+            tcp.local_addr()?,
+            listener.filter
+        );
+        let clients = Arc::clone(&clients);
+        let writer = writer.clone();
+        let queue = args.queue;
+        thread::spawn(move || accept(tcp, listener, clients, writer, queue));
+    }
+
+    let mut assembler = MessageAssembler::new();
+    let start = Instant::now();
+    loop {
+        let frame = match socket.read_frame() {
+            Ok(frame) => frame,
+            Err(e) => {
+                eprintln!("CAN read error: {e}");
+                continue;
+            }
+        };
+        let Some(frame) = from_socketcan(&frame, 0).and_then(|f| f.to_can_frame()) else {
+            continue;
+        };
+        let now = Utc::now();
+        let message = assembler.push(&frame, start.elapsed().as_micros() as u64);
+        broadcast(&clients, &frame, message.as_ref(), now);
+    }
+}
+
+/// Hands the frame, or the message it completes, to every client that wants
+/// it, each format encoded once.
+fn broadcast(clients: &Clients, frame: &CanFrame, message: Option<&Message>, now: DateTime<Utc>) {
+    let mut encoded: Vec<(GatewayFormat, Option<Arc<Vec<u8>>>)> = Vec::new();
+    clients.lock().unwrap().retain_mut(|client| {
+        if !client.is_open() {
+            return false;
+        }
+        let (pgn, source) = match (client.format.carries_messages(), message) {
+            (false, _) => (frame.id.pgn(), frame.id.source_address()),
+            (true, Some(message)) => (message.pgn, message.source),
+            (true, None) => return true,
+        };
+        if !client.filter.lock().unwrap().accepts(pgn, source) {
+            return true;
+        }
+        let bytes = match encoded.iter().find(|(format, _)| *format == client.format) {
+            Some((_, bytes)) => bytes.clone(),
+            None => {
+                let bytes = match message {
+                    Some(message) if client.format.carries_messages() => {
+                        client.format.encode_message(message, now)
+                    }
+                    _ => client.format.encode_frame(frame, now),
+                }
+                .map(Arc::new);
+                encoded.push((client.format, bytes.clone()));
+                bytes
+            }
+        };
+        let Some(bytes) = bytes else {
+            return true;
+        };
+        match client.queue.try_send(bytes) {
+            Ok(()) => {
+                client.full_since = None;
+                true
+            }
+            Err(TrySendError::Full(_)) => {
+                if client.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                    eprintln!("client {} not keeping up, losing data", client.peer);
+                }
+                let since = *client.full_since.get_or_insert_with(Instant::now);
+                if since.elapsed() >= STALL_TIMEOUT {
+                    eprintln!("client {} stalled, disconnecting", client.peer);
+                    let _ = client.stream.shutdown(Shutdown::Both);
+                    return false;
+                }
+                true
+            }
+            // The writer thread is gone: the client left.
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    });
+}
+
+fn accept(tcp: TcpListener, listener: Listener, clients: Clients, writer: BusWriter, queue: usize) {
+    for stream in tcp.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("accept error: {e}");
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map_or("?".to_string(), |a| a.to_string());
+        if stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() {
+            continue;
+        }
+        let _ = stream.set_nodelay(true);
+        let (Ok(reading), Ok(hang_up)) = (stream.try_clone(), stream.try_clone()) else {
+            continue;
+        };
+        eprintln!("client {peer} connected ({})", listener.format);
+
+        let (sender, receiver) = sync_channel(queue);
+        let filter = Arc::new(Mutex::new(listener.filter.clone()));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let closed = Arc::new(AtomicBool::new(false));
+        let client = Client {
+            peer: peer.clone(),
+            format: listener.format,
+            filter: Arc::clone(&filter),
+            queue: sender.clone(),
+            dropped: Arc::clone(&dropped),
+            full_since: None,
+            stream: hang_up,
+            closed: Arc::clone(&closed),
+        };
+        {
+            // Entries of clients gone while the bus was quiet go too.
+            let mut clients = clients.lock().unwrap();
+            clients.retain(Client::is_open);
+            clients.push(client);
+        }
+
+        let writing = peer.clone();
+        thread::spawn(move || write_client(stream, receiver, &writing, &dropped));
+        let reader = ClientReader::new(listener.format, writer.source);
+        let writer = writer.clone();
+        let format = listener.format;
+        thread::spawn(move || {
+            read_client(reading, reader, format, &peer, &filter, &sender, &writer);
+            drop(sender);
+            closed.store(true, Ordering::Relaxed);
+        });
+    }
+}
+
+/// Drains the client's queue into its socket, until either side closes.
+fn write_client(
+    mut stream: TcpStream,
+    receiver: Receiver<Arc<Vec<u8>>>,
+    peer: &str,
+    dropped: &AtomicU64,
+) {
+    while let Ok(bytes) = receiver.recv() {
+        if stream.write_all(&bytes).is_err() {
+            break;
+        }
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+    let dropped = dropped.load(Ordering::Relaxed);
+    if dropped > 0 {
+        eprintln!("client {peer} disconnected, {dropped} items lost to a full queue");
+    } else {
+        eprintln!("client {peer} disconnected");
+    }
+}
+
+/// Reads what the client sends: frames for the bus, filter changes.
+fn read_client(
+    mut stream: TcpStream,
+    mut reader: ClientReader,
+    format: GatewayFormat,
+    peer: &str,
+    filter: &Mutex<Filter>,
+    echo: &SyncSender<Arc<Vec<u8>>>,
+    writer: &BusWriter,
+) {
+    let mut buffer = [0u8; 4096];
+    let mut inbound = Vec::new();
+    loop {
+        let len = match stream.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(len) => len,
+        };
+        reader.feed(&buffer[..len], &mut inbound);
+        for item in inbound.drain(..) {
+            match item {
+                Inbound::Filter(new) => {
+                    eprintln!("client {peer}: filter {new}");
+                    *filter.lock().unwrap() = new;
+                }
+                Inbound::Rejected(reason) => eprintln!("client {peer}: {reason}"),
+                Inbound::Send(frames) => {
+                    let Some(socket) = &writer.socket else {
+                        eprintln!("client {peer}: read-only gateway, frames ignored");
+                        continue;
+                    };
+                    if let Err(e) = send_frames(&socket.lock().unwrap(), &frames) {
+                        eprintln!("client {peer}: CAN write error: {e}");
+                        continue;
+                    }
+                    for frame in &frames {
+                        if let Some(line) = format.encode_echo(frame, Utc::now()) {
+                            let _ = echo.try_send(Arc::new(line));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    // Closes the socket for the writer thread too.
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+/// Writes the frames of one message back to back, the socket held so that
+/// no other client's frames get in between.
+fn send_frames(socket: &CanSocket, frames: &[CanFrame]) -> io::Result<()> {
+    for frame in frames {
+        let id = ExtendedId::new(frame.id.0)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "bad CAN id"))?;
+        let frame = socketcan::CanFrame::new(id, &frame.data[..frame.len])
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "bad frame"))?;
+        let deadline = Instant::now() + Duration::from_millis(CAN_SEND_TIMEOUT_MS as u64);
+        if !write_frame_with_deadline(socket, &frame, deadline)? {
+            return Err(io::Error::from_raw_os_error(nix::libc::ENOBUFS));
+        }
+    }
+    Ok(())
+}
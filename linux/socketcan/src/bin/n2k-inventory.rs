@@ -0,0 +1,149 @@
+//! Inventory of an NMEA 2000 network: claims an address, sends an ISO Request
+//! for 60928, 126996 and 126998 to every device, and prints what came back.
+//!
+//! ```text
+//! n2k-inventory can0
+//! n2k-inventory can0 --wait 5 --save boat.json    # the table, and a JSON copy
+//! n2k-inventory can0 --json > boat.json           # JSON rather than the table
+//! ```
+//!
+//! Devices still missing a reply after `--wait` are asked again directly,
+//! `--retries` times.
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use embassy_time::Duration;
+use korri_n2k::protocol::managment::iso_name::IsoName;
+use socketcan_receiver::inventory::{survey, Device, Inventory};
+use socketcan_receiver::node::{block_on, claim, IsoIdentity, SocketCanBus};
+use socketcan_receiver::pgn::parse_number;
+
+/// ISO NAME device class and function of a diagnostic tool.
+const SYSTEM_TOOLS_CLASS: u8 = 10;
+const DIAGNOSTIC_FUNCTION: u8 = 130;
+
+#[derive(Parser)]
+#[command(about = "List the devices of an NMEA 2000 bus: NAME, product and installation")]
+struct Args {
+    /// CAN interface (can0, vcan0...).
+    #[arg(default_value = "can0")]
+    interface: String,
+    /// Seconds to wait for replies after each round of requests.
+    #[arg(long, default_value_t = 2.0)]
+    wait: f64,
+    /// Rounds of direct requests to the devices still missing a reply.
+    #[arg(long, default_value_t = 1)]
+    retries: u32,
+    /// JSON rather than the table on stdout.
+    #[arg(long)]
+    json: bool,
+    /// Also write the JSON to this file.
+    #[arg(long, value_name = "FILE")]
+    save: Option<PathBuf>,
+    /// Preferred source address for the requests.
+    #[arg(long, default_value_t = 250)]
+    address: u8,
+    /// ISO NAME unique number, 21 bits.
+    #[arg(long, default_value = "0x1ABCE5", value_parser = parse_number)]
+    unique_number: u32,
+    /// ISO NAME manufacturer code.
+    #[arg(long, default_value_t = 229)]
+    manufacturer_code: u16,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    if !(args.wait > 0.0 && args.wait.is_finite()) {
+        bail!("--wait must be above 0");
+    }
+    let identity = IsoIdentity {
+        preferred_address: args.address,
+        unique_number: args.unique_number,
+        manufacturer_code: args.manufacturer_code,
+        device_function: DIAGNOSTIC_FUNCTION,
+        device_class: SYSTEM_TOOLS_CLASS,
+        device_instance: 0,
+        system_instance: 0,
+        industry_group: 4,
+    };
+
+    let bus = SocketCanBus::open(&args.interface)
+        .with_context(|| format!("cannot open {}", args.interface))?;
+    let mut manager = match block_on(claim(bus, &identity)) {
+        Ok(manager) => manager,
+        Err(e) => bail!("address claim failed: {e:?}"),
+    };
+    eprintln!(
+        "{}: requesting from address {}",
+        args.interface,
+        manager.current_address()
+    );
+    let wait = Duration::from_micros((args.wait * 1e6) as u64);
+    let inventory = match block_on(survey(&mut manager, wait, args.retries)) {
+        Ok(inventory) => inventory,
+        Err(e) => bail!("{}: {e:?}", args.interface),
+    };
+
+    let json = serde_json::to_string_pretty(&inventory.to_json())?;
+    if args.json {
+        println!("{json}");
+    } else {
+        print_table(&inventory);
+    }
+    if let Some(path) = &args.save {
+        fs::write(path, json + "\n").with_context(|| format!("cannot write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn print_table(inventory: &Inventory) {
+    println!(
+        "{:>4}  {:<18}  {:<20} {:<24} {:<30} {:>4}  {:<24} {:<16} {:<20}  installation",
+        "addr", "NAME", "manufacturer", "function", "class", "inst", "model", "software", "serial"
+    );
+    for device in inventory.devices.values() {
+        let dash = || "-".to_string();
+        let product = device.product.as_ref();
+        let installation = device.configuration.as_ref().map_or(dash(), |c| {
+            [&c.installation_description1, &c.installation_description2]
+                .into_iter()
+                .filter(|s| !s.is_empty())
+                .map(|s| format!("\"{s}\""))
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+        println!(
+            "{:>4}  {:<18}  {:<20} {:<24} {:<30} {:>4}  {:<24} {:<16} {:<20}  {installation}",
+            device.address,
+            device
+                .name
+                .map_or_else(dash, |name| format!("0x{name:016X}")),
+            device.manufacturer().unwrap_or_else(dash),
+            device.function().unwrap_or_else(dash),
+            device.class().unwrap_or_else(dash),
+            instance(device),
+            product.map_or_else(dash, |p| p.model_id.clone()),
+            product.map_or_else(dash, |p| p.software_version.clone()),
+            product.map_or_else(dash, |p| p.serial_code.clone()),
+        );
+    }
+    let silent = inventory
+        .devices
+        .values()
+        .filter(|device| !device.is_complete())
+        .count();
+    println!();
+    println!(
+        "{} device(s), {silent} with missing replies",
+        inventory.devices.len()
+    );
+}
+
+fn instance(device: &Device) -> String {
+    device.name.map_or("-".to_string(), |name| {
+        IsoName::from_raw(name).device_instance().to_string()
+    })
+}
@@ -0,0 +1,106 @@
+//! Run a korri-n2k node on a SocketCAN bus: address claim, then the
+//! `shared-core::pgns` tasks, as the `total` firmware does on a board.
+//!
+//! ```text
+//! sudo ip link add dev vcan0 type vcan && sudo ip link set up vcan0
+//! n2k-node vcan0                        # total's PGNs, address 148
+//! n2k-node vcan0 --pgn 126993 --pgn 128267 --address 150 --unique-number 0x1ABCE0
+//! candump vcan0                         # or socketcan-receiver vcan0
+//! ```
+
+use std::process;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use socketcan_receiver::node::{
+    block_on, claim, init_manager, pgn_task, Executor, IsoIdentity, SocketCanBus, TASK_PGNS,
+    TOTAL_PGNS,
+};
+use socketcan_receiver::pgn::parse_number;
+
+#[derive(Parser)]
+#[command(about = "Run a korri-n2k node (address claim and PGN tasks) on a SocketCAN bus")]
+struct Args {
+    /// CAN interface (can0, vcan0...).
+    #[arg(default_value = "vcan0")]
+    interface: String,
+    /// PGN whose shared-core task to run, repeatable. Defaults to those of
+    /// the `total` firmware.
+    #[arg(long = "pgn", value_name = "PGN")]
+    pgns: Vec<u32>,
+    /// Preferred source address.
+    #[arg(long, default_value_t = 148)]
+    address: u8,
+    /// ISO NAME unique number, 21 bits: give every node on a bus its own.
+    #[arg(long, default_value = "0x1ABCDF", value_parser = parse_number)]
+    unique_number: u32,
+    /// ISO NAME manufacturer code.
+    #[arg(long, default_value_t = 229)]
+    manufacturer_code: u16,
+    /// ISO NAME device function.
+    #[arg(long, default_value_t = 145)]
+    device_function: u8,
+    /// ISO NAME device class.
+    #[arg(long, default_value_t = 75)]
+    device_class: u8,
+    /// ISO NAME device instance.
+    #[arg(long, default_value_t = 1)]
+    device_instance: u8,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let pgns = if args.pgns.is_empty() {
+        TOTAL_PGNS.to_vec()
+    } else {
+        args.pgns.clone()
+    };
+    if let Some(pgn) = pgns.iter().find(|pgn| !TASK_PGNS.contains(pgn)) {
+        bail!("no shared-core task for PGN {pgn}; available: {TASK_PGNS:?}");
+    }
+
+    let identity = IsoIdentity {
+        preferred_address: args.address,
+        unique_number: args.unique_number,
+        manufacturer_code: args.manufacturer_code,
+        device_function: args.device_function,
+        device_class: args.device_class,
+        device_instance: args.device_instance,
+        system_instance: 0,
+        industry_group: 4,
+    };
+
+    let bus = SocketCanBus::open(&args.interface)
+        .with_context(|| format!("cannot open {}", args.interface))?;
+    let name = identity.iso_name().raw();
+    eprintln!(
+        "{}: claiming an address for NAME 0x{name:016X}",
+        args.interface
+    );
+    let manager = match block_on(claim(bus, &identity)) {
+        Ok(manager) => manager,
+        Err(e) => bail!("address claim failed: {e:?}"),
+    };
+    eprintln!(
+        "{}: address {} claimed, running PGNs {pgns:?}",
+        args.interface,
+        manager.current_address()
+    );
+
+    let (runner, handle) = init_manager(manager);
+    let mut executor = Executor::new();
+    executor.spawn(async move {
+        // As on the boards, a bus error stops the node.
+        if let Err(e) = runner.drive().await {
+            eprintln!("address supervisor stopped: {e:?}");
+            process::exit(1);
+        }
+    });
+    for pgn in pgns {
+        if let Some(task) = pgn_task(pgn, handle) {
+            executor.spawn(task);
+        }
+    }
+    executor.run();
+    Ok(())
+}
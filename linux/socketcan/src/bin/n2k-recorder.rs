@@ -0,0 +1,498 @@
+//! Record a SocketCAN bus for days on end: KN2KCAP or candump segments,
+//! rotated by size or age and gzipped once closed.
+//!
+//! ```text
+//! n2k-recorder can0 --dir /data/trial                  # 64 MiB or 1 h segments
+//! n2k-recorder can0 --dir /data/trial --format candump --max-size 16 --max-time 15
+//! n2k-recorder can0 --dir /data/trial --min-free 500 --delete-oldest
+//! ```
+//!
+//! Every segment opens with a header naming the interface, bitrate, host and
+//! start time, see `segment`, and its frames carry Unix timestamps: segments
+//! put end to end make one recording again. SIGTERM or Ctrl-C closes the open
+//! segment and waits for its compression; SIGHUP starts a new segment.
+//!
+//! When free space falls under `--min-free`, or a write fails for lack of it,
+//! the segment is closed and frames are counted as lost until space is back.
+//! With `--delete-oldest`, the oldest segments go first to make room.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use clap::Parser;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::sys::statvfs::statvfs;
+use socketcan::{CanInterface, CanSocket, Socket};
+use socketcan_receiver::candump::{from_socketcan, write_candump};
+use socketcan_receiver::capture::{CaptureWriter, TimestampedFrame};
+use socketcan_receiver::replay::CaptureFormat;
+use socketcan_receiver::segment::SegmentHeader;
+
+/// How often a blocked read gives the signals a look.
+const READ_TIMEOUT: Duration = Duration::from_millis(200);
+/// Most a frame waits in memory before reaching the file.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const SPACE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const MIB: u64 = 1024 * 1024;
+
+#[derive(Parser)]
+#[command(about = "Record a CAN bus into rotated, compressed KN2KCAP or candump segments")]
+struct Args {
+    /// CAN interface to record.
+    #[arg(default_value = "can0")]
+    interface: String,
+    /// Directory the segments go to.
+    #[arg(long, default_value = ".")]
+    dir: PathBuf,
+    /// `kn2kcap` or `candump`.
+    #[arg(long, default_value = "kn2kcap")]
+    format: CaptureFormat,
+    /// Segment size, in MiB before compression.
+    #[arg(long, value_name = "MIB", default_value_t = 64)]
+    max_size: u64,
+    /// Segment length, in minutes.
+    #[arg(long, value_name = "MINUTES", default_value_t = 60)]
+    max_time: u64,
+    /// Bus bitrate for the headers, bit/s, when the interface does not tell.
+    #[arg(long)]
+    bitrate: Option<u32>,
+    /// Leave closed segments uncompressed.
+    #[arg(long)]
+    no_compress: bool,
+    /// Free space to keep on the disk, in MiB.
+    #[arg(long, value_name = "MIB", default_value_t = 100)]
+    min_free: u64,
+    /// Delete the oldest segments rather than stop recording when space runs
+    /// short.
+    #[arg(long)]
+    delete_oldest: bool,
+}
+
+static STOPPED: AtomicBool = AtomicBool::new(false);
+static ROTATE: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_stop(_: nix::libc::c_int) {
+    STOPPED.store(true, Ordering::Relaxed);
+}
+
+extern "C" fn on_rotate(_: nix::libc::c_int) {
+    ROTATE.store(true, Ordering::Relaxed);
+}
+
+enum Sink {
+    Kn2kcap(CaptureWriter<BufWriter<File>>),
+    Candump(BufWriter<File>),
+}
+
+impl Sink {
+    fn write(&mut self, frame: &TimestampedFrame, interface: &str) -> io::Result<()> {
+        match self {
+            Self::Kn2kcap(writer) => writer.write_frame(frame),
+            Self::Candump(out) => write_candump(out, interface, frame),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Kn2kcap(writer) => writer.flush(),
+            Self::Candump(out) => out.flush(),
+        }
+    }
+
+    fn file(&self) -> &File {
+        match self {
+            Self::Kn2kcap(writer) => writer.get_ref().get_ref(),
+            Self::Candump(out) => out.get_ref(),
+        }
+    }
+}
+
+struct Segment {
+    path: PathBuf,
+    sink: Sink,
+    opened: Instant,
+    frames: u64,
+}
+
+struct Recorder {
+    dir: PathBuf,
+    interface: String,
+    format: CaptureFormat,
+    bitrate: Option<u32>,
+    host: String,
+    max_size: u64,
+    max_time: Duration,
+    compress: bool,
+    min_free: u64,
+    delete_oldest: bool,
+
+    index: u32,
+    segment: Option<Segment>,
+    /// Out of space: frames are lost until some is freed.
+    suspended: bool,
+    /// Closed segments being compressed.
+    compressing: Vec<(PathBuf, JoinHandle<()>)>,
+    last_flush: Instant,
+    last_space_check: Instant,
+
+    recorded: u64,
+    lost: u64,
+}
+
+impl Recorder {
+    fn record(&mut self, frame: &TimestampedFrame) -> Result<()> {
+        if self.suspended {
+            self.lost += 1;
+            return Ok(());
+        }
+        if self.segment.is_none() {
+            if let Err(e) = self.open_segment() {
+                return self.write_failed(e.context("cannot open a segment"));
+            }
+        }
+        let Some(segment) = &mut self.segment else {
+            return Ok(());
+        };
+        match segment.sink.write(frame, &self.interface) {
+            Ok(()) => {
+                segment.frames += 1;
+                self.recorded += 1;
+                Ok(())
+            }
+            Err(e) => {
+                self.lost += 1;
+                self.write_failed(anyhow::Error::new(e).context("write failed"))
+            }
+        }
+    }
+
+    /// Flushes, rotates and checks the free space when due.
+    fn tick(&mut self) -> Result<()> {
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.last_flush = Instant::now();
+            if let Some(segment) = &mut self.segment {
+                if let Err(e) = segment.sink.flush() {
+                    return self.write_failed(anyhow::Error::new(e).context("flush failed"));
+                }
+                let size = segment.sink.file().metadata().map_or(0, |m| m.len());
+                let rotate = ROTATE.swap(false, Ordering::Relaxed);
+                if rotate || size >= self.max_size || segment.opened.elapsed() >= self.max_time {
+                    self.close_segment();
+                }
+            }
+        }
+        if self.last_space_check.elapsed() >= SPACE_CHECK_INTERVAL {
+            self.last_space_check = Instant::now();
+            self.check_space();
+        }
+        self.compressing.retain(|(_, handle)| !handle.is_finished());
+        Ok(())
+    }
+
+    fn open_segment(&mut self) -> Result<()> {
+        let header = SegmentHeader {
+            index: self.index + 1,
+            interface: self.interface.clone(),
+            bitrate: self.bitrate,
+            start: Utc::now(),
+            host: self.host.clone(),
+            format: self.format,
+        };
+        let path = self.dir.join(header.file_name());
+        let mut out = BufWriter::new(File::create_new(&path)?);
+        header.write(&mut out)?;
+        self.index = header.index;
+        let sink = match self.format {
+            CaptureFormat::Candump => Sink::Candump(out),
+            _ => Sink::Kn2kcap(CaptureWriter::new(out, self.bitrate.unwrap_or(0))),
+        };
+        eprintln!("recording {}", path.display());
+        self.segment = Some(Segment {
+            path,
+            sink,
+            opened: Instant::now(),
+            frames: 0,
+        });
+        Ok(())
+    }
+
+    /// Flushes the segment to disk and hands it over for compression. An
+    /// empty one is removed.
+    fn close_segment(&mut self) {
+        let Some(mut segment) = self.segment.take() else {
+            return;
+        };
+        if let Err(e) = segment
+            .sink
+            .flush()
+            .and_then(|()| segment.sink.file().sync_all())
+        {
+            eprintln!("warning: {}: {e}", segment.path.display());
+        }
+        let size = segment.sink.file().metadata().map_or(0, |m| m.len());
+        drop(segment.sink);
+        if segment.frames == 0 {
+            let _ = fs::remove_file(&segment.path);
+            return;
+        }
+        eprintln!(
+            "closed {}: {} frames, {:.1} MiB",
+            segment.path.display(),
+            segment.frames,
+            size as f64 / MIB as f64
+        );
+        if self.compress {
+            let path = segment.path.clone();
+            let handle = thread::spawn(move || {
+                if let Err(e) = compress(&path) {
+                    eprintln!("warning: {} left uncompressed: {e}", path.display());
+                }
+            });
+            self.compressing.push((segment.path, handle));
+        }
+    }
+
+    /// A write or open failed. Lack of space closes the segment and waits for
+    /// room; anything else is fatal.
+    fn write_failed(&mut self, e: anyhow::Error) -> Result<()> {
+        let no_space = e
+            .downcast_ref::<io::Error>()
+            .or_else(|| e.root_cause().downcast_ref::<io::Error>())
+            .is_some_and(is_no_space);
+        self.close_segment();
+        if !no_space {
+            return Err(e);
+        }
+        eprintln!("warning: {e:#}");
+        self.make_room();
+        Ok(())
+    }
+
+    fn check_space(&mut self) {
+        match (free_space(&self.dir), self.suspended) {
+            (Some(free), false) if free < self.min_free => {
+                eprintln!("warning: {} MiB left on the disk", free / MIB);
+                self.close_segment();
+                self.make_room();
+            }
+            (Some(free), true) if free >= self.min_free => {
+                eprintln!(
+                    "space is back, recording resumes ({} frames lost)",
+                    self.lost
+                );
+                self.suspended = false;
+            }
+            _ => {}
+        }
+    }
+
+    /// Deletes the oldest segments until `min_free` is free again, if allowed
+    /// to. Recording stops until then otherwise.
+    fn make_room(&mut self) {
+        if self.delete_oldest {
+            let busy: Vec<&Path> = self.compressing.iter().map(|(p, _)| p.as_path()).collect();
+            for path in segments(&self.dir, &self.interface) {
+                if free_space(&self.dir).is_some_and(|free| free >= self.min_free) {
+                    return;
+                }
+                if busy.contains(&path.as_path()) {
+                    continue;
+                }
+                match fs::remove_file(&path) {
+                    Ok(()) => eprintln!("deleted {} to make room", path.display()),
+                    Err(e) => eprintln!("warning: cannot delete {}: {e}", path.display()),
+                }
+            }
+            if free_space(&self.dir).is_some_and(|free| free >= self.min_free) {
+                return;
+            }
+        }
+        if !self.suspended {
+            eprintln!(
+                "warning: out of space in {}, recording suspended",
+                self.dir.display()
+            );
+            self.suspended = true;
+        }
+    }
+
+    /// Closes the open segment and waits for every compression.
+    fn finish(&mut self) {
+        self.close_segment();
+        for (_, handle) in self.compressing.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    if !matches!(args.format, CaptureFormat::Kn2kcap | CaptureFormat::Candump) {
+        bail!("segments are kn2kcap or candump, not {}", args.format);
+    }
+    if args.max_size == 0 || args.max_time == 0 {
+        bail!("--max-size and --max-time must be above 0");
+    }
+    fs::create_dir_all(&args.dir)
+        .with_context(|| format!("cannot create {}", args.dir.display()))?;
+
+    let socket = CanSocket::open(&args.interface)
+        .with_context(|| format!("cannot open {}", args.interface))?;
+    socket.set_read_timeout(READ_TIMEOUT)?;
+    let bitrate = args.bitrate.or_else(|| {
+        CanInterface::open(&args.interface)
+            .ok()?
+            .bit_rate()
+            .ok()
+            .flatten()
+    });
+    let host = nix::unistd::gethostname()
+        .ok()
+        .and_then(|name| name.into_string().ok())
+        .unwrap_or_else(|| "unknown".to_string());
+    catch_signals()?;
+
+    let mut recorder = Recorder {
+        dir: args.dir.clone(),
+        interface: args.interface.clone(),
+        format: args.format,
+        bitrate,
+        host,
+        max_size: args.max_size * MIB,
+        max_time: Duration::from_secs(args.max_time * 60),
+        compress: !args.no_compress,
+        min_free: args.min_free * MIB,
+        delete_oldest: args.delete_oldest,
+        index: 0,
+        segment: None,
+        suspended: false,
+        compressing: Vec::new(),
+        last_flush: Instant::now(),
+        last_space_check: Instant::now(),
+        recorded: 0,
+        lost: 0,
+    };
+    recorder.check_space();
+
+    let result = record(&socket, &mut recorder);
+    recorder.finish();
+    eprintln!(
+        "recorded {} frames in {} segment(s), {} lost for lack of space",
+        recorder.recorded, recorder.index, recorder.lost
+    );
+    result
+}
+
+fn record(socket: &CanSocket, recorder: &mut Recorder) -> Result<()> {
+    while !STOPPED.load(Ordering::Relaxed) {
+        match socket.read_frame() {
+            Ok(frame) => {
+                let now_us = SystemTime::now().duration_since(UNIX_EPOCH)?.as_micros() as u64;
+                if let Some(frame) = from_socketcan(&frame, now_us) {
+                    recorder.record(&frame)?;
+                }
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                        | io::ErrorKind::Interrupted
+                ) => {}
+            Err(e) => return Err(e).context("CAN read error"),
+        }
+        recorder.tick()?;
+    }
+    Ok(())
+}
+
+/// Gzips `path` beside itself, then removes it. On failure the partial
+/// archive goes and the segment stays as it was.
+fn compress(path: &Path) -> io::Result<()> {
+    let mut target = path.as_os_str().to_owned();
+    target.push(".gz");
+    let target = PathBuf::from(target);
+    let partial = target.with_extension("gz.part");
+    let result = (|| {
+        let mut input = File::open(path)?;
+        let output = BufWriter::new(File::create(&partial)?);
+        let mut encoder = GzEncoder::new(output, Compression::default());
+        io::copy(&mut input, &mut encoder)?;
+        let file = encoder.finish()?.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        fs::rename(&partial, &target)?;
+        fs::remove_file(path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Segments of `interface` in `dir`, oldest first: names start with the
+/// interface and the start time.
+fn segments(dir: &Path, interface: &str) -> Vec<PathBuf> {
+    let prefix = format!("{interface}-");
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| {
+                    name.starts_with(&prefix)
+                        && [".kn2kcap", ".log", ".kn2kcap.gz", ".log.gz"]
+                            .iter()
+                            .any(|ext| name.ends_with(ext))
+                })
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Bytes an unprivileged process can still write in `dir`.
+fn free_space(dir: &Path) -> Option<u64> {
+    let stats = statvfs(dir).ok()?;
+    Some(stats.blocks_available() as u64 * stats.fragment_size() as u64)
+}
+
+fn is_no_space(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::StorageFull
+        || matches!(
+            e.raw_os_error(),
+            Some(nix::libc::ENOSPC) | Some(nix::libc::EDQUOT)
+        )
+}
+
+/// SIGINT and SIGTERM stop the recording, SIGHUP starts a new segment.
+fn catch_signals() -> Result<()> {
+    let stop = SigAction::new(
+        SigHandler::Handler(on_stop),
+        SaFlags::empty(),
+        SigSet::empty(),
+    );
+    let rotate = SigAction::new(
+        SigHandler::Handler(on_rotate),
+        SaFlags::empty(),
+        SigSet::empty(),
+    );
+    // SAFETY: the handlers only store to atomics.
+    unsafe {
+        signal::sigaction(Signal::SIGINT, &stop)?;
+        signal::sigaction(Signal::SIGTERM, &stop)?;
+        signal::sigaction(Signal::SIGHUP, &rotate)?;
+    }
+    Ok(())
+}
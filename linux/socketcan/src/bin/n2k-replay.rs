@@ -0,0 +1,394 @@
+//! Play a capture back onto a CAN interface at the pace it was recorded:
+//! KN2KCAP, EBL, candump or Actisense ASCII, frame by frame, fast packets
+//! included.
+//!
+//! ```text
+//! n2k-replay trial.kn2kcap vcan0                      # as recorded
+//! n2k-replay trial.log can0 --speed 4 --loop
+//! n2k-replay trial.txt vcan0 --from 120 --to 180 --pgn 129025 --src 35
+//! n2k-replay trial.kn2kcap -                          # candump lines on stdout
+//! ```
+//!
+//! Space pauses and resumes when stdin is a terminal, `q` stops; SIGUSR1
+//! pauses and resumes too. Ctrl-C or SIGTERM stops, and what was sent is
+//! reported either way.
+
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Read, Stdout, Write};
+use std::os::fd::AsFd;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use korri_n2k::protocol::transport::CAN_SEND_TIMEOUT_MS;
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::sys::termios::{self, LocalFlags, SetArg, Termios};
+use socketcan::{CanSocket, Socket};
+use socketcan_receiver::candump::{to_socketcan, write_candump, write_frame_with_deadline};
+use socketcan_receiver::capture::TimestampedFrame;
+use socketcan_receiver::gateway::Filter;
+use socketcan_receiver::replay::{open_capture, CaptureFormat, Frames, Selection, Timeline};
+
+/// Interface named in the candump lines written to stdout.
+const STDOUT_INTERFACE: &str = "can0";
+/// Longest sleep between two looks at the controls.
+const POLL: Duration = Duration::from_millis(20);
+
+#[derive(Parser)]
+#[command(about = "Replay a capture onto a CAN interface with its original timing")]
+struct Args {
+    /// KN2KCAP, EBL, candump or Actisense ASCII capture.
+    capture: PathBuf,
+    /// CAN interface to send on, or `-` for candump lines on stdout.
+    #[arg(default_value = "vcan0")]
+    interface: String,
+    /// Capture format, guessed from the file otherwise.
+    #[arg(long)]
+    format: Option<CaptureFormat>,
+    /// Pace multiplier: 2 plays twice as fast, 0 as fast as the bus takes.
+    #[arg(long, default_value_t = 1.0)]
+    speed: f64,
+    /// Play the capture N times, forever without N.
+    #[arg(
+        long = "loop",
+        value_name = "N",
+        num_args = 0..=1,
+        default_missing_value = "0"
+    )]
+    loops: Option<u32>,
+    /// Start of the window, in seconds from the first frame.
+    #[arg(long, value_name = "SECONDS")]
+    from: Option<f64>,
+    /// End of the window, in seconds from the first frame.
+    #[arg(long, value_name = "SECONDS")]
+    to: Option<f64>,
+    /// PGN to send, repeatable. All of them by default.
+    #[arg(long = "pgn", value_name = "PGN")]
+    pgns: Vec<u32>,
+    /// Source address to send, repeatable. All of them by default.
+    #[arg(long = "src", value_name = "ADDRESS")]
+    sources: Vec<u8>,
+    /// Longest pause kept between two frames, in seconds: the logger off, a
+    /// target restart.
+    #[arg(long, value_name = "SECONDS", default_value_t = 5.0)]
+    max_gap: f64,
+}
+
+enum Output {
+    Bus(CanSocket),
+    Stdout(BufWriter<Stdout>),
+}
+
+#[derive(Default)]
+struct Totals {
+    sent: u64,
+    /// Out of the window, PGN or source.
+    skipped: u64,
+    unreadable: u64,
+    /// Left behind by a full interface queue.
+    dropped: u64,
+    passes: u32,
+    /// Furthest behind schedule a frame went out.
+    late: Duration,
+}
+
+/// Wall-clock time of the replay: when its first frame went out, shifted by
+/// every pause.
+struct Pace {
+    speed: f64,
+    start: Option<(Instant, u64)>,
+}
+
+impl Pace {
+    /// When the frame at `position_us` is due.
+    fn due(&mut self, position_us: u64) -> Instant {
+        let (start, origin) = *self.start.get_or_insert((Instant::now(), position_us));
+        if self.speed == 0.0 {
+            return start;
+        }
+        start + Duration::from_secs_f64((position_us - origin) as f64 / 1e6 / self.speed)
+    }
+
+    fn shift(&mut self, by: Duration) {
+        if let Some((start, _)) = &mut self.start {
+            *start += by;
+        }
+    }
+}
+
+static STOPPED: AtomicBool = AtomicBool::new(false);
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_stop(_: nix::libc::c_int) {
+    STOPPED.store(true, Ordering::Relaxed);
+}
+
+extern "C" fn on_pause(_: nix::libc::c_int) {
+    PAUSED.fetch_xor(true, Ordering::Relaxed);
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    if !(args.speed >= 0.0 && args.speed.is_finite()) {
+        bail!("--speed must be 0 or more");
+    }
+    let seconds = |s: f64| (s.max(0.0) * 1e6) as u64;
+    let selection = Selection {
+        from_us: args.from.map_or(0, seconds),
+        to_us: args.to.map(seconds),
+        filter: Filter {
+            pgns: args.pgns.clone(),
+            sources: args.sources.clone(),
+        },
+    };
+    if selection.to_us.is_some_and(|to| to < selection.from_us) {
+        bail!("--to comes before --from");
+    }
+
+    let (format, frames) = open_capture(&args.capture, args.format)
+        .with_context(|| format!("cannot open {}", args.capture.display()))?;
+    let mut output = if args.interface == "-" {
+        Output::Stdout(BufWriter::new(io::stdout()))
+    } else {
+        Output::Bus(
+            CanSocket::open(&args.interface)
+                .with_context(|| format!("cannot open {}", args.interface))?,
+        )
+    };
+
+    catch_signals()?;
+    let terminal = Keyboard::start();
+    eprintln!(
+        "replaying {} ({format}) onto {} at x{}, filter {}",
+        args.capture.display(),
+        args.interface,
+        args.speed,
+        selection.filter
+    );
+
+    let mut totals = Totals::default();
+    let mut pace = Pace {
+        speed: args.speed,
+        start: None,
+    };
+    let started = Instant::now();
+    let mut frames = Some(frames);
+    let result = loop {
+        let pass = match frames.take() {
+            Some(frames) => frames,
+            None => match open_capture(&args.capture, Some(format)) {
+                Ok((_, frames)) => frames,
+                Err(e) => break Err(e.into()),
+            },
+        };
+        let sent = totals.sent;
+        pace.start = None;
+        if let Err(e) = play(
+            pass,
+            &selection,
+            seconds(args.max_gap),
+            &mut pace,
+            &mut output,
+            &mut totals,
+        ) {
+            break Err(e);
+        }
+        totals.passes += 1;
+        if STOPPED.load(Ordering::Relaxed) {
+            break Ok(());
+        }
+        if totals.sent == sent {
+            break if totals.passes == 1 {
+                Err(anyhow::anyhow!("no frame to replay"))
+            } else {
+                Ok(())
+            };
+        }
+        match args.loops {
+            Some(0) => {}
+            Some(n) if totals.passes < n => {}
+            _ => break Ok(()),
+        }
+    };
+    drop(terminal);
+
+    eprintln!(
+        "sent {} frames in {} pass(es) over {:.1} s; skipped {}, unreadable {}, dropped {}; \
+         at most {:.1} ms late",
+        totals.sent,
+        totals.passes,
+        started.elapsed().as_secs_f64(),
+        totals.skipped,
+        totals.unreadable,
+        totals.dropped,
+        totals.late.as_secs_f64() * 1e3
+    );
+    result
+}
+
+/// One pass over the capture. Returns early once stopped.
+fn play(
+    frames: Frames,
+    selection: &Selection,
+    max_gap_us: u64,
+    pace: &mut Pace,
+    output: &mut Output,
+    totals: &mut Totals,
+) -> Result<()> {
+    let mut timeline = Timeline::new(max_gap_us);
+    for frame in frames {
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                eprintln!("warning: {e}");
+                totals.unreadable += 1;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let position_us = timeline.advance(frame.timestamp_us);
+        if selection.is_past(position_us) {
+            break;
+        }
+        if !selection.in_window(position_us) || !selection.accepts(&frame) {
+            totals.skipped += 1;
+            continue;
+        }
+        if !wait(pace, position_us) {
+            return Ok(());
+        }
+        let due = pace.due(position_us);
+        totals.late = totals
+            .late
+            .max(Instant::now().saturating_duration_since(due));
+        if send(output, &frame)? {
+            totals.sent += 1;
+        } else {
+            totals.dropped += 1;
+        }
+    }
+    if let Output::Stdout(out) = output {
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// Sleeps until the frame at `position_us` is due, holding while paused.
+/// `false` once stopped.
+fn wait(pace: &mut Pace, position_us: u64) -> bool {
+    loop {
+        if STOPPED.load(Ordering::Relaxed) {
+            return false;
+        }
+        if PAUSED.load(Ordering::Relaxed) {
+            let paused = Instant::now();
+            eprintln!("paused");
+            while PAUSED.load(Ordering::Relaxed) && !STOPPED.load(Ordering::Relaxed) {
+                thread::sleep(POLL);
+            }
+            pace.shift(paused.elapsed());
+            eprintln!("resumed");
+            continue;
+        }
+        let now = Instant::now();
+        let due = pace.due(position_us);
+        if now >= due {
+            return true;
+        }
+        thread::sleep((due - now).min(POLL));
+    }
+}
+
+/// `false` when the interface queue stayed full past `CAN_SEND_TIMEOUT_MS`.
+fn send(output: &mut Output, frame: &TimestampedFrame) -> Result<bool> {
+    match output {
+        Output::Stdout(out) => {
+            let now_us = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_micros() as u64);
+            let frame = TimestampedFrame {
+                timestamp_us: now_us,
+                ..*frame
+            };
+            write_candump(out, STDOUT_INTERFACE, &frame)?;
+            Ok(true)
+        }
+        Output::Bus(socket) => {
+            let Some(frame) = to_socketcan(frame) else {
+                return Ok(false);
+            };
+            let deadline = Instant::now() + Duration::from_millis(CAN_SEND_TIMEOUT_MS as u64);
+            write_frame_with_deadline(socket, &frame, deadline).context("CAN write error")
+        }
+    }
+}
+
+/// SIGINT and SIGTERM stop the replay, SIGUSR1 pauses and resumes it.
+fn catch_signals() -> Result<()> {
+    let stop = SigAction::new(
+        SigHandler::Handler(on_stop),
+        SaFlags::SA_RESTART,
+        SigSet::empty(),
+    );
+    let pause = SigAction::new(
+        SigHandler::Handler(on_pause),
+        SaFlags::SA_RESTART,
+        SigSet::empty(),
+    );
+    // SAFETY: the handlers only store to atomics.
+    unsafe {
+        signal::sigaction(Signal::SIGINT, &stop)?;
+        signal::sigaction(Signal::SIGTERM, &stop)?;
+        signal::sigaction(Signal::SIGUSR1, &pause)?;
+    }
+    Ok(())
+}
+
+/// Keys read one by one from a terminal on stdin, without echo. The terminal
+/// gets its settings back when dropped.
+struct Keyboard {
+    saved: Termios,
+}
+
+impl Keyboard {
+    /// `None` when stdin is not a terminal.
+    fn start() -> Option<Self> {
+        let stdin = io::stdin();
+        if !stdin.is_terminal() {
+            return None;
+        }
+        let saved = termios::tcgetattr(stdin.as_fd()).ok()?;
+        let mut keys = saved.clone();
+        keys.local_flags
+            .remove(LocalFlags::ICANON | LocalFlags::ECHO);
+        termios::tcsetattr(stdin.as_fd(), SetArg::TCSANOW, &keys).ok()?;
+        eprintln!("space: pause/resume, q: stop");
+
+        thread::spawn(|| {
+            // A dup, so that the thread never holds the stdin lock.
+            let Ok(mut input) = io::stdin().as_fd().try_clone_to_owned().map(File::from) else {
+                return;
+            };
+            let mut key = [0u8; 1];
+            while let Ok(1) = input.read(&mut key) {
+                match key[0] {
+                    b' ' => {
+                        PAUSED.fetch_xor(true, Ordering::Relaxed);
+                    }
+                    b'q' => STOPPED.store(true, Ordering::Relaxed),
+                    _ => {}
+                }
+            }
+        });
+        Some(Self { saved })
+    }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        let _ = termios::tcsetattr(io::stdin().as_fd(), SetArg::TCSANOW, &self.saved);
+    }
+}
@@ -0,0 +1,294 @@
+//! Run several korri-n2k nodes in one process on a shared bus, then print who
+//! ended up with which address and how: the `dual_run` scenarios without the
+//! boards.
+//!
+//! ```text
+//! n2k-sim                                   # inst1..inst5, in memory, 5 s
+//! n2k-sim --node inst1 --node inst3,start=400 --duration 3
+//! n2k-sim --node inst1 --node address=148,unique=0x1ABCD0,pgn=129025 vcan0
+//! ```
+//!
+//! A node is an instance of the boards (`inst1`..`inst5`), optionally followed
+//! by `key=value` overrides: `address`, `unique`, `manufacturer`, `function`,
+//! `class`, `instance`, `system`, `industry`, `pgn` (`+` between PGNs),
+//! `start` (delay before claiming, in ms) and `label`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::process;
+use std::rc::Rc;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use embassy_time::{Instant, Timer};
+use korri_n2k::protocol::transport::traits::can_bus::CanBus;
+use socketcan_receiver::node::{
+    claim, init_manager, instance, pgn_task, ClaimEvent, ClaimLog, Executor, IsoIdentity,
+    MemoryHub, SocketCanBus, Spawner, INSTANCES, TASK_PGNS,
+};
+use socketcan_receiver::pgn::parse_number;
+
+/// What `dual_run_1` and `dual_run_2` run.
+const DUAL_RUN_PGNS: [u32; 3] = [127503, 129025, 127488];
+
+#[derive(Parser)]
+#[command(about = "Run several korri-n2k nodes on one bus and report their address claims")]
+struct Args {
+    /// `memory` for an in-process bus, or a CAN interface (vcan0...).
+    #[arg(default_value = "memory")]
+    bus: String,
+    /// Node to start, repeatable, e.g. `inst3` or `inst1,address=150,start=200`.
+    /// Defaults to inst1..inst5.
+    #[arg(long = "node", value_name = "SPEC")]
+    nodes: Vec<String>,
+    /// Seconds to run before reporting.
+    #[arg(long, default_value_t = 5)]
+    duration: u64,
+}
+
+struct Node {
+    label: String,
+    identity: IsoIdentity,
+    pgns: Vec<u32>,
+    start_ms: u64,
+}
+
+/// How a node's own claim went, as opposed to what the bus saw.
+enum Outcome {
+    Waiting,
+    Claimed(u8),
+    Failed(String),
+    Stopped(String),
+}
+
+type Outcomes = Rc<RefCell<Vec<Outcome>>>;
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let specs = if args.nodes.is_empty() {
+        INSTANCES.iter().map(|(name, _)| name.to_string()).collect()
+    } else {
+        args.nodes.clone()
+    };
+    let nodes = specs
+        .iter()
+        .enumerate()
+        .map(|(index, spec)| parse_node(index, spec).with_context(|| format!("bad node `{spec}`")))
+        .collect::<Result<Vec<_>>>()?;
+
+    if args.bus == "memory" {
+        let hub = MemoryHub::new();
+        let buses = nodes.iter().map(|_| hub.attach()).collect();
+        run(nodes, buses, hub.attach(), args.duration);
+    } else {
+        let open =
+            || SocketCanBus::open(&args.bus).with_context(|| format!("cannot open {}", args.bus));
+        let buses = nodes.iter().map(|_| open()).collect::<Result<_>>()?;
+        run(nodes, buses, open()?, args.duration);
+    }
+    Ok(())
+}
+
+fn parse_node(index: usize, spec: &str) -> Result<Node> {
+    let mut parts = spec.split(',');
+    let first = parts.next().unwrap_or_default();
+    let (label, mut identity, rest) = match instance(first) {
+        Some(identity) => (first.to_string(), identity, None),
+        None => (format!("node{}", index + 1), INSTANCES[0].1, Some(first)),
+    };
+    let mut node = Node {
+        label,
+        identity,
+        pgns: DUAL_RUN_PGNS.to_vec(),
+        start_ms: 0,
+    };
+    for part in rest.into_iter().chain(parts) {
+        let Some((key, value)) = part.split_once('=') else {
+            bail!("expected an instance or key=value, got `{part}`");
+        };
+        let number = || parse_number(value).with_context(|| format!("bad {key} `{value}`"));
+        match key {
+            "address" => identity.preferred_address = number()?.try_into()?,
+            "unique" => identity.unique_number = number()?,
+            "manufacturer" => identity.manufacturer_code = number()?.try_into()?,
+            "function" => identity.device_function = number()?.try_into()?,
+            "class" => identity.device_class = number()?.try_into()?,
+            "instance" => identity.device_instance = number()?.try_into()?,
+            "system" => identity.system_instance = number()?.try_into()?,
+            "industry" => identity.industry_group = number()?.try_into()?,
+            "start" => node.start_ms = number()?.into(),
+            "label" => node.label = value.to_string(),
+            "pgn" => {
+                node.pgns = value
+                    .split('+')
+                    .filter(|pgn| !pgn.is_empty())
+                    .map(|pgn| pgn.parse().with_context(|| format!("bad PGN `{pgn}`")))
+                    .collect::<Result<_>>()?;
+                if let Some(pgn) = node.pgns.iter().find(|pgn| !TASK_PGNS.contains(pgn)) {
+                    bail!("no shared-core task for PGN {pgn}; available: {TASK_PGNS:?}");
+                }
+            }
+            _ => bail!("unknown key `{key}`"),
+        }
+    }
+    node.identity = identity;
+    Ok(node)
+}
+
+/// Starts every node on its bus and the claim monitor on `monitor`, then
+/// reports after `duration` seconds and exits.
+fn run<C>(nodes: Vec<Node>, buses: Vec<C>, mut monitor: C, duration: u64)
+where
+    C: CanBus + 'static,
+    C::Error: Debug,
+{
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    let start = Instant::now();
+    let log = Rc::new(RefCell::new(ClaimLog::new()));
+    let outcomes: Outcomes = Rc::new(RefCell::new(
+        nodes.iter().map(|_| Outcome::Waiting).collect(),
+    ));
+
+    let monitoring = Rc::clone(&log);
+    executor.spawn(async move {
+        loop {
+            match monitor.recv().await {
+                Ok(frame) => {
+                    let time_us = start.elapsed().as_micros();
+                    monitoring.borrow_mut().push(&frame, time_us);
+                }
+                Err(e) => {
+                    eprintln!("monitor stopped: {e:?}");
+                    return;
+                }
+            }
+        }
+    });
+
+    for (index, (node, bus)) in nodes.iter().zip(buses).enumerate() {
+        executor.spawn(node_task(
+            index,
+            node.identity,
+            node.pgns.clone(),
+            node.start_ms,
+            bus,
+            spawner.clone(),
+            Rc::clone(&outcomes),
+        ));
+    }
+
+    executor.spawn(async move {
+        Timer::after_secs(duration).await;
+        report(&nodes, &log.borrow(), &outcomes.borrow());
+        process::exit(0);
+    });
+    executor.run();
+}
+
+/// One node: claim, then the supervisor and PGN tasks, as a board's `main`.
+async fn node_task<C>(
+    index: usize,
+    identity: IsoIdentity,
+    pgns: Vec<u32>,
+    start_ms: u64,
+    bus: C,
+    spawner: Spawner,
+    outcomes: Outcomes,
+) where
+    C: CanBus + 'static,
+    C::Error: Debug,
+{
+    Timer::after_millis(start_ms).await;
+    let manager = match claim(bus, &identity).await {
+        Ok(manager) => manager,
+        Err(e) => {
+            outcomes.borrow_mut()[index] = Outcome::Failed(format!("{e:?}"));
+            return;
+        }
+    };
+    outcomes.borrow_mut()[index] = Outcome::Claimed(manager.current_address());
+
+    let (runner, handle) = init_manager(manager);
+    spawner.spawn(async move {
+        if let Err(e) = runner.drive().await {
+            outcomes.borrow_mut()[index] = Outcome::Stopped(format!("{e:?}"));
+        }
+    });
+    for pgn in pgns {
+        if let Some(task) = pgn_task(pgn, handle) {
+            spawner.spawn(task);
+        }
+    }
+}
+
+fn report(nodes: &[Node], log: &ClaimLog, outcomes: &[Outcome]) {
+    let labels: HashMap<u64, &str> = nodes
+        .iter()
+        .map(|node| (node.identity.iso_name().raw(), node.label.as_str()))
+        .collect();
+    let label = |name: u64| labels.get(&name).copied().unwrap_or("(external)");
+
+    println!("Address table");
+    println!(
+        "  {:>7}  {:<12} {:<18}  {:>9}  claim",
+        "address", "node", "NAME", "preferred"
+    );
+    for (address, name) in log.table() {
+        let preferred = nodes
+            .iter()
+            .find(|node| node.identity.iso_name().raw() == name)
+            .map_or("-".to_string(), |node| {
+                node.identity.preferred_address.to_string()
+            });
+        println!(
+            "  {address:>7}  {:<12} 0x{name:016X}  {preferred:>9}",
+            label(name)
+        );
+    }
+    for (node, outcome) in nodes.iter().zip(outcomes) {
+        let name = node.identity.iso_name().raw();
+        let held = log.address_of(name);
+        let claim = match outcome {
+            Outcome::Waiting => "not started".to_string(),
+            Outcome::Claimed(_) if held.is_some() => continue,
+            Outcome::Claimed(address) => format!("claimed {address}, since lost"),
+            Outcome::Failed(e) => format!("failed: {e}"),
+            Outcome::Stopped(e) => format!("supervisor stopped: {e}"),
+        };
+        let address = held.map_or("-".to_string(), |a| a.to_string());
+        println!(
+            "  {address:>7}  {:<12} 0x{name:016X}  {:>9}  {claim}",
+            node.label, node.identity.preferred_address
+        );
+    }
+
+    println!();
+    println!("Claim history");
+    for (time_us, event) in log.history() {
+        let what = match *event {
+            ClaimEvent::Claimed { address, .. } => format!("claims {address}"),
+            ClaimEvent::Moved { from, address, .. } => format!("moves {from} -> {address}"),
+            ClaimEvent::Conflict {
+                name,
+                holder,
+                address,
+            } => {
+                let winner = if name < holder { name } else { holder };
+                format!(
+                    "claims {address} from {}: {} wins (lower NAME)",
+                    label(holder),
+                    label(winner)
+                )
+            }
+            ClaimEvent::CannotClaim { .. } => "cannot claim an address".to_string(),
+        };
+        println!(
+            "  {:>4}.{:03} s  {:<12} {what}",
+            time_us / 1_000_000,
+            time_us / 1_000 % 1_000,
+            label(event.name())
+        );
+    }
+}
@@ -0,0 +1,325 @@
+//! Live bus statistics, like `top`: load per PGN and per source, bus
+//! utilisation from the frames' bit lengths, fast packets and error frames.
+//!
+//! ```text
+//! n2k-top can0                           # refreshed every second
+//! n2k-top can0 --interval 5 --rows 20
+//! n2k-top can0 --batch >> load.log        # one report after another
+//! n2k-top stress.kn2kcap                 # a capture, interval by interval
+//! ```
+//!
+//! Both tables are sorted by load. Sources are named from their address
+//! claims. A capture file is cut into intervals on its own timestamps and
+//! reported at once, as `--batch` would.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use korri_n2k::protocol::managment::iso_name::IsoName;
+use socketcan::{CanError, CanSocket, Socket, SocketOptions};
+use socketcan_receiver::busload::{BusLoad, N2K_BITRATE};
+use socketcan_receiver::candump::from_socketcan;
+use socketcan_receiver::capture::TimestampedFrame;
+use socketcan_receiver::messages::MessageAssembler;
+use socketcan_receiver::node::ClaimLog;
+use socketcan_receiver::pgn::{lookup_name, pgn_name};
+use socketcan_receiver::replay::open_capture;
+
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+const CLEAR_SCREEN: &str = "\x1b[H\x1b[2J";
+const RED: &str = "31";
+const YELLOW: &str = "33";
+const BOLD: &str = "1";
+
+#[derive(Parser)]
+#[command(about = "Live per-PGN and per-source load of an NMEA 2000 bus")]
+struct Args {
+    /// CAN interface (can0, vcan0...), or a capture file.
+    #[arg(default_value = "can0")]
+    source: String,
+    /// Seconds between two reports.
+    #[arg(long, default_value_t = 1.0)]
+    interval: f64,
+    /// Lines in each table.
+    #[arg(long, default_value_t = 15)]
+    rows: usize,
+    /// Bus bitrate, bit/s, the 100 % of the load.
+    #[arg(long, default_value_t = N2K_BITRATE)]
+    bitrate: u32,
+    /// Plain reports one after another, rather than a refreshed screen.
+    #[arg(long)]
+    batch: bool,
+}
+
+/// Counts across intervals, and the one under way.
+struct Top {
+    bitrate: u32,
+    rows: usize,
+    colour: bool,
+    interval: BusLoad,
+    claims: ClaimLog,
+    messages: MessageAssembler,
+    errors: BTreeMap<&'static str, u64>,
+    peak: f64,
+}
+
+impl Top {
+    fn push(&mut self, frame: &TimestampedFrame) {
+        self.interval.push(frame);
+        if let Some(can) = frame.to_can_frame() {
+            self.claims.push(&can, frame.timestamp_us);
+            self.messages.push(&can, frame.timestamp_us);
+        }
+    }
+
+    fn push_error(&mut self, error: CanError) {
+        self.interval.error_frames += 1;
+        *self.errors.entry(error_kind(&error)).or_default() += 1;
+    }
+
+    /// Report on the interval just over, `duration` long, then starts the
+    /// next one.
+    fn report(&mut self, title: &str, duration: Duration) -> String {
+        let load = std::mem::take(&mut self.interval);
+        let percent = load.total.percent(self.bitrate, duration);
+        self.peak = self.peak.max(percent);
+        let names: BTreeMap<u8, u64> = self.claims.table().collect();
+        let fast_packets = self.messages.fast_packets();
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{title}  {} kbit/s  load {}  peak {:.1} %  {:.0} frames/s  {:.0} error frames/s",
+            self.bitrate / 1000,
+            self.paint_load(percent),
+            self.peak,
+            load.total.per_second(duration),
+            load.error_frames as f64 / duration.as_secs_f64()
+        );
+        let _ = writeln!(
+            out,
+            "fast packets {:.1} sessions/s, {} reassembled, {} lost   non-N2K {:.0} frames/s",
+            load.sessions as f64 / duration.as_secs_f64(),
+            fast_packets.messages,
+            fast_packets.errors(),
+            load.other.per_second(duration)
+        );
+        if !self.errors.is_empty() {
+            let errors: Vec<String> = self
+                .errors
+                .iter()
+                .map(|(kind, count)| format!("{kind} {count}"))
+                .collect();
+            let _ = writeln!(out, "error frames since start: {}", errors.join(", "));
+        }
+
+        let _ = writeln!(out);
+        let _ = writeln!(
+            out,
+            "{}",
+            self.paint(
+                &format!(
+                    "{:>7}  {:<34} {:>9} {:>8} {:>9}  sources",
+                    "PGN", "name", "frames/s", "load %", "fp/s"
+                ),
+                BOLD
+            )
+        );
+        for (pgn, entry) in load.by_pgn().into_iter().take(self.rows) {
+            let sources: Vec<String> = entry.sources.iter().map(u8::to_string).collect();
+            let _ = writeln!(
+                out,
+                "{pgn:>7}  {:<34} {:>9.1} {:>8.2} {:>9}  {}",
+                truncate(pgn_name(pgn), 34),
+                entry.load.per_second(duration),
+                entry.load.percent(self.bitrate, duration),
+                match entry.sessions {
+                    0 => "-".to_string(),
+                    n => format!("{:.1}", n as f64 / duration.as_secs_f64()),
+                },
+                sources.join(" ")
+            );
+        }
+
+        let _ = writeln!(out);
+        let _ = writeln!(
+            out,
+            "{}",
+            self.paint(
+                &format!(
+                    "{:>7}  {:<34} {:>9} {:>8}  PGNs",
+                    "source", "device", "frames/s", "load %"
+                ),
+                BOLD
+            )
+        );
+        for (source, entry) in load.by_source().into_iter().take(self.rows) {
+            let device = names.get(&source).map_or("-".to_string(), |&name| {
+                let code = IsoName::from_raw(name).manufacturer_code();
+                let manufacturer = lookup_name("ManufacturerCode", code as u64)
+                    .unwrap_or_else(|| format!("mfr {code}"));
+                format!("{manufacturer} 0x{name:016X}")
+            });
+            let _ = writeln!(
+                out,
+                "{source:>7}  {:<34} {:>9.1} {:>8.2}  {}",
+                truncate(&device, 34),
+                entry.load.per_second(duration),
+                entry.load.percent(self.bitrate, duration),
+                entry.pgns.len()
+            );
+        }
+        out
+    }
+
+    fn paint_load(&self, percent: f64) -> String {
+        let text = format!("{percent:5.1} %");
+        match percent {
+            p if p >= 80.0 => self.paint(&text, RED),
+            p if p >= 50.0 => self.paint(&text, YELLOW),
+            _ => text,
+        }
+    }
+
+    fn paint(&self, text: &str, code: &str) -> String {
+        if self.colour {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    if !(args.interval > 0.0 && args.interval.is_finite()) {
+        bail!("--interval must be above 0");
+    }
+    if args.bitrate == 0 {
+        bail!("--bitrate must be above 0");
+    }
+    let interval = Duration::from_secs_f64(args.interval);
+    let file = Path::new(&args.source).is_file();
+    let batch = args.batch || file || !io::stdout().is_terminal();
+    let mut top = Top {
+        bitrate: args.bitrate,
+        rows: args.rows,
+        colour: !batch && std::env::var_os("NO_COLOR").is_none(),
+        interval: BusLoad::new(),
+        claims: ClaimLog::new(),
+        messages: MessageAssembler::new(),
+        errors: BTreeMap::new(),
+        peak: 0.0,
+    };
+    if file {
+        capture(&args.source, interval, &mut top)
+    } else {
+        live(&args.source, interval, batch, &mut top)
+    }
+}
+
+fn live(interface: &str, interval: Duration, batch: bool, top: &mut Top) -> Result<()> {
+    let socket = CanSocket::open(interface).with_context(|| format!("cannot open {interface}"))?;
+    socket.set_read_timeout(READ_TIMEOUT)?;
+    socket.set_error_filter_accept_all()?;
+
+    let mut since = Instant::now();
+    loop {
+        match socket.read_frame() {
+            Ok(socketcan::CanFrame::Error(error)) => top.push_error(error.into_error()),
+            Ok(frame) => {
+                let now_us = SystemTime::now().duration_since(UNIX_EPOCH)?.as_micros() as u64;
+                if let Some(frame) = from_socketcan(&frame, now_us) {
+                    top.push(&frame);
+                }
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                        | io::ErrorKind::Interrupted
+                ) => {}
+            Err(e) => return Err(e).context("CAN read error"),
+        }
+        let elapsed = since.elapsed();
+        if elapsed >= interval {
+            since = Instant::now();
+            let report = top.report(interface, elapsed);
+            if batch {
+                println!("{report}");
+            } else {
+                print!("{CLEAR_SCREEN}{report}");
+            }
+        }
+    }
+}
+
+/// Reports on a capture, interval by interval of its own time.
+fn capture(path: &str, interval: Duration, top: &mut Top) -> Result<()> {
+    let (_, frames) =
+        open_capture(Path::new(path), None).with_context(|| format!("cannot open {path}"))?;
+    let step = (interval.as_micros() as u64).max(1);
+    let mut start: Option<u64> = None;
+    let mut last_us = 0;
+    for frame in frames {
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let begin = *start.get_or_insert(frame.timestamp_us);
+        // A step back, a target restart, starts the intervals over.
+        if frame.timestamp_us < begin {
+            start = Some(frame.timestamp_us);
+        }
+        if let Some(begin) = start.filter(|&b| frame.timestamp_us >= b + step) {
+            println!("{}", top.report(&title(begin), interval));
+            // Empty intervals, a quiet bus or a gap in the capture, are
+            // skipped: the next one is that of this frame.
+            start = Some(begin + (frame.timestamp_us - begin) / step * step);
+        }
+        last_us = frame.timestamp_us;
+        top.push(&frame);
+    }
+    if let Some(begin) = start {
+        let rest =
+            Duration::from_micros(last_us.saturating_sub(begin)).max(Duration::from_millis(1));
+        if top.interval.total.frames > 0 {
+            println!("{}", top.report(&title(begin), rest));
+        }
+    }
+    Ok(())
+}
+
+/// An interval of a capture, by its timestamp.
+fn title(begin_us: u64) -> String {
+    format!("t={}.{:03} s", begin_us / 1_000_000, begin_us / 1000 % 1000)
+}
+
+fn error_kind(error: &CanError) -> &'static str {
+    match error {
+        CanError::TransmitTimeout => "tx timeout",
+        CanError::LostArbitration(_) => "lost arbitration",
+        CanError::ControllerProblem(_) => "controller",
+        CanError::ProtocolViolation { .. } => "protocol",
+        CanError::TransceiverError => "transceiver",
+        CanError::NoAck => "no ack",
+        CanError::BusOff => "bus off",
+        CanError::BusError => "bus error",
+        CanError::Restarted => "restarted",
+        _ => "other",
+    }
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    match text.char_indices().nth(width) {
+        Some((end, _)) => text[..end].to_string(),
+        None => text.to_string(),
+    }
+}
@@ -0,0 +1,198 @@
+//! Serve NMEA 0183 sentences over TCP, converted from a SocketCAN bus.
+//!
+//! ```text
+//! nmea0183-server can0                  # port 10110, talker II
+//! nmea0183-server can0 --talker GP --port 2000
+//! ```
+//!
+//! OpenCPN: add a network connection, TCP, to this host and port.
+//!
+//! Every client has its own queue and writing thread: one that cannot keep up
+//! loses sentences without holding back the bus or the others.
+
+use std::io::Write;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use shared_core::nmea0183::{Nmea0183Converter, Sentence};
+use socketcan::{CanSocket, Socket};
+use socketcan_receiver::candump::from_socketcan;
+use socketcan_receiver::messages::MessageAssembler;
+
+/// A client that cannot take a sentence within this delay is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+/// A client whose queue has been full for this long is dropped too: it is not
+/// reading at all.
+const STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Parser)]
+#[command(about = "Serve NMEA 0183 sentences over TCP, converted from a SocketCAN bus")]
+struct Args {
+    /// CAN interface (can0, vcan0...).
+    #[arg(default_value = "can0")]
+    interface: String,
+    /// TCP port, 10110 being the usual one for NMEA 0183.
+    #[arg(long, default_value_t = 10110)]
+    port: u16,
+    /// Listening address.
+    #[arg(long, default_value = "0.0.0.0")]
+    bind: String,
+    /// Talker ID prefixing every sentence.
+    #[arg(long, default_value = "II")]
+    talker: String,
+    /// Sentences queued per client before it loses some.
+    #[arg(long, default_value_t = 1024)]
+    queue: usize,
+}
+
+struct Client {
+    peer: String,
+    queue: SyncSender<Arc<Vec<u8>>>,
+    dropped: Arc<AtomicU64>,
+    full_since: Option<Instant>,
+    /// To hang up on a stalled client, whose thread then ends.
+    stream: TcpStream,
+}
+
+type Clients = Arc<Mutex<Vec<Client>>>;
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let talker = match args.talker.as_bytes() {
+        &[a, b] if a.is_ascii_uppercase() && b.is_ascii_alphanumeric() => [a, b],
+        _ => bail!("talker ID must be two characters, e.g. II or GP"),
+    };
+    if args.queue == 0 {
+        bail!("--queue must be above 0");
+    }
+
+    let socket = CanSocket::open(&args.interface)
+        .with_context(|| format!("cannot open {}", args.interface))?;
+    let listener = TcpListener::bind((args.bind.as_str(), args.port))
+        .with_context(|| format!("cannot listen on {}:{}", args.bind, args.port))?;
+    eprintln!(
+        "{} -> NMEA 0183 on {}",
+        args.interface,
+        listener.local_addr()?
+    );
+
+    let clients = Clients::default();
+    let accepting = Arc::clone(&clients);
+    let queue = args.queue;
+    thread::spawn(move || accept(listener, accepting, queue));
+
+    let mut converter = Nmea0183Converter::new(talker);
+    let mut assembler = MessageAssembler::new();
+    let start = Instant::now();
+
+    loop {
+        let frame = match socket.read_frame() {
+            Ok(frame) => frame,
+            Err(e) => {
+                eprintln!("CAN read error: {e}");
+                continue;
+            }
+        };
+        let Some(frame) = from_socketcan(&frame, 0).and_then(|f| f.to_can_frame()) else {
+            continue;
+        };
+        if !Nmea0183Converter::handles(frame.id.pgn()) {
+            continue;
+        }
+        let Some(message) = assembler.push(&frame, start.elapsed().as_micros() as u64) else {
+            continue;
+        };
+
+        converter.convert(message.pgn, &message.payload, &mut |sentence: &Sentence| {
+            broadcast(&clients, Arc::new(sentence.as_bytes().to_vec()));
+        });
+    }
+}
+
+fn accept(listener: TcpListener, clients: Clients, queue: usize) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("accept error: {e}");
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map_or("?".to_string(), |a| a.to_string());
+        if stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() {
+            continue;
+        }
+        let _ = stream.set_nodelay(true);
+        let Ok(hang_up) = stream.try_clone() else {
+            continue;
+        };
+        eprintln!("client {peer} connected");
+
+        let (sender, receiver) = sync_channel(queue);
+        let dropped = Arc::new(AtomicU64::new(0));
+        clients.lock().unwrap().push(Client {
+            peer: peer.clone(),
+            queue: sender,
+            dropped: Arc::clone(&dropped),
+            full_since: None,
+            stream: hang_up,
+        });
+        thread::spawn(move || write_client(stream, receiver, &peer, &dropped));
+    }
+}
+
+/// Queues `bytes` for every client. A client whose writing thread is gone
+/// left, one whose queue stays full is hung up on.
+fn broadcast(clients: &Clients, bytes: Arc<Vec<u8>>) {
+    clients
+        .lock()
+        .unwrap()
+        .retain_mut(|client| match client.queue.try_send(Arc::clone(&bytes)) {
+            Ok(()) => {
+                client.full_since = None;
+                true
+            }
+            Err(TrySendError::Full(_)) => {
+                if client.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                    eprintln!("client {} not keeping up, losing sentences", client.peer);
+                }
+                let since = *client.full_since.get_or_insert_with(Instant::now);
+                if since.elapsed() >= STALL_TIMEOUT {
+                    eprintln!("client {} stalled, disconnecting", client.peer);
+                    let _ = client.stream.shutdown(Shutdown::Both);
+                    return false;
+                }
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+}
+
+/// Drains the client's queue into its socket, until either side closes.
+fn write_client(
+    mut stream: TcpStream,
+    receiver: Receiver<Arc<Vec<u8>>>,
+    peer: &str,
+    dropped: &AtomicU64,
+) {
+    while let Ok(bytes) = receiver.recv() {
+        if stream.write_all(&bytes).is_err() {
+            break;
+        }
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+    let dropped = dropped.load(Ordering::Relaxed);
+    if dropped > 0 {
+        eprintln!("client {peer} disconnected, {dropped} sentences lost to a full queue");
+    } else {
+        eprintln!("client {peer} disconnected");
+    }
+}
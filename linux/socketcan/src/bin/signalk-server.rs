@@ -0,0 +1,250 @@
+//! Serve SignalK deltas over TCP and WebSocket, decoded from a SocketCAN bus.
+//!
+//! ```text
+//! signalk-server can0                   # TCP 8375, WebSocket 3000
+//! signalk-server can0 --mmsi 227123456 --ws-port 3001
+//! ```
+//!
+//! TCP clients get one delta per line, WebSocket clients one per message,
+//! after a hello. Subscriptions are not handled: every client gets every
+//! delta.
+//!
+//! Every client has its own thread, which does the WebSocket handshake and
+//! drains the client's queue: one that cannot keep up loses deltas without
+//! holding back the bus or the others.
+
+use std::io::Write;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use clap::Parser;
+use socketcan::{CanSocket, Socket};
+use socketcan_receiver::candump::from_socketcan;
+use socketcan_receiver::messages::MessageAssembler;
+use socketcan_receiver::signalk::{hello, SignalKConverter};
+use tungstenite::WebSocket;
+
+/// A client that cannot take a delta within this delay is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+/// A client whose queue has been full for this long is dropped too: it is not
+/// reading at all.
+const STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time a WebSocket client has to complete its handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SERVER_NAME: &str = "signalk-server";
+
+#[derive(Parser)]
+#[command(about = "Serve SignalK deltas over TCP and WebSocket, decoded from a SocketCAN bus")]
+struct Args {
+    /// CAN interface (can0, vcan0...).
+    #[arg(default_value = "can0")]
+    interface: String,
+    /// TCP port, newline-delimited deltas. 8375 is the SignalK one.
+    #[arg(long, default_value_t = 8375)]
+    port: u16,
+    /// WebSocket port. Any path is accepted, /signalk/v1/stream included.
+    #[arg(long, default_value_t = 3000)]
+    ws_port: u16,
+    /// Listening address.
+    #[arg(long, default_value = "0.0.0.0")]
+    bind: String,
+    /// MMSI of the own vessel; `vessels.self` otherwise.
+    #[arg(long)]
+    mmsi: Option<u32>,
+    /// Source label in `$source`, the interface name by default.
+    #[arg(long)]
+    label: Option<String>,
+    /// Deltas queued per client before it loses some.
+    #[arg(long, default_value_t = 1024)]
+    queue: usize,
+}
+
+enum Connection {
+    Tcp(TcpStream),
+    Ws(Box<WebSocket<TcpStream>>),
+}
+
+impl Connection {
+    fn send(&mut self, delta: &str) -> bool {
+        match self {
+            Self::Tcp(stream) => stream
+                .write_all(delta.as_bytes())
+                .and_then(|_| stream.write_all(b"\n"))
+                .is_ok(),
+            Self::Ws(socket) => socket.send(tungstenite::Message::text(delta)).is_ok(),
+        }
+    }
+
+    fn stream(&self) -> &TcpStream {
+        match self {
+            Self::Tcp(stream) => stream,
+            Self::Ws(socket) => socket.get_ref(),
+        }
+    }
+}
+
+struct Client {
+    peer: String,
+    queue: SyncSender<Arc<String>>,
+    dropped: Arc<AtomicU64>,
+    full_since: Option<Instant>,
+    /// To hang up on a stalled client, whose thread then ends.
+    stream: TcpStream,
+}
+
+type Clients = Arc<Mutex<Vec<Client>>>;
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let label = args.label.clone().unwrap_or_else(|| args.interface.clone());
+    if args.queue == 0 {
+        bail!("--queue must be above 0");
+    }
+
+    let socket = CanSocket::open(&args.interface)
+        .with_context(|| format!("cannot open {}", args.interface))?;
+    let tcp = TcpListener::bind((args.bind.as_str(), args.port))
+        .with_context(|| format!("cannot listen on {}:{}", args.bind, args.port))?;
+    let ws = TcpListener::bind((args.bind.as_str(), args.ws_port))
+        .with_context(|| format!("cannot listen on {}:{}", args.bind, args.ws_port))?;
+    eprintln!(
+        "{} -> SignalK on tcp://{} and ws://{}",
+        args.interface,
+        tcp.local_addr()?,
+        ws.local_addr()?
+    );
+
+    let mut converter = SignalKConverter::new(&label, args.mmsi);
+    let clients = Clients::default();
+    for (listener, websocket) in [(tcp, false), (ws, true)] {
+        let clients = Arc::clone(&clients);
+        let context = converter.context().to_string();
+        let queue = args.queue;
+        thread::spawn(move || accept(listener, websocket, &context, clients, queue));
+    }
+
+    let mut assembler = MessageAssembler::new();
+    loop {
+        let frame = match socket.read_frame() {
+            Ok(frame) => frame,
+            Err(e) => {
+                eprintln!("CAN read error: {e}");
+                continue;
+            }
+        };
+        let now = Utc::now();
+        let Some(frame) = from_socketcan(&frame, 0).and_then(|f| f.to_can_frame()) else {
+            continue;
+        };
+        let Some(message) = assembler.push(&frame, now.timestamp_micros() as u64) else {
+            continue;
+        };
+        if let Some(delta) = converter.convert(&message, now) {
+            broadcast(&clients, Arc::new(delta.to_string()));
+        }
+    }
+}
+
+fn accept(listener: TcpListener, websocket: bool, context: &str, clients: Clients, queue: usize) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("accept error: {e}");
+                continue;
+            }
+        };
+        let context = context.to_string();
+        let clients = Arc::clone(&clients);
+        thread::spawn(move || serve(stream, websocket, &context, &clients, queue));
+    }
+}
+
+/// One client, on its own thread: handshake and hello, then the deltas
+/// queued for it until either side closes.
+fn serve(stream: TcpStream, websocket: bool, context: &str, clients: &Clients, queue: usize) {
+    let peer = stream
+        .peer_addr()
+        .map_or("?".to_string(), |a| a.to_string());
+    if stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() {
+        return;
+    }
+    let _ = stream.set_nodelay(true);
+    let Ok(hang_up) = stream.try_clone() else {
+        return;
+    };
+
+    let mut connection = if websocket {
+        let _ = stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT));
+        match tungstenite::accept(stream) {
+            Ok(socket) => Connection::Ws(Box::new(socket)),
+            Err(e) => {
+                eprintln!("client {peer}: WebSocket handshake failed: {e}");
+                return;
+            }
+        }
+    } else {
+        Connection::Tcp(stream)
+    };
+    if !connection.send(&hello(SERVER_NAME, context).to_string()) {
+        return;
+    }
+    eprintln!("client {peer} connected");
+
+    let (sender, receiver) = sync_channel(queue);
+    let dropped = Arc::new(AtomicU64::new(0));
+    clients.lock().unwrap().push(Client {
+        peer: peer.clone(),
+        queue: sender,
+        dropped: Arc::clone(&dropped),
+        full_since: None,
+        stream: hang_up,
+    });
+    while let Ok(delta) = receiver.recv() {
+        if !connection.send(&delta) {
+            break;
+        }
+    }
+    let _ = connection.stream().shutdown(Shutdown::Both);
+    let dropped = dropped.load(Ordering::Relaxed);
+    if dropped > 0 {
+        eprintln!("client {peer} disconnected, {dropped} deltas lost to a full queue");
+    } else {
+        eprintln!("client {peer} disconnected");
+    }
+}
+
+/// Queues `delta` for every client. A client whose thread is gone left, one
+/// whose queue stays full is hung up on.
+fn broadcast(clients: &Clients, delta: Arc<String>) {
+    clients
+        .lock()
+        .unwrap()
+        .retain_mut(|client| match client.queue.try_send(Arc::clone(&delta)) {
+            Ok(()) => {
+                client.full_since = None;
+                true
+            }
+            Err(TrySendError::Full(_)) => {
+                if client.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                    eprintln!("client {} not keeping up, losing deltas", client.peer);
+                }
+                let since = *client.full_since.get_or_insert_with(Instant::now);
+                if since.elapsed() >= STALL_TIMEOUT {
+                    eprintln!("client {} stalled, disconnecting", client.peer);
+                    let _ = client.stream.shutdown(Shutdown::Both);
+                    return false;
+                }
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+}
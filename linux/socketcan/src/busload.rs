@@ -0,0 +1,206 @@
+//! Bus load from the frames seen: how long each frame holds the wire, stuff
+//! bits included, summed per PGN and per source over an interval.
+//!
+//! Stuff bits are counted on the frame's actual bit stream, CRC included, so
+//! an 8-byte extended frame costs between 131 and 160 bit times, intermission
+//! included, depending on its id and data.
+
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+use korri_n2k::protocol::transport::can_id::CanId;
+
+use crate::capture::wire::TimestampedFrame;
+use crate::pgn::is_fast_packet;
+
+/// NMEA 2000 backbone bitrate.
+pub const N2K_BITRATE: u32 = 250_000;
+
+/// CRC delimiter, ACK slot and delimiter, end of frame, intermission: after
+/// the stuffed part, never stuffed.
+const TRAILER_BITS: u32 = 1 + 2 + 7 + 3;
+
+/// Bit times `frame` holds the bus for, from start of frame to the end of
+/// the intermission that follows.
+pub fn frame_bits(frame: &TimestampedFrame) -> u32 {
+    let mut bits = Bits::default();
+    bits.push(0, 1); // start of frame
+    let rtr = frame.is_remote() as u32;
+    if frame.is_extended() {
+        let id = frame.id & 0x1FFF_FFFF;
+        bits.push(id >> 18, 11);
+        bits.push(0b11, 2); // SRR, IDE
+        bits.push(id & 0x3_FFFF, 18);
+        bits.push(rtr, 1);
+        bits.push(0, 2); // r1, r0
+    } else {
+        bits.push(frame.id & 0x7FF, 11);
+        bits.push(rtr, 1);
+        bits.push(0, 2); // IDE, r0
+    }
+    bits.push(frame.len.min(8) as u32, 4);
+    if !frame.is_remote() {
+        for &byte in frame.payload() {
+            bits.push(byte as u32, 8);
+        }
+    }
+    let crc = bits.crc15();
+    bits.push(crc as u32, 15);
+    bits.len() + bits.stuff_bits() + TRAILER_BITS
+}
+
+/// The stuffed part of a frame, start of frame to CRC, at most 118 bits.
+#[derive(Default)]
+struct Bits {
+    bits: Vec<bool>,
+}
+
+impl Bits {
+    /// The `count` low bits of `value`, most significant first.
+    fn push(&mut self, value: u32, count: u32) {
+        for i in (0..count).rev() {
+            self.bits.push(value >> i & 1 != 0);
+        }
+    }
+
+    fn len(&self) -> u32 {
+        self.bits.len() as u32
+    }
+
+    /// CAN CRC-15, polynomial 0x4599.
+    fn crc15(&self) -> u16 {
+        self.bits.iter().fold(0u16, |crc, &bit| {
+            let next = bit ^ (crc >> 14 & 1 != 0);
+            let crc = (crc << 1) & 0x7FFF;
+            if next {
+                crc ^ 0x4599
+            } else {
+                crc
+            }
+        })
+    }
+
+    /// One after every run of five equal bits; the stuff bit starts the next
+    /// run.
+    fn stuff_bits(&self) -> u32 {
+        let mut stuffed = 0;
+        let mut last = None;
+        let mut run = 0;
+        for &bit in &self.bits {
+            if Some(bit) == last {
+                run += 1;
+            } else {
+                last = Some(bit);
+                run = 1;
+            }
+            if run == 5 {
+                stuffed += 1;
+                last = Some(!bit);
+                run = 1;
+            }
+        }
+        stuffed
+    }
+}
+
+/// Frames and bit times.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Load {
+    pub frames: u64,
+    pub bits: u64,
+}
+
+impl Load {
+    fn add(&mut self, bits: u32) {
+        self.frames += 1;
+        self.bits += bits as u64;
+    }
+
+    /// Share of the bus, in percent, over `duration` at `bitrate`.
+    pub fn percent(&self, bitrate: u32, duration: Duration) -> f64 {
+        let capacity = bitrate as f64 * duration.as_secs_f64();
+        if capacity > 0.0 {
+            self.bits as f64 * 100.0 / capacity
+        } else {
+            0.0
+        }
+    }
+
+    pub fn per_second(&self, duration: Duration) -> f64 {
+        let seconds = duration.as_secs_f64();
+        if seconds > 0.0 {
+            self.frames as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PgnLoad {
+    pub load: Load,
+    pub sources: BTreeSet<u8>,
+    /// Fast packets started: first frames seen.
+    pub sessions: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SourceLoad {
+    pub load: Load,
+    pub pgns: BTreeSet<u32>,
+}
+
+/// What the bus carried over one interval.
+#[derive(Clone, Debug, Default)]
+pub struct BusLoad {
+    pub total: Load,
+    pub pgns: HashMap<u32, PgnLoad>,
+    pub sources: HashMap<u8, SourceLoad>,
+    /// Standard frames, or remote ones: not NMEA 2000.
+    pub other: Load,
+    pub sessions: u64,
+    pub error_frames: u64,
+}
+
+impl BusLoad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, frame: &TimestampedFrame) {
+        let bits = frame_bits(frame);
+        self.total.add(bits);
+        let Some(can) = frame.to_can_frame() else {
+            self.other.add(bits);
+            return;
+        };
+        let id = CanId(can.id.0);
+        let (pgn, source) = (id.pgn(), id.source_address());
+        let first_frame = is_fast_packet(pgn) && can.len > 0 && can.data[0] & 0x1F == 0;
+
+        let entry = self.pgns.entry(pgn).or_default();
+        entry.load.add(bits);
+        entry.sources.insert(source);
+        if first_frame {
+            entry.sessions += 1;
+            self.sessions += 1;
+        }
+        let entry = self.sources.entry(source).or_default();
+        entry.load.add(bits);
+        entry.pgns.insert(pgn);
+    }
+
+    /// PGNs, heaviest first.
+    pub fn by_pgn(&self) -> Vec<(u32, &PgnLoad)> {
+        let mut pgns: Vec<_> = self.pgns.iter().map(|(&pgn, load)| (pgn, load)).collect();
+        pgns.sort_by(|a, b| b.1.load.bits.cmp(&a.1.load.bits).then(a.0.cmp(&b.0)));
+        pgns
+    }
+
+    /// Sources, heaviest first.
+    pub fn by_source(&self) -> Vec<(u8, &SourceLoad)> {
+        let mut sources: Vec<_> = self.sources.iter().map(|(&s, load)| (s, load)).collect();
+        sources.sort_by(|a, b| b.1.load.bits.cmp(&a.1.load.bits).then(a.0.cmp(&b.0)));
+        sources
+    }
+}
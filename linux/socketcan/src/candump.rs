@@ -0,0 +1,285 @@
+//! can-utils log format, as written by `candump -l` and replayed by
+//! `canplayer`.
+//!
+//! ```text
+//! (1436509052.249713) can0 09F80E10#0102030405060708
+//! (1436509052.250021) can0 123#R
+//! ```
+//!
+//! An 8-digit id is extended, a 3-digit one standard. Frames are carried as
+//! [`TimestampedFrame`], the KN2KCAP record, so a capture converts both ways
+//! without loss and [`TimestampedFrame::to_can_frame`] gives the korri-n2k view.
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use socketcan::{CanSocket, EmbeddedFrame, ExtendedId, Frame, Id, Socket, StandardId};
+
+use crate::capture::wire::{TimestampedFrame, FLAG_EXTENDED, FLAG_REMOTE};
+
+/// One log line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CandumpRecord {
+    pub interface: String,
+    /// `timestamp_us` holds the log time: Unix time for `candump`, target
+    /// uptime for a converted capture.
+    pub frame: TimestampedFrame,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CandumpError {
+    /// Not `(seconds.microseconds)`.
+    BadTimestamp,
+    MissingInterface,
+    /// Missing `#`, or an id that is neither 3 nor 8 hex digits.
+    BadId,
+    BadData,
+    /// CAN FD (`##`) or error frame: no classic CAN equivalent.
+    Unsupported,
+}
+
+impl fmt::Display for CandumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::BadTimestamp => "bad timestamp",
+            Self::MissingInterface => "missing interface",
+            Self::BadId => "bad CAN id",
+            Self::BadData => "bad data",
+            Self::Unsupported => "CAN FD or error frame",
+        })
+    }
+}
+
+impl std::error::Error for CandumpError {}
+
+/// Parses one `(sec.usec) iface ID#DATA` line. Trailing fields, such as the
+/// direction some can-utils versions append, are ignored.
+pub fn parse_candump(line: &str) -> Result<CandumpRecord, CandumpError> {
+    let mut fields = line.split_ascii_whitespace();
+
+    let timestamp_us = fields
+        .next()
+        .and_then(|field| field.strip_prefix('(')?.strip_suffix(')'))
+        .and_then(parse_timestamp)
+        .ok_or(CandumpError::BadTimestamp)?;
+    let interface = fields.next().ok_or(CandumpError::MissingInterface)?;
+    let (id, data) = fields
+        .next()
+        .and_then(|field| field.split_once('#'))
+        .ok_or(CandumpError::BadId)?;
+
+    if data.starts_with('#') {
+        return Err(CandumpError::Unsupported);
+    }
+    let (id, flags) = match id.len() {
+        3 => (u32::from_str_radix(id, 16), 0),
+        8 => (u32::from_str_radix(id, 16), FLAG_EXTENDED),
+        _ => return Err(CandumpError::BadId),
+    };
+    let id = id.map_err(|_| CandumpError::BadId)?;
+    let limit = if flags & FLAG_EXTENDED != 0 {
+        0x1FFF_FFFF
+    } else {
+        0x7FF
+    };
+    if id > limit {
+        // Error frames set a flag above the 29 id bits.
+        return Err(CandumpError::Unsupported);
+    }
+
+    let mut frame = TimestampedFrame {
+        timestamp_us,
+        id,
+        data: [0; 8],
+        len: 0,
+        flags,
+    };
+
+    if let Some(dlc) = data.strip_prefix('R') {
+        frame.flags |= FLAG_REMOTE;
+        // Newer can-utils append the requested length.
+        frame.len = match dlc {
+            "" => 0,
+            _ => dlc
+                .parse()
+                .ok()
+                .filter(|&len| len <= 8)
+                .ok_or(CandumpError::BadData)?,
+        };
+        return Ok(CandumpRecord {
+            interface: interface.to_string(),
+            frame,
+        });
+    }
+
+    // A `_` suffix carries a raw DLC above 8, meaningless for the payload.
+    let data = data.split_once('_').map_or(data, |(data, _)| data);
+    let digits: Vec<u8> = data.bytes().filter(|&b| b != b'.').collect();
+    if !digits.len().is_multiple_of(2) || digits.len() > 16 {
+        return Err(CandumpError::BadData);
+    }
+    for (byte, pair) in frame.data.iter_mut().zip(digits.chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|_| CandumpError::BadData)?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| CandumpError::BadData)?;
+    }
+    frame.len = (digits.len() / 2) as u8;
+
+    Ok(CandumpRecord {
+        interface: interface.to_string(),
+        frame,
+    })
+}
+
+fn parse_timestamp(field: &str) -> Option<u64> {
+    let (seconds, micros) = field.split_once('.')?;
+    if micros.len() != 6 {
+        return None;
+    }
+    let seconds: u64 = seconds.parse().ok()?;
+    let micros: u64 = micros.parse().ok()?;
+    seconds.checked_mul(1_000_000)?.checked_add(micros)
+}
+
+/// Writes `frame` as one log line, newline included.
+pub fn write_candump(
+    out: &mut impl Write,
+    interface: &str,
+    frame: &TimestampedFrame,
+) -> io::Result<()> {
+    write!(
+        out,
+        "({:010}.{:06}) {interface} ",
+        frame.timestamp_us / 1_000_000,
+        frame.timestamp_us % 1_000_000
+    )?;
+    if frame.is_extended() {
+        write!(out, "{:08X}#", frame.id)?;
+    } else {
+        write!(out, "{:03X}#", frame.id)?;
+    }
+    if frame.is_remote() {
+        out.write_all(b"R")?;
+        if frame.len > 0 {
+            write!(out, "{}", frame.len)?;
+        }
+    } else {
+        for byte in frame.payload() {
+            write!(out, "{byte:02X}")?;
+        }
+    }
+    out.write_all(b"\n")
+}
+
+/// A frame read from a SocketCAN socket, or `None` for an error frame.
+pub fn from_socketcan(frame: &socketcan::CanFrame, timestamp_us: u64) -> Option<TimestampedFrame> {
+    if let socketcan::CanFrame::Error(_) = frame {
+        return None;
+    }
+    let mut flags = 0;
+    if frame.is_extended() {
+        flags |= FLAG_EXTENDED;
+    }
+    if frame.is_remote_frame() {
+        flags |= FLAG_REMOTE;
+    }
+    let mut data = [0u8; 8];
+    let payload = frame.data();
+    data[..payload.len()].copy_from_slice(payload);
+    Some(TimestampedFrame {
+        timestamp_us,
+        id: frame.raw_id(),
+        data,
+        len: frame.dlc().min(8) as u8,
+        flags,
+    })
+}
+
+/// The frame to write on a SocketCAN socket, or `None` for an id too wide for
+/// its format.
+pub fn to_socketcan(frame: &TimestampedFrame) -> Option<socketcan::CanFrame> {
+    let id: Id = if frame.is_extended() {
+        ExtendedId::new(frame.id)?.into()
+    } else {
+        StandardId::new(u16::try_from(frame.id).ok()?)?.into()
+    };
+    if frame.is_remote() {
+        socketcan::CanFrame::new_remote(id, frame.len as usize)
+    } else {
+        socketcan::CanFrame::new(id, frame.payload())
+    }
+}
+
+/// Writes `frame` to `socket`, retrying while the interface queue is full
+/// until `deadline`. A full queue answers ENOBUFS rather than blocking, or
+/// `WouldBlock` past the socket's write timeout. `Ok(false)` when the queue
+/// was still full at the deadline.
+pub fn write_frame_with_deadline(
+    socket: &CanSocket,
+    frame: &socketcan::CanFrame,
+    deadline: Instant,
+) -> io::Result<bool> {
+    loop {
+        match socket.write_frame(frame) {
+            Ok(()) => return Ok(true),
+            Err(e)
+                if e.raw_os_error() == Some(nix::libc::ENOBUFS)
+                    || e.kind() == io::ErrorKind::WouldBlock =>
+            {
+                if Instant::now() >= deadline {
+                    return Ok(false);
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Reads a log line by line. Blank lines and `#` comments, such as a recorder
+/// segment header, are skipped; a malformed line is an
+/// [`io::ErrorKind::InvalidData`] error naming it, after which reading can go
+/// on.
+pub struct CandumpReader<R> {
+    inner: R,
+    line: String,
+    line_number: u64,
+}
+
+impl<R: BufRead> CandumpReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            line: String::new(),
+            line_number: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CandumpReader<R> {
+    type Item = io::Result<CandumpRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.inner.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+            self.line_number += 1;
+            let line = self.line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            return Some(parse_candump(&self.line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {e}", self.line_number),
+                )
+            }));
+        }
+    }
+}
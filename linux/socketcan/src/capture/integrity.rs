@@ -0,0 +1,108 @@
+//! Loss tracking over a whole capture, as seen by the target and by the link.
+
+use super::reader::Event;
+use super::wire::{Metadata, StatsSnapshot};
+
+/// Beyond this many records, a sequence gap is too large for a real loss: the
+/// stream is more likely corrupted than truncated.
+pub const IMPLAUSIBLE_GAP: u32 = 2 * super::wire::CHANNEL_DEPTH;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Complete,
+    /// Losses on the target or on the link, or corrupted batches.
+    Incomplete,
+    /// No loss seen, but fewer than two snapshots to bound the window.
+    TooShort,
+}
+
+/// Running totals, fed with every [`Event`] of a capture.
+#[derive(Clone, Debug, Default)]
+pub struct Integrity {
+    pub headers: u32,
+    pub bitrate: Option<u32>,
+    /// Wire version of the last header.
+    pub version: Option<u8>,
+    /// Last seen, v2 only.
+    pub metadata: Option<Metadata>,
+    pub frames: u64,
+    pub link_gaps: u32,
+    pub link_lost: u64,
+    pub worst_gap: u32,
+    pub restarts: u32,
+    pub resyncs: u32,
+    pub noise_bytes: u64,
+    /// Resyncs caused by printable output rather than a truncated record.
+    pub noise_text: u32,
+    pub bad_stats: u32,
+    /// v2 batches dropped for a bad CRC or content.
+    pub bad_batches: u32,
+    /// First and last snapshot of the current target session.
+    pub first_stats: Option<StatsSnapshot>,
+    pub last_stats: Option<StatsSnapshot>,
+    session_snapshots: u32,
+}
+
+impl Integrity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(&mut self, event: &Event) {
+        match *event {
+            Event::Header(header) => {
+                self.headers += 1;
+                self.bitrate.get_or_insert(header.bitrate);
+                self.version = Some(header.version);
+            }
+            Event::Metadata(metadata) => self.metadata = Some(metadata),
+            Event::BadBatch(_) => self.bad_batches += 1,
+            Event::Frame(_) => self.frames += 1,
+            Event::Stats(stats) => {
+                // Target counters accumulate since its boot, usually well
+                // before the capture started. Only the delta describes the
+                // captured window.
+                self.first_stats.get_or_insert(stats);
+                self.last_stats = Some(stats);
+                self.session_snapshots += 1;
+            }
+            Event::LinkLoss(lost) => {
+                self.link_gaps += 1;
+                self.link_lost += lost as u64;
+                self.worst_gap = self.worst_gap.max(lost as u32);
+            }
+            Event::Restart => {
+                self.restarts += 1;
+                self.first_stats = None;
+                self.last_stats = None;
+                self.session_snapshots = 0;
+            }
+            Event::BadStats(_) => self.bad_stats += 1,
+            Event::Resync { skipped, text } => {
+                self.resyncs += 1;
+                self.noise_bytes += skipped as u64;
+                if text {
+                    self.noise_text += 1;
+                }
+            }
+        }
+    }
+
+    /// Counter increase over the captured window of the last session.
+    pub fn window(&self) -> Option<StatsSnapshot> {
+        Some(self.last_stats?.since(&self.first_stats?))
+    }
+
+    pub fn verdict(&self) -> Verdict {
+        // Losses come first: a broken stream must never be reported as merely
+        // too short to judge.
+        let target_lost = self.window().is_some_and(|w| w.lost() > 0);
+        if self.link_gaps > 0 || self.bad_batches > 0 || target_lost {
+            return Verdict::Incomplete;
+        }
+        if self.session_snapshots < 2 {
+            return Verdict::TooShort;
+        }
+        Verdict::Complete
+    }
+}
@@ -0,0 +1,18 @@
+//! Host side of the ESP32-S3 `sniffer` capture stream (KN2KCAP).
+//!
+//! ```text
+//! file / serial port ─► CaptureReader ─► Event ─┬─► caller
+//!                       (resync, sequence)      └─► Integrity (totals, verdict)
+//! ```
+//!
+//! [`CaptureWriter`] goes the other way, for captures recorded on the host.
+
+pub mod integrity;
+pub mod reader;
+pub mod wire;
+pub mod writer;
+
+pub use integrity::{Integrity, Verdict};
+pub use reader::{CaptureReader, Event};
+pub use wire::{Header, Metadata, StatsSnapshot, TimestampedFrame};
+pub use writer::CaptureWriter;
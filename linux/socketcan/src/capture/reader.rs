@@ -0,0 +1,317 @@
+//! Stream decoder: splits a capture into records, resyncing on `MAGIC`.
+//!
+//! Both wire versions are read, and a stream may switch from one to the other
+//! when the host asks: each v1 header and v2 batch starts with its own marker.
+//! A v2 batch whose CRC fails is dropped whole.
+//!
+//! Integrity is checked from two independent sources: the target counters
+//! (losses before USB) and the sequence numbers (losses on the USB link, which
+//! the target cannot see). The reader turns both into [`Event`]s; it does not
+//! keep totals, see [`Integrity`](super::Integrity) for that.
+
+use std::collections::VecDeque;
+use std::io::{self, Read};
+
+use super::wire::{
+    batch_size, decode_batch, decode_record, looks_valid, magic_version, BatchError, Header,
+    Metadata, Record, StatsSnapshot, TimestampedFrame, BATCH_PREFIX, MAGIC, RECORD_SIZE,
+};
+
+/// Past half the 16-bit space, a sequence jump is a step backwards.
+const SEQ_BACKWARDS: u16 = 0x8000;
+
+const READ_CHUNK: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Header(Header),
+    Frame(TimestampedFrame),
+    Stats(StatsSnapshot),
+    /// Who is capturing, with every v2 header.
+    Metadata(Metadata),
+    /// A v2 batch rejected whole. Its records are lost; the next batch shows
+    /// how many through a [`Event::LinkLoss`].
+    BadBatch(BatchError),
+    /// Records lost on the USB link, from a gap in the sequence numbers.
+    LinkLoss(u16),
+    /// The target restarted: counters and sequence numbers start over, so
+    /// neither can be compared across this event.
+    Restart,
+    /// Snapshot breaking the firmware invariants, discarded.
+    BadStats(StatsSnapshot),
+    /// Sync was lost and found again `skipped` bytes later. `text` flags
+    /// printable bytes: console or bootloader output mixed into the stream,
+    /// rather than a truncated record.
+    Resync {
+        skipped: usize,
+        text: bool,
+    },
+}
+
+/// Pulls records from any byte source: a file, a serial port, a pipe.
+pub struct CaptureReader<R> {
+    inner: R,
+    buffer: Vec<u8>,
+    pending: VecDeque<Event>,
+    synced: bool,
+    ever_synced: bool,
+    /// Wire version of the last marker.
+    version: u8,
+    /// A record or batch decoded since the last sync: until then, a bad
+    /// batch is more likely a false sync than a corrupted one.
+    confirmed: bool,
+    skipped: usize,
+    skipped_text: usize,
+    expected_seq: Option<u16>,
+    last_stats: Option<StatsSnapshot>,
+    bytes_read: u64,
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buffer: Vec::with_capacity(READ_CHUNK + RECORD_SIZE),
+            pending: VecDeque::new(),
+            synced: false,
+            ever_synced: false,
+            version: 1,
+            confirmed: false,
+            skipped: 0,
+            skipped_text: 0,
+            expected_seq: None,
+            last_stats: None,
+            bytes_read: 0,
+        }
+    }
+
+    /// `false` until a session header has been found.
+    pub fn has_synced(&self) -> bool {
+        self.ever_synced
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Feeds the buffer until at least one event is pending. `Ok(false)` at the
+    /// end of the stream.
+    fn fill(&mut self) -> io::Result<bool> {
+        while self.pending.is_empty() {
+            if self.step() {
+                continue;
+            }
+
+            let mut chunk = [0u8; READ_CHUNK];
+            let n = match self.inner.read(&mut chunk) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if n == 0 {
+                self.flush_resync();
+                return Ok(!self.pending.is_empty());
+            }
+            self.bytes_read += n as u64;
+            self.buffer.extend_from_slice(&chunk[..n]);
+        }
+        Ok(true)
+    }
+
+    /// Consumes what the buffer allows. `false` when more bytes are needed.
+    fn step(&mut self) -> bool {
+        if !self.synced {
+            return self.search_magic();
+        }
+        if let Some(version) = magic_version(&self.buffer) {
+            self.version = version;
+        }
+        if self.version == 1 {
+            self.step_record()
+        } else {
+            self.step_batch()
+        }
+    }
+
+    /// v1: one fixed-size record.
+    fn step_record(&mut self) -> bool {
+        if self.buffer.len() < RECORD_SIZE {
+            return false;
+        }
+
+        if !looks_valid(&self.buffer[..RECORD_SIZE]) {
+            self.lose_sync();
+            return true;
+        }
+
+        let mut raw = [0u8; RECORD_SIZE];
+        raw.copy_from_slice(&self.buffer[..RECORD_SIZE]);
+        self.buffer.drain(..RECORD_SIZE);
+
+        if let Some(record) = decode_record(&raw) {
+            self.confirmed = true;
+            self.handle(record);
+        }
+        true
+    }
+
+    /// v2: one whole batch.
+    fn step_batch(&mut self) -> bool {
+        if self.buffer.len() < BATCH_PREFIX {
+            return false;
+        }
+        let Some(size) = batch_size(&self.buffer) else {
+            self.lose_sync();
+            return true;
+        };
+        if self.buffer.len() < size {
+            return false;
+        }
+
+        match decode_batch(&self.buffer[..size]) {
+            Ok(records) => {
+                self.buffer.drain(..size);
+                self.confirmed = true;
+                for record in records {
+                    self.handle(record);
+                }
+            }
+            Err(error) => {
+                // The length may be as wrong as the rest: search the next
+                // marker rather than trusting it.
+                if self.confirmed {
+                    self.pending.push_back(Event::BadBatch(error));
+                }
+                self.lose_sync();
+            }
+        }
+        true
+    }
+
+    /// Search again, shifted by one byte.
+    fn lose_sync(&mut self) {
+        self.synced = false;
+        self.confirmed = false;
+        self.skip(1);
+    }
+
+    fn search_magic(&mut self) -> bool {
+        match self
+            .buffer
+            .windows(MAGIC.len())
+            .position(|window| magic_version(window).is_some())
+        {
+            Some(index) => {
+                self.skip(index);
+                self.synced = true;
+                self.ever_synced = true;
+                self.flush_resync();
+                true
+            }
+            None => {
+                // The tail may hold the start of a split marker.
+                let keep = MAGIC.len() - 1;
+                if self.buffer.len() > keep {
+                    self.skip(self.buffer.len() - keep);
+                }
+                false
+            }
+        }
+    }
+
+    fn skip(&mut self, count: usize) {
+        if self.ever_synced {
+            self.skipped += count;
+            self.skipped_text += self.buffer[..count]
+                .iter()
+                .filter(|&&b| (0x20..0x7F).contains(&b) || b == b'\n' || b == b'\r')
+                .count();
+        }
+        self.buffer.drain(..count);
+    }
+
+    fn flush_resync(&mut self) {
+        if self.skipped == 0 {
+            return;
+        }
+        self.pending.push_back(Event::Resync {
+            skipped: self.skipped,
+            text: self.skipped_text * 5 > self.skipped * 4,
+        });
+        self.skipped = 0;
+        self.skipped_text = 0;
+    }
+
+    fn handle(&mut self, record: Record) {
+        match record {
+            Record::Header(header) => self.pending.push_back(Event::Header(header)),
+            Record::Metadata(metadata) => self.pending.push_back(Event::Metadata(metadata)),
+            Record::Frame { seq, frame } => {
+                self.check_seq(seq);
+                self.pending.push_back(Event::Frame(frame));
+            }
+            Record::Stats { seq, stats } => {
+                if !stats.is_plausible() {
+                    self.check_seq(seq);
+                    self.pending.push_back(Event::BadStats(stats));
+                    return;
+                }
+
+                // A counter reset means the target restarted, not a corrupted
+                // record. Checked before the sequence, otherwise the restart
+                // shows up as a huge phantom gap.
+                let rebooted = self
+                    .last_stats
+                    .is_some_and(|last| stats.frames_rx < last.frames_rx);
+                if rebooted {
+                    self.restart();
+                    // The new session numbers from this record on.
+                    self.expected_seq = Some(seq.wrapping_add(1));
+                } else {
+                    self.check_seq(seq);
+                }
+
+                if let Some(last) = self.last_stats {
+                    if !stats.follows(&last) {
+                        self.pending.push_back(Event::BadStats(stats));
+                        return;
+                    }
+                }
+                self.last_stats = Some(stats);
+                self.pending.push_back(Event::Stats(stats));
+            }
+        }
+    }
+
+    fn check_seq(&mut self, seq: u16) {
+        if let Some(expected) = self.expected_seq {
+            let missing = seq.wrapping_sub(expected);
+            if missing > SEQ_BACKWARDS {
+                // The sequence moved backwards, which no loss can do: the
+                // target restarted and began numbering from zero again.
+                self.restart();
+            } else if missing > 0 {
+                self.pending.push_back(Event::LinkLoss(missing));
+            }
+        }
+        self.expected_seq = Some(seq.wrapping_add(1));
+    }
+
+    fn restart(&mut self) {
+        self.expected_seq = None;
+        self.last_stats = None;
+        self.pending.push_back(Event::Restart);
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.fill() {
+            Ok(true) => self.pending.pop_front().map(Ok),
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
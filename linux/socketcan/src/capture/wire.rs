@@ -0,0 +1,574 @@
+//! Host mirror of the firmware's `capture::wire`, little-endian, in two
+//! versions.
+//!
+//! ```text
+//! v1 record: fixed 24 bytes, MAGIC header re-sent with every snapshot
+//!
+//! v2 batch:  MAGIC_V2 | seq u16 | base_us u64 | len u16 | records[len] | crc u32
+//!                       └──────────── CRC-32 ────────────────────────┘
+//! header:    0x00 | version | bitrate u32                             6 bytes
+//! frame:     0x01 | len+flags | id u32 | delta_us u32 | data[len]    10 + len
+//! stats:     0x02 | 8 counters u32                                   33 bytes
+//! metadata:  0x03 | firmware[3] | chip_id[6] | mode | filter[9]      20 bytes
+//! ```
+//!
+//! v2 frame and stats records are numbered from the batch `seq`, and frames
+//! dated `base_us + delta_us`. The host picks the version by writing
+//! [`VERSION_REQUEST`] then a version byte, see [`encode_version_request`];
+//! the target speaks v1 until then.
+//!
+//! The firmware crate targets `xtensa-esp32s3-none-elf` and cannot be a
+//! dependency here, so the layout is duplicated. Any change on one side must be
+//! made on the other.
+
+use korri_n2k::protocol::transport::{can_frame::CanFrame, can_id::CanId};
+
+pub const RECORD_SIZE: usize = 24;
+
+/// Session marker, also the resync point when attaching mid-capture.
+pub const MAGIC: [u8; 8] = *b"KN2KCAP\x01";
+/// Starts every v2 batch.
+pub const MAGIC_V2: [u8; 8] = *b"KN2KCAP\x02";
+
+/// Host to target: the wire version wanted follows as one byte.
+pub const VERSION_REQUEST: [u8; 8] = *b"KN2KCAP?";
+/// Oldest and latest versions the firmware speaks.
+pub const VERSION_MIN: u8 = 1;
+pub const VERSION: u8 = 2;
+
+pub const RECORD_HEADER: u8 = 0x00;
+pub const RECORD_FRAME: u8 = 0x01;
+pub const RECORD_STATS: u8 = 0x02;
+pub const RECORD_METADATA: u8 = 0x03;
+
+/// `MAGIC_V2 | seq | base_us | len`, before the records of a v2 batch.
+pub const BATCH_PREFIX: usize = 20;
+pub const BATCH_CRC: usize = 4;
+pub const HEADER_V2_SIZE: usize = 6;
+pub const FRAME_V2_MAX: usize = 18;
+pub const STATS_V2_SIZE: usize = 33;
+pub const METADATA_SIZE: usize = 20;
+/// Well above what the firmware writes: past this, a v2 prefix is noise.
+pub const BATCH_MAX_RECORDS: usize = 4096;
+
+/// TWAI operating mode, as reported in the metadata.
+pub const MODE_LISTEN_ONLY: u8 = 0;
+pub const MODE_NORMAL: u8 = 1;
+pub const MODE_SELF_TEST: u8 = 2;
+
+/// Extended frame (29-bit id). Absent means standard frame (11-bit id).
+pub const FLAG_EXTENDED: u8 = 0b0000_0001;
+/// Remote Transmission Request: the frame carries no data.
+pub const FLAG_REMOTE: u8 = 0b0000_0010;
+
+/// Depth of the firmware capture channel, bound for `max_channel_depth`.
+pub const CHANNEL_DEPTH: u32 = 1024;
+
+/// Longest backlog run esp-hal can drain before silently dropping frames.
+pub const BACKLOG_LIMIT: u32 = 32;
+
+/// Session header, re-sent by the target with every counter snapshot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// Wire version the target speaks.
+    pub version: u8,
+    /// Bus bitrate, bit/s.
+    pub bitrate: u32,
+}
+
+/// Acceptance filter as the controller applies it: a frame passes when
+/// `frame id & mask == id & mask`, so a zero mask accepts everything.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    pub id: u32,
+    pub mask: u32,
+    /// [`FLAG_EXTENDED`] when the filter matches 29-bit ids only.
+    pub flags: u8,
+}
+
+impl Filter {
+    pub fn accepts_all(&self) -> bool {
+        self.mask == 0
+    }
+}
+
+/// Who is capturing, and how. Sent with every v2 header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    /// major, minor, patch.
+    pub firmware: [u8; 3],
+    /// Factory MAC address, unique per chip.
+    pub chip_id: [u8; 6],
+    /// One of the `MODE_*` values.
+    pub mode: u8,
+    pub filter: Filter,
+}
+
+impl Metadata {
+    pub fn mode_name(&self) -> &'static str {
+        match self.mode {
+            MODE_LISTEN_ONLY => "listen-only",
+            MODE_NORMAL => "normal",
+            MODE_SELF_TEST => "self-test",
+            _ => "unknown mode",
+        }
+    }
+}
+
+/// A CAN frame as timestamped by the target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimestampedFrame {
+    /// Microseconds since the target booted.
+    pub timestamp_us: u64,
+    /// Right-aligned CAN identifier (29 or 11 bits, see `flags`).
+    pub id: u32,
+    pub data: [u8; 8],
+    pub len: u8,
+    pub flags: u8,
+}
+
+impl TimestampedFrame {
+    /// An N2K frame, always extended.
+    pub fn from_can_frame(frame: &CanFrame, timestamp_us: u64) -> Self {
+        Self {
+            timestamp_us,
+            id: frame.id.0,
+            data: frame.data,
+            len: frame.len.min(8) as u8,
+            flags: FLAG_EXTENDED,
+        }
+    }
+
+    pub fn is_extended(&self) -> bool {
+        self.flags & FLAG_EXTENDED != 0
+    }
+
+    pub fn is_remote(&self) -> bool {
+        self.flags & FLAG_REMOTE != 0
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.data[..(self.len as usize).min(8)]
+    }
+
+    /// The frame as korri-n2k sees it, or `None` for a standard or remote
+    /// frame: an 11-bit id holds no PGN, and an RTR frame carries no data.
+    pub fn to_can_frame(&self) -> Option<CanFrame> {
+        if !self.is_extended() || self.is_remote() {
+            return None;
+        }
+        Some(CanFrame {
+            id: CanId(self.id),
+            data: self.data,
+            len: (self.len as usize).min(8),
+        })
+    }
+}
+
+/// Target counters, cumulative since its boot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    pub frames_rx: u32,
+    pub channel_drops: u32,
+    pub hw_overruns: u32,
+    pub bus_off: u32,
+    pub soft_errors: u32,
+    pub sink_drops: u32,
+    pub max_channel_depth: u32,
+    pub max_backlog_run: u32,
+}
+
+impl StatsSnapshot {
+    pub fn is_lossless(&self) -> bool {
+        self.channel_drops == 0 && self.hw_overruns == 0 && self.sink_drops == 0
+    }
+
+    /// Frames lost on the target, before they reached USB.
+    pub fn lost(&self) -> u32 {
+        self.channel_drops + self.hw_overruns + self.sink_drops
+    }
+
+    /// Firmware invariants, broken by a corrupted record.
+    pub fn is_plausible(&self) -> bool {
+        self.max_channel_depth <= CHANNEL_DEPTH && self.channel_drops <= self.frames_rx
+    }
+
+    /// `true` if no counter or peak went backwards since `earlier`. The target
+    /// never decreases them, so anything else is a corrupted record.
+    pub fn follows(&self, earlier: &StatsSnapshot) -> bool {
+        self.frames_rx >= earlier.frames_rx
+            && self.channel_drops >= earlier.channel_drops
+            && self.hw_overruns >= earlier.hw_overruns
+            && self.soft_errors >= earlier.soft_errors
+            && self.sink_drops >= earlier.sink_drops
+            && self.bus_off >= earlier.bus_off
+            && self.max_channel_depth >= earlier.max_channel_depth
+            && self.max_backlog_run >= earlier.max_backlog_run
+    }
+
+    /// Counter increase since `earlier`. Peaks are not deltas and are kept as is.
+    pub fn since(&self, earlier: &StatsSnapshot) -> StatsSnapshot {
+        StatsSnapshot {
+            frames_rx: self.frames_rx.wrapping_sub(earlier.frames_rx),
+            channel_drops: self.channel_drops.wrapping_sub(earlier.channel_drops),
+            hw_overruns: self.hw_overruns.wrapping_sub(earlier.hw_overruns),
+            bus_off: self.bus_off.wrapping_sub(earlier.bus_off),
+            soft_errors: self.soft_errors.wrapping_sub(earlier.soft_errors),
+            sink_drops: self.sink_drops.wrapping_sub(earlier.sink_drops),
+            max_channel_depth: self.max_channel_depth,
+            max_backlog_run: self.max_backlog_run,
+        }
+    }
+}
+
+/// One decoded record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Record {
+    Header(Header),
+    Frame { seq: u16, frame: TimestampedFrame },
+    Stats { seq: u16, stats: StatsSnapshot },
+    Metadata(Metadata),
+}
+
+/// Why a v2 batch was discarded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BatchError {
+    Crc,
+    /// CRC right, content not: a record of unknown type or running past the
+    /// batch.
+    BadRecord,
+}
+
+/// The version to speak when the host asks for `requested`: the closest one
+/// the firmware knows.
+pub fn negotiate(requested: u8) -> u8 {
+    requested.clamp(VERSION_MIN, VERSION)
+}
+
+/// What the host writes to the target's port to ask for `version`.
+pub fn encode_version_request(version: u8) -> [u8; 9] {
+    let mut out = [0u8; 9];
+    out[..8].copy_from_slice(&VERSION_REQUEST);
+    out[8] = version;
+    out
+}
+
+/// The version a marker at the start of `bytes` announces, v1 header or v2
+/// batch.
+pub fn magic_version(bytes: &[u8]) -> Option<u8> {
+    match bytes.get(..MAGIC.len())? {
+        marker if marker == MAGIC => Some(1),
+        marker if marker == MAGIC_V2 => Some(2),
+        _ => None,
+    }
+}
+
+/// Session header for the version negotiated from `requested`, written at the
+/// start of `out`. Returns that version and the bytes written.
+///
+/// v1: `MAGIC` | bus bitrate | record size, a 24-byte record of its own.
+/// v2: a record inside a batch, the batch prefix holding the marker.
+pub fn encode_header(requested: u8, bitrate: u32, out: &mut [u8]) -> (u8, usize) {
+    let version = negotiate(requested);
+    if version == 1 {
+        out[0..8].copy_from_slice(&MAGIC);
+        out[8..12].copy_from_slice(&bitrate.to_le_bytes());
+        out[12..16].copy_from_slice(&(RECORD_SIZE as u32).to_le_bytes());
+        out[16..RECORD_SIZE].fill(0);
+        return (version, RECORD_SIZE);
+    }
+    out[0] = RECORD_HEADER;
+    out[1] = version;
+    out[2..6].copy_from_slice(&bitrate.to_le_bytes());
+    (version, HEADER_V2_SIZE)
+}
+
+/// v1 | 0 type | 1 len+flags | 2 sequence | 4 id | 8 timestamp_us | 16 data |
+pub fn encode_frame(frame: &TimestampedFrame, seq: u16) -> [u8; RECORD_SIZE] {
+    let mut out = [0u8; RECORD_SIZE];
+    out[0] = RECORD_FRAME;
+    out[1] = (frame.len & 0x0F) | (frame.flags << 4);
+    out[2..4].copy_from_slice(&seq.to_le_bytes());
+    out[4..8].copy_from_slice(&frame.id.to_le_bytes());
+    out[8..16].copy_from_slice(&frame.timestamp_us.to_le_bytes());
+    out[16..24].copy_from_slice(&frame.data);
+    out
+}
+
+/// v1 counter snapshot, small counters saturated as the target does.
+pub fn encode_stats(stats: &StatsSnapshot, seq: u16) -> [u8; RECORD_SIZE] {
+    let mut out = [0u8; RECORD_SIZE];
+    out[0] = RECORD_STATS;
+    out[2..4].copy_from_slice(&seq.to_le_bytes());
+    out[4..8].copy_from_slice(&stats.frames_rx.to_le_bytes());
+    out[8..12].copy_from_slice(&stats.channel_drops.to_le_bytes());
+    out[12..16].copy_from_slice(&stats.hw_overruns.to_le_bytes());
+    out[16..18].copy_from_slice(&(stats.soft_errors.min(u16::MAX as u32) as u16).to_le_bytes());
+    out[18..20].copy_from_slice(&(stats.sink_drops.min(u16::MAX as u32) as u16).to_le_bytes());
+    out[20..22]
+        .copy_from_slice(&(stats.max_channel_depth.min(u16::MAX as u32) as u16).to_le_bytes());
+    out[22] = stats.max_backlog_run.min(u8::MAX as u32) as u8;
+    out[23] = stats.bus_off.min(u8::MAX as u32) as u8;
+    out
+}
+
+/// Opens a v2 batch whose first frame or stats record numbers `seq`. The
+/// length and CRC are filled in by [`seal_batch`].
+pub fn encode_batch_start(seq: u16, base_us: u64, out: &mut [u8]) -> usize {
+    out[0..8].copy_from_slice(&MAGIC_V2);
+    out[8..10].copy_from_slice(&seq.to_le_bytes());
+    out[10..18].copy_from_slice(&base_us.to_le_bytes());
+    out[18..20].fill(0);
+    BATCH_PREFIX
+}
+
+/// v2 frame, only `len` data bytes, timestamp relative to the batch.
+pub fn encode_compact_frame(frame: &TimestampedFrame, base_us: u64, out: &mut [u8]) -> usize {
+    let len = (frame.len as usize).min(8);
+    let delta = frame
+        .timestamp_us
+        .saturating_sub(base_us)
+        .min(u32::MAX as u64) as u32;
+    out[0] = RECORD_FRAME;
+    out[1] = (frame.len & 0x0F) | (frame.flags << 4);
+    out[2..6].copy_from_slice(&frame.id.to_le_bytes());
+    out[6..10].copy_from_slice(&delta.to_le_bytes());
+    out[10..10 + len].copy_from_slice(&frame.data[..len]);
+    10 + len
+}
+
+/// v2 counter snapshot, every counter in full.
+pub fn encode_full_stats(stats: &StatsSnapshot, out: &mut [u8]) -> usize {
+    out[0] = RECORD_STATS;
+    for (i, counter) in stats_counters(stats).iter().enumerate() {
+        out[1 + 4 * i..5 + 4 * i].copy_from_slice(&counter.to_le_bytes());
+    }
+    STATS_V2_SIZE
+}
+
+/// | 0 type | 1 firmware | 4 chip_id | 10 mode | 11 filter id | 15 mask | 19 flags |
+pub fn encode_metadata(metadata: &Metadata, out: &mut [u8]) -> usize {
+    out[0] = RECORD_METADATA;
+    out[1..4].copy_from_slice(&metadata.firmware);
+    out[4..10].copy_from_slice(&metadata.chip_id);
+    out[10] = metadata.mode;
+    out[11..15].copy_from_slice(&metadata.filter.id.to_le_bytes());
+    out[15..19].copy_from_slice(&metadata.filter.mask.to_le_bytes());
+    out[19] = metadata.filter.flags;
+    METADATA_SIZE
+}
+
+/// Closes the v2 batch held in `out[..end]`: records length, then the CRC of
+/// everything after the marker. Returns the batch size.
+pub fn seal_batch(out: &mut [u8], end: usize) -> usize {
+    let len = (end - BATCH_PREFIX) as u16;
+    out[18..20].copy_from_slice(&len.to_le_bytes());
+    let crc = crc32(&out[MAGIC_V2.len()..end]);
+    out[end..end + BATCH_CRC].copy_from_slice(&crc.to_le_bytes());
+    end + BATCH_CRC
+}
+
+/// CRC-32/ISO-HDLC, the zlib and Ethernet one.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+static CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// In the order of the v2 stats record.
+fn stats_counters(stats: &StatsSnapshot) -> [u32; 8] {
+    [
+        stats.frames_rx,
+        stats.channel_drops,
+        stats.hw_overruns,
+        stats.bus_off,
+        stats.soft_errors,
+        stats.sink_drops,
+        stats.max_channel_depth,
+        stats.max_backlog_run,
+    ]
+}
+
+/// Total size of the v2 batch `prefix` starts, once at least
+/// [`BATCH_PREFIX`] bytes are in. `None` when it cannot be one.
+pub fn batch_size(prefix: &[u8]) -> Option<usize> {
+    if prefix.len() < BATCH_PREFIX || magic_version(prefix) != Some(2) {
+        return None;
+    }
+    let len = u16_at(prefix, 18) as usize;
+    (len <= BATCH_MAX_RECORDS).then_some(BATCH_PREFIX + len + BATCH_CRC)
+}
+
+/// Records of a whole v2 batch, `batch_size` bytes, frames and stats numbered
+/// and dated.
+pub fn decode_batch(batch: &[u8]) -> Result<Vec<Record>, BatchError> {
+    let end = batch.len() - BATCH_CRC;
+    if crc32(&batch[MAGIC_V2.len()..end]) != u32_at(batch, end) {
+        return Err(BatchError::Crc);
+    }
+
+    let mut seq = u16_at(batch, 8);
+    let mut next_seq = || {
+        let current = seq;
+        seq = seq.wrapping_add(1);
+        current
+    };
+    let base_us = u64::from_le_bytes(batch[10..18].try_into().unwrap());
+    let mut records = Vec::new();
+    let mut rest = &batch[BATCH_PREFIX..end];
+    while let Some(&kind) = rest.first() {
+        let size = match kind {
+            RECORD_HEADER => HEADER_V2_SIZE,
+            RECORD_FRAME if rest.len() >= 2 && rest[1] & 0x0F <= 8 => {
+                10 + (rest[1] & 0x0F) as usize
+            }
+            RECORD_STATS => STATS_V2_SIZE,
+            RECORD_METADATA => METADATA_SIZE,
+            _ => return Err(BatchError::BadRecord),
+        };
+        if rest.len() < size {
+            return Err(BatchError::BadRecord);
+        }
+        let record = &rest[..size];
+        rest = &rest[size..];
+
+        records.push(match kind {
+            RECORD_HEADER => Record::Header(Header {
+                version: record[1],
+                bitrate: u32_at(record, 2),
+            }),
+            RECORD_FRAME => {
+                let len = record[1] & 0x0F;
+                let mut data = [0u8; 8];
+                data[..len as usize].copy_from_slice(&record[10..]);
+                let frame = TimestampedFrame {
+                    timestamp_us: base_us + u32_at(record, 6) as u64,
+                    id: u32_at(record, 2),
+                    data,
+                    len,
+                    flags: record[1] >> 4,
+                };
+                Record::Frame {
+                    seq: next_seq(),
+                    frame,
+                }
+            }
+            RECORD_STATS => {
+                let counter = |i: usize| u32_at(record, 1 + 4 * i);
+                let stats = StatsSnapshot {
+                    frames_rx: counter(0),
+                    channel_drops: counter(1),
+                    hw_overruns: counter(2),
+                    bus_off: counter(3),
+                    soft_errors: counter(4),
+                    sink_drops: counter(5),
+                    max_channel_depth: counter(6),
+                    max_backlog_run: counter(7),
+                };
+                Record::Stats {
+                    seq: next_seq(),
+                    stats,
+                }
+            }
+            _ => Record::Metadata(Metadata {
+                firmware: [record[1], record[2], record[3]],
+                chip_id: record[4..10].try_into().unwrap(),
+                mode: record[10],
+                filter: Filter {
+                    id: u32_at(record, 11),
+                    mask: u32_at(record, 15),
+                    flags: record[19],
+                },
+            }),
+        });
+    }
+    Ok(records)
+}
+
+/// Guard against a false sync: `MAGIC` can appear inside frame data.
+pub fn looks_valid(record: &[u8]) -> bool {
+    if record.starts_with(&MAGIC) {
+        return true;
+    }
+    match record[0] {
+        RECORD_STATS => true,
+        RECORD_FRAME => record[1] & 0x0F <= 8,
+        _ => false,
+    }
+}
+
+pub fn decode_record(record: &[u8; RECORD_SIZE]) -> Option<Record> {
+    if record.starts_with(&MAGIC) {
+        return Some(Record::Header(Header {
+            version: 1,
+            bitrate: u32_at(record, 8),
+        }));
+    }
+
+    let seq = u16_at(record, 2);
+    match record[0] {
+        RECORD_FRAME => {
+            let len = record[1] & 0x0F;
+            if len > 8 {
+                return None;
+            }
+            let mut data = [0u8; 8];
+            data.copy_from_slice(&record[16..24]);
+            Some(Record::Frame {
+                seq,
+                frame: TimestampedFrame {
+                    timestamp_us: u64::from_le_bytes(record[8..16].try_into().unwrap()),
+                    id: u32_at(record, 4),
+                    data,
+                    len,
+                    flags: record[1] >> 4,
+                },
+            })
+        }
+        // Small counters were saturated into shorter integers by the target.
+        RECORD_STATS => Some(Record::Stats {
+            seq,
+            stats: StatsSnapshot {
+                frames_rx: u32_at(record, 4),
+                channel_drops: u32_at(record, 8),
+                hw_overruns: u32_at(record, 12),
+                soft_errors: u16_at(record, 16) as u32,
+                sink_drops: u16_at(record, 18) as u32,
+                max_channel_depth: u16_at(record, 20) as u32,
+                max_backlog_run: record[22] as u32,
+                bus_off: record[23] as u32,
+            },
+        }),
+        _ => None,
+    }
+}
+
+fn u16_at(record: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([record[at], record[at + 1]])
+}
+
+fn u32_at(record: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(record[at..at + 4].try_into().unwrap())
+}
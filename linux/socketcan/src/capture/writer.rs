@@ -0,0 +1,94 @@
+//! KN2KCAP v2 written on the host, for captures taken from a SocketCAN bus
+//! rather than the sniffer: the same batches, so `kn2kcap` and the replay read
+//! both alike.
+//!
+//! Every batch starts with a header record, as the target re-sends its own,
+//! so a reader attached mid-file knows the bitrate from the next batch on.
+//! There are no stats records: the host sees no controller counters.
+
+use std::io::{self, Write};
+
+use super::wire::{
+    encode_batch_start, encode_compact_frame, encode_header, seal_batch, TimestampedFrame,
+    BATCH_CRC, BATCH_PREFIX, FRAME_V2_MAX, HEADER_V2_SIZE,
+};
+
+/// Records per batch, in bytes: under `BATCH_MAX_RECORDS`, which readers take
+/// for noise.
+const BATCH_RECORDS: usize = 4000;
+
+/// Buffers frames into v2 batches. A batch goes out when full, or on
+/// [`CaptureWriter::flush`].
+pub struct CaptureWriter<W: Write> {
+    inner: W,
+    bitrate: u32,
+    batch: Vec<u8>,
+    /// Timestamp frames in the open batch are relative to.
+    base_us: u64,
+    /// Frames in the open batch.
+    frames: usize,
+    /// Number of the next frame.
+    seq: u16,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// `bitrate` in bit/s, 0 when unknown.
+    pub fn new(inner: W, bitrate: u32) -> Self {
+        Self {
+            inner,
+            bitrate,
+            batch: Vec::with_capacity(BATCH_PREFIX + BATCH_RECORDS + BATCH_CRC),
+            base_us: 0,
+            frames: 0,
+            seq: 0,
+        }
+    }
+
+    pub fn write_frame(&mut self, frame: &TimestampedFrame) -> io::Result<()> {
+        let delta_us = frame.timestamp_us.checked_sub(self.base_us);
+        let fits = self.batch.len() + FRAME_V2_MAX <= BATCH_PREFIX + BATCH_RECORDS;
+        if self.frames > 0 && !(fits && delta_us.is_some_and(|d| d <= u32::MAX as u64)) {
+            self.write_batch()?;
+        }
+        if self.frames == 0 {
+            self.start_batch(frame.timestamp_us);
+        }
+        let mut record = [0u8; FRAME_V2_MAX];
+        let len = encode_compact_frame(frame, self.base_us, &mut record);
+        self.batch.extend_from_slice(&record[..len]);
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Writes out the open batch, if any, then flushes the inner writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.frames > 0 {
+            self.write_batch()?;
+        }
+        self.inner.flush()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    fn start_batch(&mut self, base_us: u64) {
+        let mut prefix = [0u8; BATCH_PREFIX + HEADER_V2_SIZE];
+        let start = encode_batch_start(self.seq, base_us, &mut prefix);
+        let (_, header) = encode_header(2, self.bitrate, &mut prefix[start..]);
+        self.batch.clear();
+        self.batch.extend_from_slice(&prefix[..start + header]);
+        self.base_us = base_us;
+    }
+
+    fn write_batch(&mut self) -> io::Result<()> {
+        let end = self.batch.len();
+        self.batch.resize(end + BATCH_CRC, 0);
+        let size = seal_batch(&mut self.batch, end);
+        self.seq = self.seq.wrapping_add(self.frames as u16);
+        self.frames = 0;
+        // The batch is gone either way: a partial write leaves a truncated
+        // batch, which readers drop.
+        self.inner.write_all(&self.batch[..size])
+    }
+}
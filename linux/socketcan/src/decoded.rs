@@ -0,0 +1,207 @@
+//! Human-readable messages, decoded with korri-n2k, for watching a bus live.
+//!
+//! ```text
+//! 10:00:00.123 127488 Engine Parameters, Rapid Update  src 35 Garmin (NAME 0xC09691011CBABCDF)  dst 255  prio 2
+//!     Instance: SingleEngineOrDualEnginePort (0)
+//!     Speed: 1500.00 rpm
+//!     Boost Pressure: unavailable
+//! ```
+//!
+//! Sources are named from the ISO Address Claims seen so far: their NAME and
+//! manufacturer. Fields show their units, angles in degrees and temperatures
+//! in Celsius, lookups their korri-n2k enum name and raw value.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fmt::Write;
+
+use chrono::{DateTime, Local};
+use korri_n2k::core::{FieldDescriptor, FieldKind, PgnDescriptor, PgnValue};
+use korri_n2k::infra::codec::traits::FieldAccess;
+use korri_n2k::protocol::managment::iso_name::IsoName;
+
+use crate::analyzer::{date, hex, number, text, time, unavailable, Number};
+use crate::messages::Message;
+use crate::pgn::{
+    decode, descriptor, lookup_name, pgn_name, ADDRESS_CLAIM_PGN, BROADCAST, NULL_ADDRESS,
+};
+
+/// Formats messages, learning the sources' NAMEs as claims go by.
+#[derive(Default)]
+pub struct Decoder {
+    names: HashMap<u8, u64>,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// ISO NAME of the device at `address`, once it has claimed it.
+    pub fn name(&self, address: u8) -> Option<u64> {
+        self.names.get(&address).copied()
+    }
+
+    /// `message` as a header line, then one line per field.
+    pub fn format(&mut self, message: &Message, time: DateTime<Local>) -> String {
+        self.learn(message);
+
+        let descriptor = descriptor(message.pgn);
+        let description = descriptor.map_or(pgn_name(message.pgn), |d| d.description);
+        let mut out = format!(
+            "{} {:>6} {description}  src {}",
+            time.format("%H:%M:%S%.3f"),
+            message.pgn,
+            self.describe(message.source)
+        );
+        if message.destination != BROADCAST {
+            let _ = write!(out, "  dst {}", self.describe(message.destination));
+        } else {
+            out.push_str("  dst 255");
+        }
+        let _ = writeln!(out, "  prio {}", message.priority);
+
+        match (descriptor, decode(message.pgn, &message.payload)) {
+            (Some(descriptor), Some(Ok(decoded))) => fields(&mut out, descriptor, &*decoded),
+            (_, Some(Err(e))) => {
+                let _ = writeln!(out, "    korri-n2k: {e:?}");
+                let _ = writeln!(out, "    data: {}", hex(&message.payload));
+            }
+            _ => {
+                let _ = writeln!(out, "    data: {}", hex(&message.payload));
+            }
+        }
+        out
+    }
+
+    fn learn(&mut self, message: &Message) {
+        if message.pgn != ADDRESS_CLAIM_PGN || message.source == NULL_ADDRESS {
+            return;
+        }
+        let Some(name) = message.payload.get(..8).and_then(|b| b.try_into().ok()) else {
+            return;
+        };
+        let name = u64::from_le_bytes(name);
+        // A device that moved no longer answers at its old address.
+        self.names.retain(|_, known| *known != name);
+        self.names.insert(message.source, name);
+    }
+
+    /// `35 Garmin (NAME 0x...)`, or the bare address before any claim.
+    fn describe(&self, address: u8) -> String {
+        let Some(name) = self.name(address) else {
+            return address.to_string();
+        };
+        let code = IsoName::from_raw(name).manufacturer_code();
+        let manufacturer =
+            lookup_name("ManufacturerCode", code as u64).unwrap_or_else(|| format!("mfr {code}"));
+        format!("{address} {manufacturer} (NAME 0x{name:016X})")
+    }
+}
+
+/// Lines for every field, repeating groups numbered from 1.
+fn fields(out: &mut String, descriptor: &PgnDescriptor, decoded: &dyn FieldAccess) {
+    let all = descriptor.fields;
+    let mut index = 0;
+    while index < all.len() {
+        let set = descriptor
+            .repeating_field_sets
+            .iter()
+            .find(|set| set.start_field_index == index);
+        if let Some(set) = set {
+            let group = &all[index..index + set.size];
+            let count = decoded.repetitive_count(set.array_id).unwrap_or(0);
+            for i in 0..count {
+                let _ = writeln!(out, "    {} {}:", set.array_id, i + 1);
+                for field in group {
+                    let value = decoded.repetitive_field(set.array_id, i, field.id);
+                    line(out, "        ", field, value);
+                }
+            }
+            index += set.size;
+        } else {
+            let field = &all[index];
+            line(out, "    ", field, decoded.field(field.id));
+            index += 1;
+        }
+    }
+}
+
+fn line(out: &mut String, indent: &str, field: &FieldDescriptor, value: Option<PgnValue>) {
+    if matches!(field.kind, FieldKind::Reserved | FieldKind::Spare) {
+        return;
+    }
+    if let Some(text) = value.and_then(|value| field_text(field, value)) {
+        let _ = writeln!(out, "{indent}{}: {text}", field.name);
+    }
+}
+
+fn field_text(field: &FieldDescriptor, value: PgnValue) -> Option<String> {
+    if let PgnValue::Bytes(bytes) = value {
+        let bytes = &bytes.data[..bytes.len];
+        return Some(match field.kind {
+            FieldKind::StringFix
+            | FieldKind::StringLz
+            | FieldKind::StringLau
+            | FieldKind::Decimal => {
+                format!("\"{}\"", text(bytes))
+            }
+            _ => hex(bytes),
+        });
+    }
+    let value = number(value)?;
+    if unavailable(field, &value) {
+        return Some("unavailable".to_string());
+    }
+
+    Some(match (&field.kind, value) {
+        (FieldKind::Lookup, Number::Unsigned(raw)) => {
+            match field
+                .enum_direct_name
+                .and_then(|lookup| lookup_name(lookup, raw))
+            {
+                Some(name) => format!("{name} ({raw})"),
+                None => raw.to_string(),
+            }
+        }
+        (FieldKind::IsoName, Number::Unsigned(raw)) => format!("0x{raw:016X}"),
+        (FieldKind::Pgn, Number::Unsigned(raw)) => {
+            format!("{raw} {}", pgn_name(raw as u32))
+        }
+        (FieldKind::Date, value) => date(value.as_f64())?,
+        (FieldKind::Time, value) => time(value.as_f64()),
+        (_, value) => quantity(field, value),
+    })
+}
+
+/// A number with its unit, angles in degrees and temperatures in Celsius, as
+/// many decimals as the resolution calls for.
+fn quantity(field: &FieldDescriptor, value: Number) -> String {
+    let resolution = field.resolution.unwrap_or(1.0) as f64;
+    let (value, scale, unit) = match (field.physical_unit, value) {
+        (Some("rad"), value) => (value.as_f64() * 180.0 / PI, 180.0 / PI, "deg"),
+        (Some("rad/s"), value) => (value.as_f64() * 180.0 / PI, 180.0 / PI, "deg/s"),
+        (Some("K"), value) => (value.as_f64() - 273.15, 1.0, "°C"),
+        (unit, Number::Unsigned(v)) if resolution == 1.0 => {
+            return with_unit(v.to_string(), unit);
+        }
+        (unit, Number::Signed(v)) if resolution == 1.0 => {
+            return with_unit(v.to_string(), unit);
+        }
+        (unit, value) => (value.as_f64(), 1.0, unit.unwrap_or_default()),
+    };
+    let step = resolution * scale;
+    let decimals = if step >= 1.0 {
+        0
+    } else {
+        ((-step.log10() - 1e-9).ceil() as usize).min(9)
+    };
+    with_unit(format!("{value:.decimals$}"), Some(unit))
+}
+
+fn with_unit(value: String, unit: Option<&str>) -> String {
+    match unit {
+        Some(unit) if !unit.is_empty() => format!("{value} {unit}"),
+        _ => value,
+    }
+}
@@ -0,0 +1,141 @@
+//! Actisense EBL logs on the host, on top of `shared_core::format::ebl`.
+//!
+//! Frames are carried as [`TimestampedFrame`], like KN2KCAP and candump, so
+//! an EBL log goes through the same conversions. Read timestamps are Unix
+//! time once the log has given a time record.
+
+use std::io::{self, Read, Write};
+
+use shared_core::format::{
+    encode_ebl_frame, encode_ebl_time, EblCounters, EblDeframer, EblRecord, EBL_MAX_RECORD,
+};
+
+use crate::capture::wire::TimestampedFrame;
+
+const READ_CHUNK: usize = 4096;
+
+/// Past this gap between two frames, the writer restates the time: the
+/// 16-bit millisecond counter wraps after 65.5 s.
+const TIME_RECORD_GAP_US: u64 = 60_000_000;
+
+/// Pulls frames from an EBL log. Undecodable records are skipped and counted,
+/// see [`EblReader::counters`].
+pub struct EblReader<R> {
+    inner: R,
+    deframer: EblDeframer,
+    buffer: Vec<u8>,
+    pos: usize,
+    bytes_read: u64,
+}
+
+impl<R: Read> EblReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            deframer: EblDeframer::new(),
+            buffer: Vec::with_capacity(READ_CHUNK),
+            pos: 0,
+            bytes_read: 0,
+        }
+    }
+
+    pub fn counters(&self) -> EblCounters {
+        self.deframer.counters()
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+}
+
+impl<R: Read> Iterator for EblReader<R> {
+    type Item = io::Result<TimestampedFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while self.pos < self.buffer.len() {
+                let byte = self.buffer[self.pos];
+                self.pos += 1;
+                if let Some(Ok(EblRecord::Frame {
+                    timestamp_us,
+                    frame,
+                })) = self.deframer.push(byte)
+                {
+                    return Some(Ok(TimestampedFrame::from_can_frame(&frame, timestamp_us)));
+                }
+            }
+
+            self.buffer.resize(READ_CHUNK, 0);
+            self.pos = 0;
+            match self.inner.read(&mut self.buffer) {
+                Ok(0) => {
+                    self.buffer.clear();
+                    return None;
+                }
+                Ok(n) => {
+                    self.buffer.truncate(n);
+                    self.bytes_read += n as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => self.buffer.clear(),
+                Err(e) => {
+                    self.buffer.clear();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Writes frames as an EBL log, a time record first and after every long
+/// silence. `timestamp_us` is taken as Unix time: a converted KN2KCAP capture
+/// comes out dated from 1970, as its timestamps are target uptime.
+pub struct EblWriter<W: Write> {
+    inner: W,
+    /// Time of the last time record, and of the last frame.
+    base_us: Option<u64>,
+    last_us: u64,
+}
+
+impl<W: Write> EblWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            base_us: None,
+            last_us: 0,
+        }
+    }
+
+    /// Writes `frame`. Standard and remote frames are skipped: EBL logs
+    /// N2K traffic only.
+    pub fn write_frame(&mut self, frame: &TimestampedFrame) -> io::Result<()> {
+        let Some(can) = frame.to_can_frame() else {
+            return Ok(());
+        };
+        let mut buffer = [0u8; EBL_MAX_RECORD];
+
+        let timestamp_us = frame.timestamp_us;
+        let base_us = match self.base_us {
+            Some(base_us)
+                if timestamp_us >= self.last_us
+                    && timestamp_us - self.last_us < TIME_RECORD_GAP_US =>
+            {
+                base_us
+            }
+            _ => {
+                let len = encode_ebl_time(timestamp_us, &mut buffer);
+                self.inner.write_all(&buffer[..len])?;
+                self.base_us = Some(timestamp_us);
+                timestamp_us
+            }
+        };
+        self.last_us = timestamp_us;
+
+        let time_ms = ((timestamp_us - base_us) / 1000) as u16;
+        let len = encode_ebl_frame(&can, time_ms, &mut buffer);
+        self.inner.write_all(&buffer[..len])
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,224 @@
+//! Fast-packet reassembly for the host, keyed by source, PGN and sequence
+//! counter.
+//!
+//! ```text
+//! frame 0: seq << 5 | 0 | len | data[6]
+//! frame n: seq << 5 | n | data[7]
+//! ```
+//!
+//! korri-n2k's `FastPacketAssembler` is sized for firmware: four sessions,
+//! one per source and PGN, frames in order. A host sees a whole bus, where
+//! senders interleave messages, and gateways or captures may reorder frames.
+//! Here sessions are unbounded, each frame lands at the offset of its counter
+//! whatever the order, and a message completes once all its frames are in.
+//!
+//! [`split_message`] goes the other way, for messages put back on a bus.
+
+use std::collections::HashMap;
+
+use korri_n2k::protocol::transport::{
+    can_frame::CanFrame, can_id::CanId, fast_packet::MAX_FAST_PACKET_PAYLOAD,
+};
+
+use crate::pgn::is_fast_packet;
+
+/// A session with no frame for this long is dropped: frames were lost.
+pub const FAST_PACKET_TIMEOUT_US: u64 = 750_000;
+
+/// Reassembly totals since creation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FastPacketCounters {
+    pub messages: u64,
+    /// Frames received after a later frame of the same message.
+    pub out_of_order: u64,
+    /// Frames received twice, ignored.
+    pub duplicates: u64,
+    /// Sessions dropped after [`FAST_PACKET_TIMEOUT_US`] of silence.
+    pub expired: u64,
+    /// Sessions abandoned for a new first frame with the same sequence
+    /// counter.
+    pub restarted: u64,
+    /// Frames that cannot belong to a message: length above 223 bytes,
+    /// counter past the announced length, no data.
+    pub bad_frames: u64,
+}
+
+impl FastPacketCounters {
+    /// Messages known to be lost or damaged.
+    pub fn errors(&self) -> u64 {
+        self.expired + self.restarted + self.bad_frames
+    }
+}
+
+struct Session {
+    data: [u8; MAX_FAST_PACKET_PAYLOAD],
+    /// Announced by frame 0, once it is in.
+    len: Option<usize>,
+    /// Bit n set when frame n is in.
+    received: u32,
+    last_us: u64,
+}
+
+impl Session {
+    fn new(timestamp_us: u64) -> Self {
+        Self {
+            data: [0xFF; MAX_FAST_PACKET_PAYLOAD],
+            len: None,
+            received: 0,
+            last_us: timestamp_us,
+        }
+    }
+}
+
+/// Frames needed for a `len`-byte payload.
+fn frame_count(len: usize) -> usize {
+    1 + len.saturating_sub(6).div_ceil(7)
+}
+
+/// Turns fast-packet frames into payloads.
+#[derive(Default)]
+pub struct FastPacketReassembler {
+    sessions: HashMap<(u8, u32, u8), Session>,
+    counters: FastPacketCounters,
+}
+
+impl FastPacketReassembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn counters(&self) -> FastPacketCounters {
+        self.counters
+    }
+
+    /// Messages under way.
+    pub fn pending(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// The payload `data`, a fast-packet frame of `pgn`, completes, if any.
+    /// Timestamps must not go back: sessions expire on them.
+    pub fn push(
+        &mut self,
+        pgn: u32,
+        source: u8,
+        data: &[u8],
+        timestamp_us: u64,
+    ) -> Option<Vec<u8>> {
+        self.expire(timestamp_us);
+
+        let Some(&header) = data.first() else {
+            self.counters.bad_frames += 1;
+            return None;
+        };
+        let key = (source, pgn, header >> 5);
+        let counter = (header & 0x1F) as usize;
+
+        let session = self
+            .sessions
+            .entry(key)
+            .or_insert_with(|| Session::new(timestamp_us));
+        if counter == 0 && session.received & 1 != 0 {
+            // Same counter, new message: the previous one lost frames.
+            self.counters.restarted += 1;
+            *session = Session::new(timestamp_us);
+        } else if session.received & (1 << counter) != 0 {
+            self.counters.duplicates += 1;
+            return None;
+        }
+
+        let (offset, bytes) = if counter == 0 {
+            let Some(&len) = data.get(1) else {
+                self.counters.bad_frames += 1;
+                self.sessions.remove(&key);
+                return None;
+            };
+            let len = len as usize;
+            // Frames already in past the announced length.
+            let beyond = session.received.checked_shr(frame_count(len) as u32);
+            if len > MAX_FAST_PACKET_PAYLOAD || beyond.is_some_and(|bits| bits != 0) {
+                self.counters.bad_frames += 1;
+                self.sessions.remove(&key);
+                return None;
+            }
+            session.len = Some(len);
+            (0, &data[2..])
+        } else {
+            let offset = 6 + (counter - 1) * 7;
+            if offset >= session.len.unwrap_or(MAX_FAST_PACKET_PAYLOAD) {
+                self.counters.bad_frames += 1;
+                if session.received == 0 {
+                    self.sessions.remove(&key);
+                }
+                return None;
+            }
+            (offset, &data[1..])
+        };
+
+        if session.received >> counter > 1 {
+            self.counters.out_of_order += 1;
+        }
+        session.received |= 1 << counter;
+        session.last_us = timestamp_us;
+        let end = (offset + bytes.len()).min(MAX_FAST_PACKET_PAYLOAD);
+        session.data[offset..end].copy_from_slice(&bytes[..end - offset]);
+
+        let len = session.len?;
+        let all = u32::MAX >> (32 - frame_count(len));
+        if session.received & all != all {
+            return None;
+        }
+        let payload = session.data[..len].to_vec();
+        self.sessions.remove(&key);
+        self.counters.messages += 1;
+        Some(payload)
+    }
+
+    fn expire(&mut self, now_us: u64) {
+        let before = self.sessions.len();
+        self.sessions
+            .retain(|_, session| now_us.saturating_sub(session.last_us) < FAST_PACKET_TIMEOUT_US);
+        self.counters.expired += (before - self.sessions.len()) as u64;
+    }
+}
+
+/// The frames of a whole message sent as `id`: one frame, or fast-packet
+/// frames numbered with `sequence` (3 bits) when the PGN is one, padded with
+/// 0xFF as senders do. `None` when the payload does not fit: over 8 bytes for
+/// a single-frame PGN, over 223 for a fast packet.
+pub fn split_message(id: CanId, payload: &[u8], sequence: u8) -> Option<Vec<CanFrame>> {
+    let frame = |bytes: &[u8]| {
+        let mut data = [0xFF; 8];
+        data[..bytes.len()].copy_from_slice(bytes);
+        CanFrame {
+            id,
+            data,
+            len: bytes.len(),
+        }
+    };
+
+    if !is_fast_packet(id.pgn()) {
+        return (payload.len() <= 8).then(|| vec![frame(payload)]);
+    }
+    if payload.len() > MAX_FAST_PACKET_PAYLOAD {
+        return None;
+    }
+    let header = (sequence & 0x07) << 5;
+    let split = payload.len().min(6);
+    let mut first = vec![header, payload.len() as u8];
+    first.extend_from_slice(&payload[..split]);
+
+    let mut frames = vec![CanFrame {
+        len: 8,
+        ..frame(&first)
+    }];
+    for (counter, chunk) in payload[split..].chunks(7).enumerate() {
+        let mut bytes = vec![header | (counter as u8 + 1)];
+        bytes.extend_from_slice(chunk);
+        frames.push(CanFrame {
+            len: 8,
+            ..frame(&bytes)
+        });
+    }
+    Some(frames)
+}
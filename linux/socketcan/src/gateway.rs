@@ -0,0 +1,310 @@
+//! Protocols of a TCP gateway: the bus out to clients, their frames back in.
+//!
+//! | format      | to the client                              | from the client                  |
+//! |-------------|--------------------------------------------|----------------------------------|
+//! | `ngt1`      | NGT-1 0x93, whole messages                 | 0x94, or 0x93 with its source    |
+//! | `actisense` | `HH:MM:SS.mmm R CANID D0..D7`, local time  | the same lines, `R` or `T`       |
+//! | `ydwg`      | `hh:mm:ss.ddd R CANID D0..D7`, UTC         | `CANID D0..D7`, echoed with `T`  |
+//!
+//! Text clients may narrow what they receive with a `FILTER` line, e.g.
+//! `FILTER pgn=129025+129026,src=35`; `FILTER` alone lets everything through
+//! again. NGT-1 clients keep the filter of their listener.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Local, Timelike, Utc};
+use korri_n2k::protocol::transport::can_frame::CanFrame;
+use shared_core::format::{
+    encode_ngt1, format_actisense, format_ydwg, parse_actisense, parse_ydwg, ActisenseDirection,
+    N2kMessage, Ngt1Deframer, YdwgMessage, MSG_N2K_DATA, MSG_N2K_RECEIVED, NGT1_MAX_MESSAGE,
+    YDWG_MAX_LINE,
+};
+
+use crate::fast_packet::split_message;
+use crate::messages::Message;
+
+/// Longest text line a client may send, command or frame.
+const MAX_LINE: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GatewayFormat {
+    /// Actisense NGT-1 binary, as read by SignalK, OpenCPN and canboat.
+    Ngt1,
+    /// Actisense ASCII, as `socketcan-receiver` prints it.
+    Actisense,
+    /// Yacht Devices RAW, as a YDWG-02 serves it.
+    Ydwg,
+}
+
+impl GatewayFormat {
+    /// Whether clients receive whole messages rather than frames.
+    pub fn carries_messages(self) -> bool {
+        self == Self::Ngt1
+    }
+
+    /// `frame`, read from the bus at `now`, as clients of this format get it.
+    /// `None` for NGT-1, which carries messages only.
+    pub fn encode_frame(self, frame: &CanFrame, now: DateTime<Utc>) -> Option<Vec<u8>> {
+        self.encode_line(frame, now, ActisenseDirection::Received)
+    }
+
+    /// `message`, reassembled from the bus, for NGT-1 clients. `None` for
+    /// the text formats and for messages NGT-1 cannot hold (ISO transport
+    /// beyond 223 bytes).
+    pub fn encode_message(self, message: &Message, now: DateTime<Utc>) -> Option<Vec<u8>> {
+        if self != Self::Ngt1 {
+            return None;
+        }
+        let mut n2k = N2kMessage::new(
+            message.priority,
+            message.pgn,
+            message.source,
+            message.destination,
+            &message.payload,
+        )?;
+        n2k.timestamp_ms = day_ms(now);
+        let mut buffer = [0u8; NGT1_MAX_MESSAGE];
+        let len = encode_ngt1(MSG_N2K_RECEIVED, &n2k, &mut buffer)?;
+        Some(buffer[..len].to_vec())
+    }
+
+    /// `T` line for a frame a client had sent, `None` but for YDWG.
+    pub fn encode_echo(self, frame: &CanFrame, now: DateTime<Utc>) -> Option<Vec<u8>> {
+        match self {
+            Self::Ydwg => self.encode_line(frame, now, ActisenseDirection::Transmitted),
+            _ => None,
+        }
+    }
+
+    fn encode_line(
+        self,
+        frame: &CanFrame,
+        now: DateTime<Utc>,
+        direction: ActisenseDirection,
+    ) -> Option<Vec<u8>> {
+        match self {
+            Self::Ngt1 => None,
+            Self::Actisense => {
+                let mut buffer = [0u8; 128];
+                let local = now.with_timezone(&Local);
+                let len = format_actisense(frame, day_ms(local) as u64, &mut buffer);
+                Some(buffer[..len].to_vec())
+            }
+            Self::Ydwg => {
+                let mut buffer = [0u8; YDWG_MAX_LINE];
+                let len = format_ydwg(frame, day_ms(now), direction, &mut buffer);
+                Some(buffer[..len].to_vec())
+            }
+        }
+    }
+}
+
+impl FromStr for GatewayFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ngt1" => Ok(Self::Ngt1),
+            "actisense" => Ok(Self::Actisense),
+            "ydwg" => Ok(Self::Ydwg),
+            _ => Err(format!("unknown format `{s}`: ngt1, actisense or ydwg")),
+        }
+    }
+}
+
+impl fmt::Display for GatewayFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ngt1 => "ngt1",
+            Self::Actisense => "actisense",
+            Self::Ydwg => "ydwg",
+        })
+    }
+}
+
+/// Milliseconds since midnight.
+fn day_ms<Tz: chrono::TimeZone>(time: DateTime<Tz>) -> u32 {
+    time.num_seconds_from_midnight() * 1000 + time.timestamp_subsec_millis().min(999)
+}
+
+/// PGNs and sources a client receives, everything when a list is empty.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    pub pgns: Vec<u32>,
+    pub sources: Vec<u8>,
+}
+
+impl Filter {
+    /// `pgn=129025+129026,src=35+36`, either part optional; empty for all.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut filter = Self::default();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, values) = part
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got `{part}`"))?;
+            let values = values.split('+').filter(|v| !v.is_empty());
+            match key {
+                "pgn" => {
+                    for value in values {
+                        filter
+                            .pgns
+                            .push(value.parse().map_err(|_| format!("bad PGN `{value}`"))?);
+                    }
+                }
+                "src" => {
+                    for value in values {
+                        filter
+                            .sources
+                            .push(value.parse().map_err(|_| format!("bad source `{value}`"))?);
+                    }
+                }
+                _ => return Err(format!("unknown filter key `{key}`: pgn or src")),
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn accepts(&self, pgn: u32, source: u8) -> bool {
+        (self.pgns.is_empty() || self.pgns.contains(&pgn))
+            && (self.sources.is_empty() || self.sources.contains(&source))
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.pgns.is_empty() && self.sources.is_empty() {
+            return f.write_str("all");
+        }
+        let join = |values: Vec<String>| values.join("+");
+        let mut parts = Vec::new();
+        if !self.pgns.is_empty() {
+            parts.push(format!(
+                "pgn={}",
+                join(self.pgns.iter().map(u32::to_string).collect())
+            ));
+        }
+        if !self.sources.is_empty() {
+            parts.push(format!(
+                "src={}",
+                join(self.sources.iter().map(u8::to_string).collect())
+            ));
+        }
+        f.write_str(&parts.join(","))
+    }
+}
+
+/// What a client's bytes amount to.
+#[derive(Clone, Debug)]
+pub enum Inbound {
+    /// Frames of one message, to send in order.
+    Send(Vec<CanFrame>),
+    Filter(Filter),
+    Rejected(String),
+}
+
+/// Parses what one client sends, in the format of its listener.
+pub struct ClientReader {
+    format: GatewayFormat,
+    /// Source given to NGT-1 0x94 messages, which leave it to the gateway.
+    source: Option<u8>,
+    line: Vec<u8>,
+    deframer: Ngt1Deframer,
+    /// Fast-packet sequence counter of the messages this client sends.
+    sequence: u8,
+}
+
+impl ClientReader {
+    pub fn new(format: GatewayFormat, source: Option<u8>) -> Self {
+        Self {
+            format,
+            source,
+            line: Vec::new(),
+            deframer: Ngt1Deframer::new(),
+            sequence: 0,
+        }
+    }
+
+    /// Consumes `bytes`, as they came off the socket.
+    pub fn feed(&mut self, bytes: &[u8], out: &mut Vec<Inbound>) {
+        if self.format == GatewayFormat::Ngt1 {
+            let messages: Vec<_> = self.deframer.feed(bytes).collect();
+            for message in messages {
+                out.push(match message {
+                    Ok(message) => self.message(&message),
+                    Err(e) => Inbound::Rejected(format!("NGT-1: {e:?}")),
+                });
+            }
+            return;
+        }
+        for &byte in bytes {
+            if byte != b'\n' {
+                if self.line.len() < MAX_LINE {
+                    self.line.push(byte);
+                }
+                continue;
+            }
+            let line = std::mem::take(&mut self.line);
+            if let Some(inbound) = self.text_line(&line) {
+                out.push(inbound);
+            }
+        }
+    }
+
+    fn text_line(&self, line: &[u8]) -> Option<Inbound> {
+        let text = String::from_utf8_lossy(line);
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+        if let Some(spec) = text.strip_prefix("FILTER") {
+            return Some(match Filter::parse(spec) {
+                Ok(filter) => Inbound::Filter(filter),
+                Err(e) => Inbound::Rejected(e),
+            });
+        }
+        let frame = match self.format {
+            GatewayFormat::Actisense => parse_actisense(line).map(|line| line.frame),
+            _ => match parse_ydwg(line) {
+                Ok(YdwgMessage::Send(frame)) => Ok(frame),
+                Ok(YdwgMessage::Frame(line)) => Ok(line.frame),
+                Ok(YdwgMessage::Other(_)) => {
+                    return Some(Inbound::Rejected(format!("unknown command `{text}`")));
+                }
+                Err(e) => Err(e),
+            },
+        };
+        Some(match frame {
+            Ok(frame) => Inbound::Send(vec![frame]),
+            Err(e) => Inbound::Rejected(format!("`{text}`: {e:?}")),
+        })
+    }
+
+    fn message(&mut self, message: &N2kMessage) -> Inbound {
+        let mut message = message.clone();
+        // 0x94 leaves the source to the gateway; parse_ngt1_body marks it 255.
+        if message.source == 255 {
+            match self.source {
+                Some(source) => message.source = source,
+                None => {
+                    return Inbound::Rejected(format!(
+                        "NGT-1 0x{MSG_N2K_DATA:02X} for PGN {} without a gateway source address",
+                        message.pgn
+                    ));
+                }
+            }
+        }
+        let Some(id) = message.can_id() else {
+            return Inbound::Rejected(format!("PGN {}: no CAN id", message.pgn));
+        };
+        let sequence = self.sequence;
+        self.sequence = (self.sequence + 1) & 0x07;
+        match split_message(id, message.payload(), sequence) {
+            Some(frames) => Inbound::Send(frames),
+            None => Inbound::Rejected(format!(
+                "PGN {}: {} bytes do not fit its transport",
+                message.pgn, message.len
+            )),
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! Who is on the bus: ISO Requests for the address claims, product and
+//! configuration information of every device, and the table of the replies.
+//!
+//! ```text
+//! claim an address ─► ISO Request 60928, 126996, 126998 to 255 ─► replies
+//!                  ─► ISO Request to each device still missing one ─► Inventory
+//! ```
+//!
+//! Requests go out from an address of our own: devices are not bound to
+//! answer the null address, and some ignore it.
+
+use std::collections::BTreeMap;
+
+use embassy_time::{with_deadline, Duration, Instant};
+use korri_n2k::error::SendPgnError;
+use korri_n2k::infra::codec::traits::PgnData;
+use korri_n2k::protocol::managment::iso_name::IsoName;
+use korri_n2k::protocol::messages::Pgn126996;
+use korri_n2k::protocol::transport::traits::can_bus::CanBus;
+use serde_json::{json, Value};
+
+use crate::analyzer::text;
+use crate::messages::{Message, MessageAssembler};
+use crate::node::AddressManagerType;
+use crate::pgn::{lookup_name, ADDRESS_CLAIM_PGN, BROADCAST, NULL_ADDRESS};
+
+pub const ISO_REQUEST_PGN: u32 = 59904;
+pub const PRODUCT_INFORMATION_PGN: u32 = 126996;
+pub const CONFIGURATION_INFORMATION_PGN: u32 = 126998;
+
+/// What the survey asks every device for.
+pub const REQUESTED_PGNS: [u32; 3] = [
+    ADDRESS_CLAIM_PGN,
+    PRODUCT_INFORMATION_PGN,
+    CONFIGURATION_INFORMATION_PGN,
+];
+
+/// ISO Requests are priority 6.
+const REQUEST_PRIORITY: u8 = 6;
+
+/// Why a survey stopped.
+#[derive(Debug)]
+pub enum SurveyError<E: core::fmt::Debug> {
+    /// Receiving from the bus failed.
+    Bus(E),
+    /// The ISO Request for `pgn` was not sent.
+    Request { pgn: u32, error: SendPgnError<E> },
+}
+
+/// Payload of an ISO Request for `pgn`.
+pub fn iso_request(pgn: u32) -> [u8; 3] {
+    let bytes = pgn.to_le_bytes();
+    [bytes[0], bytes[1], bytes[2]]
+}
+
+/// PGN 126996, its strings trimmed of their padding.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProductInformation {
+    /// e.g. 2.1 for version 2.100.
+    pub nmea2000_version: f32,
+    pub product_code: u16,
+    pub model_id: String,
+    pub software_version: String,
+    pub model_version: String,
+    pub serial_code: String,
+    /// `LevelA`, `LevelB`.
+    pub certification_level: String,
+    /// Times 50 mA.
+    pub load_equivalency: u8,
+}
+
+impl ProductInformation {
+    pub fn from_payload(payload: &[u8]) -> Option<Self> {
+        let product = Pgn126996::from_payload(payload).ok()?;
+        Some(Self {
+            nmea2000_version: product.nmea2000_version,
+            product_code: product.product_code,
+            model_id: text(&product.model_id),
+            software_version: text(&product.software_version_code),
+            model_version: text(&product.model_version),
+            serial_code: text(&product.model_serial_code),
+            certification_level: format!("{:?}", product.certification_level),
+            load_equivalency: product.load_equivalency,
+        })
+    }
+}
+
+/// PGN 126998: what the installer wrote into the device.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigurationInformation {
+    pub installation_description1: String,
+    pub installation_description2: String,
+    pub manufacturer_information: String,
+}
+
+impl ConfigurationInformation {
+    /// Parsed here rather than by korri-n2k, which leaves one of the two
+    /// header bytes out of STRING_LAU lengths where devices count both.
+    pub fn from_payload(payload: &[u8]) -> Option<Self> {
+        let mut rest = payload;
+        Some(Self {
+            installation_description1: string_lau(&mut rest)?,
+            installation_description2: string_lau(&mut rest)?,
+            manufacturer_information: string_lau(&mut rest)?,
+        })
+    }
+}
+
+/// A STRING_LAU off the front of `rest`: length, header included, then 1 for
+/// ASCII or 0 for UTF-16, then the text.
+fn string_lau(rest: &mut &[u8]) -> Option<String> {
+    let &[length, encoding, ..] = *rest else {
+        return None;
+    };
+    let length = (length as usize).max(2);
+    let body = rest.get(2..length)?;
+    *rest = &rest[length..];
+    Some(match encoding {
+        0 => {
+            let units: Vec<u16> = body
+                .as_chunks::<2>()
+                .0
+                .iter()
+                .map(|&pair| u16::from_le_bytes(pair))
+                .take_while(|&unit| unit != 0 && unit != 0xFFFF)
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => text(body),
+    })
+}
+
+/// One device, by the address it answered from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Device {
+    pub address: u8,
+    pub name: Option<u64>,
+    pub product: Option<ProductInformation>,
+    pub configuration: Option<ConfigurationInformation>,
+}
+
+impl Device {
+    /// Whether every requested PGN has come back.
+    pub fn is_complete(&self) -> bool {
+        self.name.is_some() && self.product.is_some() && self.configuration.is_some()
+    }
+
+    /// Manufacturer name, or `mfr N` for a code korri-n2k does not know.
+    pub fn manufacturer(&self) -> Option<String> {
+        let code = IsoName::from_raw(self.name?).manufacturer_code();
+        Some(lookup_name("ManufacturerCode", code as u64).unwrap_or_else(|| format!("mfr {code}")))
+    }
+
+    /// Device function name: its meaning depends on the class, korri-n2k
+    /// keys it `class << 8 | function`.
+    pub fn function(&self) -> Option<String> {
+        let name = IsoName::from_raw(self.name?);
+        let key = (name.device_class() as u64) << 8 | name.device_function() as u64;
+        Some(
+            lookup_name("DeviceFunction", key)
+                .unwrap_or_else(|| name.device_function().to_string()),
+        )
+    }
+
+    pub fn class(&self) -> Option<String> {
+        let class = IsoName::from_raw(self.name?).device_class();
+        Some(lookup_name("DeviceClass", class as u64).unwrap_or_else(|| class.to_string()))
+    }
+
+    /// JSON object; what did not come back is `null`.
+    pub fn to_json(&self) -> Value {
+        let name = self.name.map(IsoName::from_raw);
+        json!({
+            "address": self.address,
+            "name": self.name.map(|name| format!("0x{name:016X}")),
+            "manufacturer_code": name.map(|n| n.manufacturer_code()),
+            "manufacturer": self.manufacturer(),
+            "unique_number": name.map(|n| n.unique_number()),
+            "device_function": name.map(|n| n.device_function()),
+            "device_function_name": self.function(),
+            "device_class": name.map(|n| n.device_class()),
+            "device_class_name": self.class(),
+            "device_instance": name.map(|n| n.device_instance()),
+            "system_instance": name.map(|n| n.system_instance()),
+            "industry_group": name.map(|n| n.industry_group()),
+            "product": self.product.as_ref().map(|p| json!({
+                "nmea2000_version": format!("{:.3}", p.nmea2000_version),
+                "product_code": p.product_code,
+                "model_id": p.model_id,
+                "software_version": p.software_version,
+                "model_version": p.model_version,
+                "serial_code": p.serial_code,
+                "certification_level": p.certification_level,
+                "load_equivalency": p.load_equivalency,
+            })),
+            "configuration": self.configuration.as_ref().map(|c| json!({
+                "installation_description1": c.installation_description1,
+                "installation_description2": c.installation_description2,
+                "manufacturer_information": c.manufacturer_information,
+            })),
+        })
+    }
+}
+
+/// Devices heard from, by address.
+#[derive(Clone, Debug, Default)]
+pub struct Inventory {
+    pub devices: BTreeMap<u8, Device>,
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes what `message` says about its source, if anything.
+    pub fn push(&mut self, message: &Message) {
+        if message.source >= NULL_ADDRESS {
+            return;
+        }
+        match message.pgn {
+            ADDRESS_CLAIM_PGN => {
+                let Some(name) = message.payload.get(..8).and_then(|b| b.try_into().ok()) else {
+                    return;
+                };
+                let name = u64::from_le_bytes(name);
+                // A device that moved no longer answers at its old address.
+                self.devices.retain(|&address, device| {
+                    address == message.source || device.name != Some(name)
+                });
+                let device = self.device(message.source);
+                if device.name != Some(name) {
+                    // Another device took the address: what was known is not its.
+                    *device = Device {
+                        address: message.source,
+                        name: Some(name),
+                        ..Device::default()
+                    };
+                }
+            }
+            PRODUCT_INFORMATION_PGN => {
+                if let Some(product) = ProductInformation::from_payload(&message.payload) {
+                    self.device(message.source).product = Some(product);
+                }
+            }
+            CONFIGURATION_INFORMATION_PGN => {
+                if let Some(config) = ConfigurationInformation::from_payload(&message.payload) {
+                    self.device(message.source).configuration = Some(config);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn device(&mut self, address: u8) -> &mut Device {
+        self.devices.entry(address).or_insert_with(|| Device {
+            address,
+            ..Device::default()
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Array(self.devices.values().map(Device::to_json).collect())
+    }
+}
+
+/// Requests [`REQUESTED_PGNS`] from every device through `manager`, listens
+/// for `wait`, then asks the devices still missing a reply directly, up to
+/// `retries` times. Our own address is left out of the result.
+pub async fn survey<C: CanBus>(
+    manager: &mut AddressManagerType<C>,
+    wait: Duration,
+    retries: u32,
+) -> Result<Inventory, SurveyError<C::Error>>
+where
+    C::Error: core::fmt::Debug,
+{
+    let mut inventory = Inventory::new();
+    let mut assembler = MessageAssembler::new();
+
+    for pgn in REQUESTED_PGNS {
+        request(manager, pgn, BROADCAST).await?;
+    }
+    listen(manager, &mut assembler, &mut inventory, wait).await?;
+
+    for _ in 0..retries {
+        let own = manager.current_address();
+        let missing: Vec<(u8, Vec<u32>)> = inventory
+            .devices
+            .values()
+            .filter(|device| device.address != own && !device.is_complete())
+            .map(|device| {
+                let pgns = [
+                    (ADDRESS_CLAIM_PGN, device.name.is_none()),
+                    (PRODUCT_INFORMATION_PGN, device.product.is_none()),
+                    (
+                        CONFIGURATION_INFORMATION_PGN,
+                        device.configuration.is_none(),
+                    ),
+                ];
+                let pgns = pgns.iter().filter(|(_, m)| *m).map(|(p, _)| *p).collect();
+                (device.address, pgns)
+            })
+            .collect();
+        if missing.is_empty() {
+            break;
+        }
+        for (address, pgns) in missing {
+            for pgn in pgns {
+                request(manager, pgn, address).await?;
+            }
+        }
+        listen(manager, &mut assembler, &mut inventory, wait).await?;
+    }
+
+    inventory.devices.remove(&manager.current_address());
+    Ok(inventory)
+}
+
+async fn request<C: CanBus>(
+    manager: &mut AddressManagerType<C>,
+    pgn: u32,
+    destination: u8,
+) -> Result<(), SurveyError<C::Error>>
+where
+    C::Error: core::fmt::Debug,
+{
+    manager
+        .send_payload(
+            ISO_REQUEST_PGN,
+            REQUEST_PRIORITY,
+            Some(destination),
+            &iso_request(pgn),
+        )
+        .await
+        .map_err(|error| SurveyError::Request { pgn, error })
+}
+
+/// Feeds `inventory` with what comes in for `wait`, fast packets
+/// reassembled. The manager keeps defending our address meanwhile.
+async fn listen<C: CanBus>(
+    manager: &mut AddressManagerType<C>,
+    assembler: &mut MessageAssembler,
+    inventory: &mut Inventory,
+    wait: Duration,
+) -> Result<(), SurveyError<C::Error>>
+where
+    C::Error: core::fmt::Debug,
+{
+    let deadline = Instant::now() + wait;
+    while let Ok(frame) = with_deadline(deadline, manager.recv()).await {
+        let Some(frame) = frame.map_err(SurveyError::Bus)? else {
+            continue;
+        };
+        if let Some(message) = assembler.push(&frame, Instant::now().as_micros()) {
+            inventory.push(&message);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,20 @@
+//! Host-side NMEA2000 tooling: everything the boards cannot do for themselves,
+//! from reading their capture streams back to running on a SocketCAN bus.
+
+pub mod actisense;
+pub mod analyzer;
+pub mod busload;
+pub mod candump;
+pub mod capture;
+pub mod decoded;
+pub mod ebl;
+pub mod fast_packet;
+pub mod gateway;
+pub mod inventory;
+pub mod messages;
+pub mod node;
+pub mod pcapng;
+pub mod pgn;
+pub mod replay;
+pub mod segment;
+pub mod signalk;
@@ -1,15 +1,52 @@
 use anyhow::Result;
 use chrono::Local;
-use korri_n2k::protocol::transport::can_frame::CanFrame;
+use clap::{Parser, ValueEnum};
+use shared_core::format::{
+    format_pcdin, format_ydwg, ActisenseDirection, N2kMessage, PCDIN_MAX_SENTENCE, YDWG_MAX_LINE,
+};
 use socketcan::{CanFrame as LinuxCanFrame, CanSocket, EmbeddedFrame, Frame, Socket};
+use socketcan_receiver::analyzer::analyzer_json;
+use socketcan_receiver::candump::{from_socketcan, write_candump};
+use socketcan_receiver::decoded::Decoder;
+use socketcan_receiver::messages::MessageAssembler;
+use socketcan_receiver::pcapng::PcapngWriter;
 use std::io::{self, Write};
-use std::time::Instant;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// HH:MM:SS.mmm R CANID D0..D7
+    Actisense,
+    /// `candump -l`, rejouable avec canplayer
+    Candump,
+    /// Yacht Devices RAW, comme une passerelle YDWG-02
+    Ydwg,
+    /// pcapng pour Wireshark (`| wireshark -k -i -`)
+    Pcapng,
+    /// JSON de canboat `analyzer -json`, messages décodés par korri-n2k
+    Json,
+    /// $PCDIN : messages N2K entiers encapsulés en NMEA 0183
+    Pcdin,
+    /// Texte lisible : champs décodés avec unités et noms de lookups,
+    /// sources nommées d'après leur NAME
+    Decoded,
+}
+
+#[derive(Parser)]
+#[command(about = "Affiche les trames d'un bus SocketCAN")]
+struct Args {
+    /// Interface CAN (can0, vcan0...)
+    #[arg(default_value = "can0")]
+    interface: String,
+    /// Format de sortie
+    #[arg(long, value_enum, default_value = "actisense")]
+    format: OutputFormat,
+}
 
 /// Formatage Actisense adapté pour Linux
 /// Format: HH:MM:SS.mmm R CANID D0 D1 D2 D3 D4 D5 D6 D7
-fn format_actisense(frame: &LinuxCanFrame, start_instant: Instant) -> String {
+fn format_actisense(frame: &LinuxCanFrame, _start_instant: Instant) -> String {
     let now = Local::now();
-    let uptime = start_instant.elapsed();
 
     // On utilise chrono pour l'heure système réelle,
     // ou on peut simuler l'uptime comme dans l'exemple original.
@@ -28,11 +65,20 @@ fn format_actisense(frame: &LinuxCanFrame, start_instant: Instant) -> String {
 fn main() -> Result<()> {
     // Sur BBB, l'interface est généralement can0 ou can1
     // Tu peux tester avec "vcan0" sur ton PC
-    let interface = std::env::args()
-        .nth(1)
-        .unwrap_or_else(|| "can0".to_string());
+    let args = Args::parse();
+    let interface = args.interface;
 
-    println!("Démarrage du récepteur sur {}...", interface);
+    // Hors Actisense, les messages d'état passent sur stderr, pour que stdout
+    // reste un flux valide.
+    let status = |message: String| {
+        if args.format != OutputFormat::Actisense {
+            eprintln!("{message}");
+        } else {
+            println!("{message}");
+        }
+    };
+
+    status(format!("Démarrage du récepteur sur {}...", interface));
 
     let socket = CanSocket::open(&interface)
         .map_err(|e| anyhow::anyhow!("Impossible d'ouvrir {}: {}", interface, e))?;
@@ -41,10 +87,103 @@ fn main() -> Result<()> {
     let stdout = io::stdout();
     let mut handle = stdout.lock();
 
-    println!("Prêt à recevoir (Format Actisense)...");
+    let format_name = match args.format {
+        OutputFormat::Actisense => "Actisense",
+        OutputFormat::Candump => "candump",
+        OutputFormat::Ydwg => "YDWG RAW",
+        OutputFormat::Pcapng => "pcapng",
+        OutputFormat::Json => "canboat JSON",
+        OutputFormat::Pcdin => "PCDIN",
+        OutputFormat::Decoded => "décodé",
+    };
+    let mut pcapng = if args.format == OutputFormat::Pcapng {
+        Some(PcapngWriter::new(io::stdout(), &interface)?)
+    } else {
+        None
+    };
+    let mut assembler = MessageAssembler::new();
+    let mut decoder = Decoder::new();
+    status(format!("Prêt à recevoir (Format {})...", format_name));
 
     loop {
         match socket.read_frame() {
+            Ok(frame) if args.format == OutputFormat::Candump => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                if let Some(frame) = from_socketcan(&frame, now.as_micros() as u64) {
+                    let _ = write_candump(&mut handle, &interface, &frame);
+                    let _ = handle.flush();
+                }
+            }
+            Ok(frame) if pcapng.is_some() => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                if let (Some(pcapng), Some(frame)) =
+                    (&mut pcapng, from_socketcan(&frame, now.as_micros() as u64))
+                {
+                    // Wireshark fermé : inutile de continuer
+                    pcapng.write_frame(&frame)?;
+                    pcapng.flush()?;
+                }
+            }
+            Ok(frame) if args.format == OutputFormat::Ydwg => {
+                // Heure UTC du jour, comme l'horloge d'une passerelle
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let time_ms = (now.as_millis() % 86_400_000) as u32;
+                // Seules les trames étendues sont du NMEA2000
+                let Some(frame) = from_socketcan(&frame, 0).and_then(|f| f.to_can_frame()) else {
+                    continue;
+                };
+                let mut buffer = [0u8; YDWG_MAX_LINE];
+                let len = format_ydwg(&frame, time_ms, ActisenseDirection::Received, &mut buffer);
+                let _ = handle.write_all(&buffer[..len]);
+                let _ = handle.flush();
+            }
+            Ok(frame) if args.format == OutputFormat::Json => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                // Les fast packets sortent une fois réassemblés
+                let Some(message) = from_socketcan(&frame, 0)
+                    .and_then(|f| f.to_can_frame())
+                    .and_then(|f| assembler.push(&f, now.as_micros() as u64))
+                else {
+                    continue;
+                };
+                let _ = writeln!(handle, "{}", analyzer_json(&message));
+                let _ = handle.flush();
+            }
+            Ok(frame) if args.format == OutputFormat::Decoded => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let Some(message) = from_socketcan(&frame, 0)
+                    .and_then(|f| f.to_can_frame())
+                    .and_then(|f| assembler.push(&f, now.as_micros() as u64))
+                else {
+                    continue;
+                };
+                let _ = handle.write_all(decoder.format(&message, Local::now()).as_bytes());
+                let _ = handle.flush();
+            }
+            Ok(frame) if args.format == OutputFormat::Pcdin => {
+                // Heure du récepteur, comme celle d'une passerelle
+                let uptime_us = start_time.elapsed().as_micros() as u64;
+                let Some(message) = from_socketcan(&frame, 0)
+                    .and_then(|f| f.to_can_frame())
+                    .and_then(|f| assembler.push(&f, uptime_us))
+                else {
+                    continue;
+                };
+                let Some(mut n2k) = N2kMessage::new(
+                    message.priority,
+                    message.pgn,
+                    message.source,
+                    message.destination,
+                    &message.payload,
+                ) else {
+                    continue;
+                };
+                n2k.timestamp_ms = (uptime_us / 1000) as u32;
+                let mut buffer = [0u8; PCDIN_MAX_SENTENCE];
+                let len = format_pcdin(&n2k, &mut buffer);
+                let _ = handle.write_all(&buffer[..len]);
+                let _ = handle.flush();
+            }
             Ok(frame) => {
                 let output = format_actisense(&frame, start_time);
                 let _ = handle.write_all(output.as_bytes());
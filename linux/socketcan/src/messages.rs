@@ -0,0 +1,92 @@
+//! Whole NMEA 2000 messages out of CAN frames: single frames as they come,
+//! fast packets and ISO transport sessions once reassembled.
+
+use korri_n2k::protocol::transport::can_frame::CanFrame;
+use shared_core::transport::{TpCounters, TpReceiver, PGN_TP_CM, PGN_TP_DT};
+
+use crate::fast_packet::{FastPacketCounters, FastPacketReassembler};
+use crate::pgn::{is_fast_packet, BROADCAST};
+
+/// A message and the header of its last frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    /// Time of the last frame, in the unit of the caller.
+    pub timestamp_us: u64,
+    pub priority: u8,
+    pub pgn: u32,
+    pub source: u8,
+    /// [`BROADCAST`] for PDU2 PGNs.
+    pub destination: u8,
+    pub payload: Vec<u8>,
+}
+
+/// Transport sessions followed at once, 1785 bytes each.
+const TP_SESSIONS: usize = 16;
+
+/// Feeds on frames, hands out messages.
+pub struct MessageAssembler {
+    fast_packets: FastPacketReassembler,
+    /// Passive: every BAM and RTS/CTS session on the bus, never replying.
+    transport: Box<TpReceiver<TP_SESSIONS>>,
+}
+
+impl MessageAssembler {
+    pub fn new() -> Self {
+        Self {
+            fast_packets: FastPacketReassembler::new(),
+            transport: Box::new(TpReceiver::passive()),
+        }
+    }
+
+    /// The message `frame` completes, if any. Timestamps must not go back:
+    /// incomplete fast packets expire on them.
+    pub fn push(&mut self, frame: &CanFrame, timestamp_us: u64) -> Option<Message> {
+        let pgn = frame.id.pgn();
+        if pgn == PGN_TP_CM || pgn == PGN_TP_DT {
+            let now_ms = timestamp_us / 1000;
+            while self.transport.poll(now_ms).is_some() {}
+            let message = self.transport.on_frame(frame, now_ms).message?;
+            return Some(Message {
+                timestamp_us,
+                priority: message.priority,
+                pgn: message.pgn,
+                source: message.source,
+                destination: message.destination,
+                payload: message.data.to_vec(),
+            });
+        }
+
+        let source = frame.id.source_address();
+        let payload = if is_fast_packet(pgn) {
+            self.fast_packets
+                .push(pgn, source, &frame.data[..frame.len.min(8)], timestamp_us)?
+        } else {
+            frame.data[..frame.len.min(8)].to_vec()
+        };
+
+        Some(Message {
+            timestamp_us,
+            priority: frame.id.priority(),
+            pgn,
+            source,
+            destination: frame.id.destination().unwrap_or(BROADCAST),
+            payload,
+        })
+    }
+
+    /// Fast packet counters: expired sessions, duplicates...
+    pub fn fast_packets(&self) -> FastPacketCounters {
+        self.fast_packets.counters()
+    }
+
+    /// ISO transport counters: timeouts, aborts...
+    pub fn transport(&self) -> TpCounters {
+        self.transport.counters()
+    }
+}
+
+impl Default for MessageAssembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
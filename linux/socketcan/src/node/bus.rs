@@ -0,0 +1,170 @@
+//! SocketCAN adapter for the korri-n2k transport traits, the host counterpart
+//! of `EspCanBus` and `Stm32CanBus`.
+//!
+//! SocketCAN reads block, so a thread reads the socket and queues frames for
+//! the async side. Writes go straight to the same socket: a frame sent here is
+//! not read back by it, while other sockets on the interface, other nodes on
+//! `vcan0` included, see it.
+
+use std::collections::VecDeque;
+use std::future::poll_fn;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use embassy_time::Timer;
+use korri_n2k::protocol::transport::{
+    can_frame::CanFrame,
+    can_id::CanId,
+    traits::{can_bus::CanBus, korri_timer::KorriTimer},
+    CAN_SEND_TIMEOUT_MS,
+};
+use socketcan::{CanSocket, EmbeddedFrame, ExtendedId, Frame, Socket};
+
+use crate::candump::write_frame_with_deadline;
+
+/// Frames waiting for `recv`. Past this, the oldest are dropped: the node is
+/// not keeping up and the most recent traffic matters more.
+pub const RX_QUEUE_DEPTH: usize = 1024;
+
+#[derive(Debug)]
+pub enum SocketCanError {
+    Io(io::Error),
+    FrameCreate,
+    SendTimeout,
+}
+
+impl From<io::Error> for SocketCanError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+#[derive(Default)]
+struct RxQueue {
+    frames: VecDeque<CanFrame>,
+    /// The reader thread stopped on this error.
+    error: Option<io::Error>,
+    waker: Option<Waker>,
+    dropped: u64,
+}
+
+/// Async SocketCAN adapter, standard and remote frames filtered out as on the
+/// boards.
+pub struct SocketCanBus {
+    socket: Arc<CanSocket>,
+    rx: Arc<Mutex<RxQueue>>,
+}
+
+impl SocketCanBus {
+    /// Opens `interface` and starts its reader thread.
+    pub fn open(interface: &str) -> io::Result<Self> {
+        let socket = Arc::new(CanSocket::open(interface)?);
+        // A blocked write would stall every task of the node.
+        socket.set_write_timeout(Duration::from_millis(CAN_SEND_TIMEOUT_MS as u64))?;
+        let rx = Arc::new(Mutex::new(RxQueue::default()));
+
+        let reader = (socket.clone(), rx.clone());
+        thread::Builder::new()
+            .name(format!("{interface}-rx"))
+            .spawn(move || read_loop(&reader.0, &reader.1))?;
+        Ok(Self { socket, rx })
+    }
+
+    /// Frames dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.rx.lock().unwrap().dropped
+    }
+}
+
+fn read_loop(socket: &CanSocket, rx: &Mutex<RxQueue>) {
+    loop {
+        let frame = match socket.read_frame() {
+            Ok(frame) => frame,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                let mut rx = rx.lock().unwrap();
+                rx.error = Some(e);
+                if let Some(waker) = rx.waker.take() {
+                    waker.wake();
+                }
+                return;
+            }
+        };
+        // Non-N2K frames, skipped silently.
+        if !frame.is_extended() || frame.is_remote_frame() || frame.is_error_frame() {
+            continue;
+        }
+        let payload = frame.data();
+        let mut data = [0u8; 8];
+        data[..payload.len()].copy_from_slice(payload);
+        let frame = CanFrame {
+            id: CanId(frame.raw_id()),
+            data,
+            len: payload.len(),
+        };
+
+        let mut rx = rx.lock().unwrap();
+        if rx.frames.len() >= RX_QUEUE_DEPTH {
+            rx.frames.pop_front();
+            rx.dropped += 1;
+        }
+        rx.frames.push_back(frame);
+        if let Some(waker) = rx.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl CanBus for SocketCanBus {
+    type Error = SocketCanError;
+
+    async fn send(&mut self, frame: &CanFrame) -> Result<(), Self::Error> {
+        let id = ExtendedId::new(frame.id.0).ok_or(SocketCanError::FrameCreate)?;
+        let frame = socketcan::CanFrame::new(id, &frame.data[..frame.len])
+            .ok_or(SocketCanError::FrameCreate)?;
+
+        // Retried until the same deadline the boards use. The node's tasks
+        // wait meanwhile, as they would on a blocked write.
+        let deadline = Instant::now() + Duration::from_millis(CAN_SEND_TIMEOUT_MS as u64);
+        if write_frame_with_deadline(&self.socket, &frame, deadline)? {
+            Ok(())
+        } else {
+            Err(SocketCanError::SendTimeout)
+        }
+    }
+
+    /// Cancel-safe: a frame leaves the queue only when returned.
+    async fn recv(&mut self) -> Result<CanFrame, Self::Error> {
+        poll_fn(|cx| {
+            let mut rx = self.rx.lock().unwrap();
+            if let Some(frame) = rx.frames.pop_front() {
+                return Poll::Ready(Ok(frame));
+            }
+            if let Some(e) = rx.error.take() {
+                return Poll::Ready(Err(e.into()));
+            }
+            rx.waker = Some(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+/// embassy-time adapter implementing `KorriTimer`, on the host time driver.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LinuxTimer;
+
+impl LinuxTimer {
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl KorriTimer for LinuxTimer {
+    async fn delay_ms(&mut self, millis: u32) {
+        Timer::after(embassy_time::Duration::from_millis(millis as u64)).await;
+    }
+}
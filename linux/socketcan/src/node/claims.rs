@@ -0,0 +1,126 @@
+//! Who holds which address, rebuilt from the ISO Address Claims (PGN 60928)
+//! seen on the bus, with the history of how it got there.
+//!
+//! Only the bus is observed, not the nodes: the log sees what any other device
+//! would, whichever stack the claimants run.
+
+use std::collections::{BTreeMap, HashMap};
+
+use korri_n2k::protocol::transport::can_frame::CanFrame;
+
+use crate::pgn::{ADDRESS_CLAIM_PGN, NULL_ADDRESS};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClaimEvent {
+    /// `name` took `address`, which nobody held.
+    Claimed { name: u64, address: u8 },
+    /// `name` left or lost `from`, and took `address`.
+    Moved { name: u64, from: u8, address: u8 },
+    /// `name` claimed `address`, held by `holder`. The lower NAME keeps it.
+    Conflict { name: u64, holder: u64, address: u8 },
+    /// `name` announced it could not claim any address.
+    CannotClaim { name: u64 },
+}
+
+impl ClaimEvent {
+    pub fn name(&self) -> u64 {
+        match *self {
+            Self::Claimed { name, .. }
+            | Self::Moved { name, .. }
+            | Self::Conflict { name, .. }
+            | Self::CannotClaim { name } => name,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ClaimLog {
+    holders: BTreeMap<u8, u64>,
+    addresses: HashMap<u64, u8>,
+    /// Last address of the devices that lost theirs.
+    lost: HashMap<u64, u8>,
+    history: Vec<(u64, ClaimEvent)>,
+}
+
+impl ClaimLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes in a frame seen at `time_us`. Returns the event it caused, if
+    /// any: a device repeating or defending its claim causes none.
+    pub fn push(&mut self, frame: &CanFrame, time_us: u64) -> Option<ClaimEvent> {
+        if frame.id.pgn() != ADDRESS_CLAIM_PGN || frame.len != 8 {
+            return None;
+        }
+        let name = u64::from_le_bytes(frame.data);
+        let address = frame.id.source_address();
+        let held = self.addresses.get(&name).copied();
+        let previous = held.or_else(|| self.lost.get(&name).copied());
+
+        let event = if address == NULL_ADDRESS {
+            if let Some(from) = held {
+                self.release(name, from);
+            }
+            ClaimEvent::CannotClaim { name }
+        } else {
+            match self.holders.get(&address).copied() {
+                Some(holder) if holder == name => return None,
+                Some(holder) => {
+                    if name < holder {
+                        self.release(holder, address);
+                        self.take(name, address, held);
+                    }
+                    ClaimEvent::Conflict {
+                        name,
+                        holder,
+                        address,
+                    }
+                }
+                None => {
+                    self.take(name, address, held);
+                    match previous {
+                        Some(from) => ClaimEvent::Moved {
+                            name,
+                            from,
+                            address,
+                        },
+                        None => ClaimEvent::Claimed { name, address },
+                    }
+                }
+            }
+        };
+        self.history.push((time_us, event));
+        Some(event)
+    }
+
+    /// Current holders, by address.
+    pub fn table(&self) -> impl Iterator<Item = (u8, u64)> + '_ {
+        self.holders.iter().map(|(&address, &name)| (address, name))
+    }
+
+    /// Address held by `name`, if any.
+    pub fn address_of(&self, name: u64) -> Option<u8> {
+        self.addresses.get(&name).copied()
+    }
+
+    /// Every event, in the order seen, with its time.
+    pub fn history(&self) -> &[(u64, ClaimEvent)] {
+        &self.history
+    }
+
+    fn take(&mut self, name: u64, address: u8, held: Option<u8>) {
+        if let Some(from) = held {
+            self.holders.remove(&from);
+        }
+        self.holders.insert(address, name);
+        self.addresses.insert(name, address);
+        self.lost.remove(&name);
+    }
+
+    fn release(&mut self, name: u64, address: u8) {
+        self.holders.remove(&address);
+        self.addresses.remove(&name);
+        self.lost.insert(name, address);
+    }
+}
@@ -0,0 +1,21 @@
+//! Global defmt logger for the host.
+//!
+//! The shared-core tasks log through defmt, which needs a logger and a
+//! timestamp to link.
+//! Decoding defmt frames takes the interned string table of a target ELF, so
+//! on the host they are dropped; the node reports what matters on stderr.
+
+#[defmt::global_logger]
+struct Discard;
+
+unsafe impl defmt::Logger for Discard {
+    fn acquire() {}
+
+    unsafe fn flush() {}
+
+    unsafe fn release() {}
+
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+defmt::timestamp!("{=u64:us}", embassy_time::Instant::now().as_micros());
@@ -0,0 +1,122 @@
+//! Single-thread executor, in place of `embassy-executor` on the host.
+//!
+//! Tasks are polled only when their waker fires, as on the boards; the thread
+//! parks in between. Futures need not be `Send`: the shared-core tasks hold
+//! `&'static` handles and run on this one thread.
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+type BoxedTask = Pin<Box<dyn Future<Output = ()>>>;
+
+struct TaskWaker {
+    ready: AtomicBool,
+    thread: Thread,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ready.store(true, Ordering::Release);
+        self.thread.unpark();
+    }
+}
+
+/// Runs `future` to completion on the calling thread, for what must happen
+/// before the tasks start, such as the address claim.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Arc::new(TaskWaker {
+        ready: AtomicBool::new(true),
+        thread: thread::current(),
+    });
+    let mut future = std::pin::pin!(future);
+    let task_waker = Waker::from(waker.clone());
+    let mut cx = Context::from_waker(&task_waker);
+    loop {
+        if waker.ready.swap(false, Ordering::Acquire) {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        } else {
+            thread::park();
+        }
+    }
+}
+
+/// Spawns tasks on a running [`Executor`], as embassy's `Spawner` does, for a
+/// task that starts others once it is ready, e.g. after its address claim.
+#[derive(Clone, Default)]
+pub struct Spawner {
+    queue: Rc<RefCell<Vec<BoxedTask>>>,
+}
+
+impl Spawner {
+    pub fn spawn(&self, task: impl Future<Output = ()> + 'static) {
+        self.queue.borrow_mut().push(Box::pin(task));
+    }
+}
+
+#[derive(Default)]
+pub struct Executor {
+    spawner: Spawner,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn(&mut self, task: impl Future<Output = ()> + 'static) {
+        self.spawner.spawn(task);
+    }
+
+    pub fn spawner(&self) -> Spawner {
+        self.spawner.clone()
+    }
+
+    /// Runs the tasks on the calling thread until all of them return, which
+    /// node tasks never do.
+    pub fn run(self) {
+        let thread = thread::current();
+        let mut tasks: Vec<(BoxedTask, Arc<TaskWaker>)> = Vec::new();
+
+        loop {
+            // Spawned since the last round, by `spawn` or by a task.
+            let spawned = std::mem::take(&mut *self.spawner.queue.borrow_mut());
+            tasks.extend(spawned.into_iter().map(|task| {
+                let waker = Arc::new(TaskWaker {
+                    // Every task gets a first poll.
+                    ready: AtomicBool::new(true),
+                    thread: thread.clone(),
+                });
+                (task, waker)
+            }));
+            if tasks.is_empty() {
+                return;
+            }
+
+            let mut polled = false;
+            tasks.retain_mut(|(task, waker)| {
+                if !waker.ready.swap(false, Ordering::Acquire) {
+                    return true;
+                }
+                polled = true;
+                let waker = Waker::from(waker.clone());
+                let mut cx = Context::from_waker(&waker);
+                task.as_mut().poll(&mut cx) == Poll::Pending
+            });
+            if !polled && self.spawner.queue.borrow().is_empty() {
+                thread::park();
+            }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! The boards' `instances::inst1..inst5`, for running their scenarios on the
+//! host. inst1, inst3 and inst4 all prefer address 148, so that they fight
+//! over it; `dual_run_1` and `dual_run_2` run inst1 and inst2.
+
+use super::IsoIdentity;
+
+const fn board(preferred_address: u8, unique_number: u32) -> IsoIdentity {
+    IsoIdentity {
+        preferred_address,
+        unique_number,
+        manufacturer_code: 229,
+        device_function: 145,
+        device_class: 75,
+        device_instance: 1,
+        system_instance: 0,
+        industry_group: 4,
+    }
+}
+
+pub const INSTANCES: [(&str, IsoIdentity); 5] = [
+    ("inst1", board(148, 0x1ABCDF)),
+    (
+        "inst2",
+        IsoIdentity {
+            preferred_address: 149,
+            unique_number: 0x1ABCDE,
+            manufacturer_code: 239,
+            device_function: 115,
+            device_class: 74,
+            device_instance: 2,
+            system_instance: 1,
+            industry_group: 5,
+        },
+    ),
+    ("inst3", board(148, 0x1ABCDE)),
+    ("inst4", board(148, 0x1ABCDC)),
+    ("inst5", board(150, 0x1ABCE0)),
+];
+
+/// The identity of instance `name`, `inst1` to `inst5`.
+pub fn instance(name: &str) -> Option<IsoIdentity> {
+    INSTANCES
+        .iter()
+        .find(|(instance, _)| *instance == name)
+        .map(|(_, identity)| *identity)
+}
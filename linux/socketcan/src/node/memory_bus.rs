@@ -0,0 +1,104 @@
+//! In-memory CAN bus, for several nodes in one process without `vcan`.
+//!
+//! Every frame sent on a [`MemoryBus`] is queued for all the other buses
+//! attached to the same [`MemoryHub`], never for the sender itself, as with
+//! SocketCAN sockets on one interface. Delivery is in send order and never
+//! fails: there is no arbitration, no bus load and no error frame.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::future::poll_fn;
+use std::rc::Rc;
+use std::task::{Poll, Waker};
+
+use korri_n2k::protocol::transport::{can_frame::CanFrame, traits::can_bus::CanBus};
+
+use super::bus::RX_QUEUE_DEPTH;
+
+#[derive(Default)]
+struct Port {
+    frames: VecDeque<CanFrame>,
+    waker: Option<Waker>,
+    dropped: u64,
+}
+
+/// The shared medium. Single-threaded, like the [`Executor`](super::Executor)
+/// the nodes run on.
+#[derive(Clone, Default)]
+pub struct MemoryHub {
+    ports: Rc<RefCell<Vec<Rc<RefCell<Port>>>>>,
+}
+
+impl MemoryHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new bus on this hub; it receives what is sent from now on.
+    pub fn attach(&self) -> MemoryBus {
+        let port = Rc::new(RefCell::new(Port::default()));
+        let mut ports = self.ports.borrow_mut();
+        ports.push(Rc::clone(&port));
+        MemoryBus {
+            hub: self.clone(),
+            index: ports.len() - 1,
+            port,
+        }
+    }
+
+    fn deliver(&self, from: usize, frame: &CanFrame) {
+        for (index, port) in self.ports.borrow().iter().enumerate() {
+            if index == from {
+                continue;
+            }
+            let mut port = port.borrow_mut();
+            if port.frames.len() == RX_QUEUE_DEPTH {
+                port.frames.pop_front();
+                port.dropped += 1;
+            }
+            port.frames.push_back(frame.clone());
+            if let Some(waker) = port.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// One node's connection to a [`MemoryHub`].
+pub struct MemoryBus {
+    hub: MemoryHub,
+    index: usize,
+    port: Rc<RefCell<Port>>,
+}
+
+impl MemoryBus {
+    /// Frames dropped because `recv` was not keeping up.
+    pub fn dropped(&self) -> u64 {
+        self.port.borrow().dropped
+    }
+}
+
+impl CanBus for MemoryBus {
+    type Error = Infallible;
+
+    async fn send(&mut self, frame: &CanFrame) -> Result<(), Self::Error> {
+        self.hub.deliver(self.index, frame);
+        Ok(())
+    }
+
+    /// Cancel-safe: a frame leaves the queue only when returned.
+    async fn recv(&mut self) -> Result<CanFrame, Self::Error> {
+        poll_fn(|cx| {
+            let mut port = self.port.borrow_mut();
+            match port.frames.pop_front() {
+                Some(frame) => Poll::Ready(Ok(frame)),
+                None => {
+                    port.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+}
@@ -0,0 +1,109 @@
+//! A korri-n2k node on SocketCAN: what the boards run, on `can0` or `vcan0`.
+//!
+//! ```text
+//! socket ─► reader thread ─► SocketCanBus ─► AddressRunner ◄─ Handle ◄─ shared-core tasks
+//!                                            (claim, defend)
+//! ```
+//!
+//! The boards get their executor and time driver from embassy and their HAL;
+//! here [`Executor`] and a host time driver stand in, so the shared-core tasks
+//! run unchanged. `critical-section` uses its `std` implementation for the
+//! supervisor channels.
+
+pub mod bus;
+pub mod claims;
+mod defmt_sink;
+pub mod executor;
+pub mod instances;
+pub mod memory_bus;
+pub mod tasks;
+mod time_driver;
+
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use korri_n2k::error::ClaimError;
+use korri_n2k::protocol::managment::{
+    address_claiming::AddressClaimStrategy,
+    address_manager::AddressManager,
+    address_supervisor::{AddressHandle, AddressRunner, AddressService, SupervisorCommand},
+    iso_name::IsoName,
+};
+use korri_n2k::protocol::transport::traits::can_bus::CanBus;
+
+pub use bus::{LinuxTimer, SocketCanBus, SocketCanError};
+pub use claims::{ClaimEvent, ClaimLog};
+pub use executor::{block_on, Executor, Spawner};
+pub use instances::{instance, INSTANCES};
+pub use memory_bus::{MemoryBus, MemoryHub};
+pub use tasks::{pgn_task, TASK_PGNS, TOTAL_PGNS};
+
+const COMMAND_CAPACITY: usize = 16;
+
+pub type AddressManagerType<C = SocketCanBus> = AddressManager<'static, C, LinuxTimer>;
+
+pub type ManagerRunner<C = SocketCanBus> =
+    AddressRunner<'static, C, LinuxTimer, COMMAND_CAPACITY, 0>;
+
+pub type Handle = &'static AddressHandle<'static, COMMAND_CAPACITY>;
+
+/// ISO NAME fields and preferred address of a node, as in the boards'
+/// `instances`.
+#[derive(Clone, Copy, Debug)]
+pub struct IsoIdentity {
+    pub preferred_address: u8,
+    pub unique_number: u32,
+    pub manufacturer_code: u16,
+    pub device_function: u8,
+    pub device_class: u8,
+    pub device_instance: u8,
+    pub system_instance: u8,
+    pub industry_group: u8,
+}
+
+impl IsoIdentity {
+    pub fn iso_name(&self) -> IsoName {
+        IsoName::builder()
+            .unique_number(self.unique_number)
+            .manufacturer_code(self.manufacturer_code)
+            .device_function(self.device_function)
+            .device_class(self.device_class)
+            .device_instance(self.device_instance)
+            .system_instance(self.system_instance)
+            .industry_group(self.industry_group)
+            .arbitrary_address_capable(true)
+            .build()
+    }
+}
+
+/// Claims an address for `identity` on `bus`, preferred address first.
+pub async fn claim<C: CanBus>(
+    bus: C,
+    identity: &IsoIdentity,
+) -> Result<AddressManagerType<C>, ClaimError<C::Error>> {
+    AddressManager::new(
+        bus,
+        LinuxTimer::new(),
+        identity.iso_name().raw(),
+        AddressClaimStrategy::Arbitrary {
+            preferred: identity.preferred_address,
+        },
+    )
+    .await
+}
+
+/// Splits a claimed manager into the runner, to spawn, and the handle the
+/// shared-core tasks send through. Both live for the rest of the process.
+pub fn init_manager<C: CanBus + 'static>(
+    manager: AddressManagerType<C>,
+) -> (ManagerRunner<C>, Handle) {
+    let chan: &'static _ = Box::leak(Box::new(Channel::<
+        CriticalSectionRawMutex,
+        SupervisorCommand,
+        COMMAND_CAPACITY,
+    >::new()));
+    let service = AddressService::<_, _, COMMAND_CAPACITY, 0>::new(manager, Some(chan), None);
+    let parts = service.into_parts();
+    let handle = parts
+        .handle
+        .expect("command channel ensures handle availability");
+    (parts.runner, Box::leak(Box::new(handle)))
+}
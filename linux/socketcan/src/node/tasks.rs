@@ -0,0 +1,44 @@
+//! The `shared-core::pgns` tasks by PGN, as the boards' `tasks` modules spawn
+//! them.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use shared_core::pgns;
+
+use super::Handle;
+
+/// What the `total` firmware runs.
+pub const TOTAL_PGNS: [u32; 4] = [127503, 129025, 127488, 127489];
+
+/// Every PGN with a task.
+pub const TASK_PGNS: [u32; 15] = [
+    126985, 126993, 127237, 127245, 127488, 127489, 127503, 128259, 128267, 129025, 129038, 129039,
+    129044, 129284, 130310,
+];
+
+/// The task sending `pgn` through `handle`, `None` if shared-core has none.
+pub fn pgn_task(pgn: u32, handle: Handle) -> Option<Pin<Box<dyn Future<Output = ()>>>> {
+    Some(match pgn {
+        126985 => Box::pin(pgns::alert_text_126985::task_alert_text_126985(handle)),
+        126993 => Box::pin(pgns::heartbeat_126993::task_heartbeat_126993(handle)),
+        127237 => Box::pin(pgns::heading_control_127237::task_heading_control_127237(
+            handle,
+        )),
+        127245 => Box::pin(pgns::rudder_127245::task_rudder_127245(handle)),
+        127488 => Box::pin(pgns::engine_127488::task_engine_127488(handle)),
+        127489 => Box::pin(pgns::engine_127489::task_engine_127489(handle)),
+        127503 => Box::pin(pgns::ac_input_127503::task_ac_input_127503(handle)),
+        128259 => Box::pin(pgns::speed_128259::task_speed_128259(handle)),
+        128267 => Box::pin(pgns::depth_128267::task_depth_128267(handle)),
+        129025 => Box::pin(pgns::position_129025::task_position_129025(handle)),
+        129038 => Box::pin(pgns::ais_class_a_129038::task_ais_class_a_129038(handle)),
+        129039 => Box::pin(pgns::ais_class_b_129039::task_ais_class_b_129039(handle)),
+        129044 => Box::pin(pgns::datum_129044::task_datum_129044(handle)),
+        129284 => Box::pin(pgns::navigation_129284::task_navigation_129284(handle)),
+        130310 => Box::pin(pgns::environmental_130310::task_environmental_130310(
+            handle,
+        )),
+        _ => return None,
+    })
+}
@@ -0,0 +1,76 @@
+//! `embassy-time` driver on the host clock.
+//!
+//! embassy-time's own `std` driver needs `embassy-time-queue-utils`; this one
+//! keeps its wakers in a plain list, which is plenty for the handful of timers
+//! a node runs. Ticks are microseconds since the first call.
+
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::task::Waker;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use embassy_time_driver::Driver;
+
+struct HostDriver {
+    zero: OnceLock<Instant>,
+    alarms: Mutex<Vec<(u64, Waker)>>,
+    changed: Condvar,
+}
+
+embassy_time_driver::time_driver_impl!(static DRIVER: HostDriver = HostDriver {
+    zero: OnceLock::new(),
+    alarms: Mutex::new(Vec::new()),
+    changed: Condvar::new(),
+});
+
+impl HostDriver {
+    fn zero(&self) -> Instant {
+        *self.zero.get_or_init(|| {
+            thread::Builder::new()
+                .name("embassy-time".into())
+                .spawn(alarm_thread)
+                .expect("spawn the timer thread");
+            Instant::now()
+        })
+    }
+}
+
+impl Driver for HostDriver {
+    fn now(&self) -> u64 {
+        self.zero().elapsed().as_micros() as u64
+    }
+
+    fn schedule_wake(&self, at: u64, waker: &Waker) {
+        self.zero();
+        let mut alarms = self.alarms.lock().unwrap();
+        // A timer polled again re-registers the same waker: keep one entry,
+        // the earliest.
+        match alarms.iter_mut().find(|(_, known)| known.will_wake(waker)) {
+            Some(alarm) => alarm.0 = alarm.0.min(at),
+            None => alarms.push((at, waker.clone())),
+        }
+        self.changed.notify_one();
+    }
+}
+
+fn alarm_thread() {
+    let zero = DRIVER.zero();
+    let mut alarms = DRIVER.alarms.lock().unwrap();
+    loop {
+        let now = zero.elapsed().as_micros() as u64;
+        alarms.retain(|(at, waker)| {
+            if *at <= now {
+                waker.wake_by_ref();
+            }
+            *at > now
+        });
+
+        alarms = match alarms.iter().map(|(at, _)| *at).min() {
+            Some(next) => {
+                let wait = Duration::from_micros(next - now);
+                DRIVER.changed.wait_timeout(alarms, wait).unwrap().0
+            }
+            None => DRIVER.changed.wait(alarms).unwrap(),
+        };
+    }
+}
@@ -0,0 +1,214 @@
+//! pcapng export for Wireshark, which dissects NMEA 2000 on top of SocketCAN.
+//!
+//! ```text
+//! SHB ─ IDB (bitrate) ─ EPB ─ EPB ─ … ─ ISB (target counters) ─ EPB ─ …
+//! ```
+//!
+//! Frames are `LINKTYPE_CAN_SOCKETCAN` packets with microsecond timestamps.
+//! A bitrate change opens a new interface. Target counter snapshots become
+//! Interface Statistics Blocks: Wireshark totals their drop counts, and their
+//! comment keeps the full breakdown.
+
+use std::io::{self, Write};
+
+use crate::capture::reader::Event;
+use crate::capture::wire::{StatsSnapshot, TimestampedFrame};
+
+pub const LINKTYPE_CAN_SOCKETCAN: u16 = 227;
+
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const BLOCK_INTERFACE: u32 = 0x0000_0001;
+const BLOCK_STATISTICS: u32 = 0x0000_0005;
+const BLOCK_ENHANCED_PACKET: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const OPT_END: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const IF_NAME: u16 = 2;
+const IF_SPEED: u16 = 8;
+const IF_TSRESOL: u16 = 9;
+const ISB_IFRECV: u16 = 4;
+const ISB_IFDROP: u16 = 5;
+const ISB_OSDROP: u16 = 7;
+
+/// `struct can_frame`: 32-bit id with flags, big-endian, length, 3 reserved
+/// bytes, 8 data bytes.
+const SOCKETCAN_FRAME: usize = 16;
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_RTR_FLAG: u32 = 0x4000_0000;
+
+/// Streams a capture out as pcapng, one block per write: the output can be a
+/// pipe into `wireshark -k -i -`.
+pub struct PcapngWriter<W: Write> {
+    inner: W,
+    name: String,
+    /// Current interface and its bitrate, once one has been described.
+    interface: Option<(u32, Option<u32>)>,
+    interfaces: u32,
+    last_timestamp_us: u64,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    /// Writes the section header. `name` labels every interface.
+    pub fn new(inner: W, name: &str) -> io::Result<Self> {
+        let mut writer = Self {
+            inner,
+            name: name.to_string(),
+            interface: None,
+            interfaces: 0,
+            last_timestamp_us: 0,
+        };
+        let mut body = Vec::new();
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        // Section length unknown: the file is written as a stream.
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        push_option(&mut body, OPT_END, &[]);
+        writer.block(BLOCK_SECTION_HEADER, &body)?;
+        Ok(writer)
+    }
+
+    /// Describes the bus the next frames come from. Nothing is written if the
+    /// bitrate is the current one.
+    pub fn set_bitrate(&mut self, bitrate: Option<u32>) -> io::Result<()> {
+        if let Some((_, current)) = self.interface {
+            if current == bitrate || bitrate.is_none() {
+                return Ok(());
+            }
+        }
+
+        let mut body = Vec::new();
+        body.extend_from_slice(&LINKTYPE_CAN_SOCKETCAN.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&(SOCKETCAN_FRAME as u32).to_le_bytes());
+        push_option(&mut body, IF_NAME, self.name.as_bytes());
+        // 10^-6 s, written out although it is the default.
+        push_option(&mut body, IF_TSRESOL, &[6]);
+        if let Some(bitrate) = bitrate {
+            push_option(&mut body, IF_SPEED, &(bitrate as u64).to_le_bytes());
+        }
+        push_option(&mut body, OPT_END, &[]);
+        self.block(BLOCK_INTERFACE, &body)?;
+
+        self.interface = Some((self.interfaces, bitrate));
+        self.interfaces += 1;
+        Ok(())
+    }
+
+    pub fn write_frame(&mut self, frame: &TimestampedFrame) -> io::Result<()> {
+        let interface = self.current_interface()?;
+        self.last_timestamp_us = frame.timestamp_us;
+
+        let mut id = frame.id;
+        if frame.is_extended() {
+            id |= CAN_EFF_FLAG;
+        }
+        if frame.is_remote() {
+            id |= CAN_RTR_FLAG;
+        }
+        let mut packet = [0u8; SOCKETCAN_FRAME];
+        packet[0..4].copy_from_slice(&id.to_be_bytes());
+        packet[4] = frame.len.min(8);
+        packet[8..16].copy_from_slice(&frame.data);
+
+        let mut body = Vec::with_capacity(20 + SOCKETCAN_FRAME);
+        body.extend_from_slice(&interface.to_le_bytes());
+        push_timestamp(&mut body, frame.timestamp_us);
+        body.extend_from_slice(&(SOCKETCAN_FRAME as u32).to_le_bytes());
+        body.extend_from_slice(&(SOCKETCAN_FRAME as u32).to_le_bytes());
+        body.extend_from_slice(&packet);
+        self.block(BLOCK_ENHANCED_PACKET, &body)
+    }
+
+    /// Target counters, dated from the last frame as the target sends no time
+    /// of its own. Counters are cumulative since the target booted.
+    pub fn write_stats(&mut self, stats: &StatsSnapshot) -> io::Result<()> {
+        let interface = self.current_interface()?;
+        let comment = format!(
+            "target counters: {} frames received, {} channel drops, {} hardware \
+             overruns, {} sink drops, {} soft errors, {} bus-off, peak channel \
+             depth {}, longest backlog run {}",
+            stats.frames_rx,
+            stats.channel_drops,
+            stats.hw_overruns,
+            stats.sink_drops,
+            stats.soft_errors,
+            stats.bus_off,
+            stats.max_channel_depth,
+            stats.max_backlog_run
+        );
+
+        let mut body = Vec::new();
+        body.extend_from_slice(&interface.to_le_bytes());
+        push_timestamp(&mut body, self.last_timestamp_us);
+        push_option(&mut body, OPT_COMMENT, comment.as_bytes());
+        push_option(
+            &mut body,
+            ISB_IFRECV,
+            &(stats.frames_rx as u64).to_le_bytes(),
+        );
+        // Lost by the controller, then lost by the firmware on the way to USB.
+        push_option(
+            &mut body,
+            ISB_IFDROP,
+            &(stats.hw_overruns as u64).to_le_bytes(),
+        );
+        let os_drops = stats.channel_drops as u64 + stats.sink_drops as u64;
+        push_option(&mut body, ISB_OSDROP, &os_drops.to_le_bytes());
+        push_option(&mut body, OPT_END, &[]);
+        self.block(BLOCK_STATISTICS, &body)
+    }
+
+    /// Writes what a [`CaptureReader`](crate::capture::CaptureReader) event
+    /// carries for Wireshark; other events are skipped.
+    pub fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        match event {
+            Event::Header(header) => self.set_bitrate(Some(header.bitrate)),
+            Event::Frame(frame) => self.write_frame(frame),
+            Event::Stats(stats) => self.write_stats(stats),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Frames seen before any header go to an interface of unknown bitrate.
+    fn current_interface(&mut self) -> io::Result<u32> {
+        if self.interface.is_none() {
+            self.set_bitrate(None)?;
+        }
+        Ok(self.interface.map_or(0, |(id, _)| id))
+    }
+
+    /// type | total length | body | total length, body padded to 32 bits.
+    fn block(&mut self, kind: u32, body: &[u8]) -> io::Result<()> {
+        let padding = (4 - body.len() % 4) % 4;
+        let total = (12 + body.len() + padding) as u32;
+        self.inner.write_all(&kind.to_le_bytes())?;
+        self.inner.write_all(&total.to_le_bytes())?;
+        self.inner.write_all(body)?;
+        self.inner.write_all(&[0u8; 3][..padding])?;
+        self.inner.write_all(&total.to_le_bytes())
+    }
+}
+
+/// code | length | value, padded to 32 bits.
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    body.resize(body.len() + (4 - value.len() % 4) % 4, 0);
+}
+
+/// High then low 32 bits, in units of the interface resolution.
+fn push_timestamp(body: &mut Vec<u8>, timestamp_us: u64) {
+    body.extend_from_slice(&((timestamp_us >> 32) as u32).to_le_bytes());
+    body.extend_from_slice(&(timestamp_us as u32).to_le_bytes());
+}
@@ -0,0 +1,266 @@
+//! PGN names and identifier helpers shared by the host tools.
+
+use std::num::ParseIntError;
+
+use korri_n2k::core::PgnDescriptor;
+use korri_n2k::error::DeserializationError;
+use korri_n2k::infra::codec::traits::{FieldAccess, PgnData};
+use korri_n2k::protocol::lookups;
+use korri_n2k::protocol::messages::*;
+use shared_core::fast_packet;
+
+/// Destination of a broadcast (PDU2) message.
+pub const BROADCAST: u8 = 0xFF;
+/// Source address of a device without one, claiming it cannot get any.
+pub const NULL_ADDRESS: u8 = 254;
+pub const ADDRESS_CLAIM_PGN: u32 = 60928;
+
+/// A decimal number, or hexadecimal after `0x`, as PGNs and NAME fields are
+/// given on the command line.
+pub fn parse_number(text: &str) -> Result<u32, ParseIntError> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+}
+
+const PGN_NAMES: &[(u32, &str)] = &[
+    (59392, "ISO Acknowledgement"),
+    (59904, "ISO Request"),
+    (60160, "ISO TP Data Transfer"),
+    (60416, "ISO TP Connection Mgmt"),
+    (60928, "ISO Address Claim"),
+    (65240, "ISO Commanded Address"),
+    (126208, "NMEA Group Function"),
+    (126983, "Alert"),
+    (126984, "Alert Response"),
+    (126985, "Alert Text"),
+    (126992, "System Time"),
+    (126993, "Heartbeat"),
+    (126996, "Product Information"),
+    (126998, "Configuration Information"),
+    (127233, "Man Overboard"),
+    (127237, "Heading/Track Control"),
+    (127245, "Rudder"),
+    (127250, "Vessel Heading"),
+    (127251, "Rate of Turn"),
+    (127252, "Heave"),
+    (127257, "Attitude"),
+    (127258, "Magnetic Variation"),
+    (127488, "Engine, Rapid Update"),
+    (127489, "Engine, Dynamic"),
+    (127493, "Transmission, Dynamic"),
+    (127497, "Trip Parameters, Engine"),
+    (127501, "Binary Switch Bank"),
+    (127503, "AC Input Status"),
+    (127504, "AC Output Status"),
+    (127505, "Fluid Level"),
+    (127506, "DC Detailed Status"),
+    (127507, "Charger Status"),
+    (127508, "Battery Status"),
+    (127513, "Battery Configuration"),
+    (128259, "Speed, Water Referenced"),
+    (128267, "Water Depth"),
+    (128275, "Distance Log"),
+    (129025, "Position, Rapid Update"),
+    (129026, "COG & SOG, Rapid Update"),
+    (129029, "GNSS Position Data"),
+    (129033, "Local Time Offset"),
+    (129038, "AIS Class A Position"),
+    (129039, "AIS Class B Position"),
+    (129040, "AIS Class B Extended"),
+    (129041, "AIS Aids to Navigation"),
+    (129044, "Datum"),
+    (129283, "Cross Track Error"),
+    (129284, "Navigation Data"),
+    (129285, "Route/WP Information"),
+    (129291, "Set & Drift, Rapid"),
+    (129539, "GNSS DOPs"),
+    (129540, "GNSS Sats in View"),
+    (129793, "AIS UTC and Date Report"),
+    (129794, "AIS Class A Static and Voyage"),
+    (129809, "AIS Class B CS Static A"),
+    (129810, "AIS Class B CS Static B"),
+    (130306, "Wind Data"),
+    (130310, "Environmental Parameters"),
+    (130311, "Environmental Parameters"),
+    (130312, "Temperature"),
+    (130313, "Humidity"),
+    (130314, "Actual Pressure"),
+    (130316, "Temperature, Extended"),
+    (130577, "Direction Data"),
+];
+
+/// Short human name of a PGN, `"—"` when unknown.
+pub fn pgn_name(pgn: u32) -> &'static str {
+    if let Ok(index) = PGN_NAMES.binary_search_by_key(&pgn, |&(p, _)| p) {
+        return PGN_NAMES[index].1;
+    }
+    // J1939/N2K proprietary ranges: no public definition, but not unknown.
+    // Proprietary A is two single PDU1 PGNs, not a range: 0xF000-0xFEFF are
+    // standard ISO/J1939 PDU2 PGNs.
+    match pgn {
+        61184 | 126720 => "proprietary A",
+        65280..=65535 | 130816..=131071 => "proprietary B",
+        _ => "—",
+    }
+}
+
+/// Builds the table of the PGNs korri-n2k has a struct for, and the decoder
+/// dispatching on it.
+macro_rules! korri_pgns {
+    ($($message:ident::$descriptor:ident),* $(,)?) => {
+        /// Sorted by PGN.
+        const DESCRIPTORS: &[&PgnDescriptor] = &[$(&$message::$descriptor),*];
+
+        /// `payload` decoded into the korri-n2k struct of `pgn`, `None` when
+        /// there is no struct for it.
+        pub fn decode(
+            pgn: u32,
+            payload: &[u8],
+        ) -> Option<Result<Box<dyn FieldAccess>, DeserializationError>> {
+            $(
+                if pgn == $message::$descriptor.id {
+                    return Some(
+                        $message::from_payload(payload)
+                            .map(|message| Box::new(message) as Box<dyn FieldAccess>),
+                    );
+                }
+            )*
+            None
+        }
+    };
+}
+
+// PGN 60416 and 130821 are left out: several structs share each of them, told
+// apart by the payload.
+korri_pgns! {
+    Pgn59904::PGN_59904_DESCRIPTOR,
+    Pgn60160::PGN_60160_DESCRIPTOR,
+    Pgn60928::PGN_60928_DESCRIPTOR,
+    Pgn126985::PGN_126985_DESCRIPTOR,
+    Pgn126992::PGN_126992_DESCRIPTOR,
+    Pgn126993::PGN_126993_DESCRIPTOR,
+    Pgn126996::PGN_126996_DESCRIPTOR,
+    Pgn126998::PGN_126998_DESCRIPTOR,
+    Pgn127237::PGN_127237_DESCRIPTOR,
+    Pgn127245::PGN_127245_DESCRIPTOR,
+    Pgn127250::PGN_127250_DESCRIPTOR,
+    Pgn127251::PGN_127251_DESCRIPTOR,
+    Pgn127257::PGN_127257_DESCRIPTOR,
+    Pgn127488::PGN_127488_DESCRIPTOR,
+    Pgn127489::PGN_127489_DESCRIPTOR,
+    Pgn127497::PGN_127497_DESCRIPTOR,
+    Pgn127503::PGN_127503_DESCRIPTOR,
+    Pgn127505::PGN_127505_DESCRIPTOR,
+    Pgn127508::PGN_127508_DESCRIPTOR,
+    Pgn127750::PGN_127750_DESCRIPTOR,
+    Pgn128001::PGN_128001_DESCRIPTOR,
+    Pgn128259::PGN_128259_DESCRIPTOR,
+    Pgn128267::PGN_128267_DESCRIPTOR,
+    Pgn128275::PGN_128275_DESCRIPTOR,
+    Pgn129025::PGN_129025_DESCRIPTOR,
+    Pgn129026::PGN_129026_DESCRIPTOR,
+    Pgn129029::PGN_129029_DESCRIPTOR,
+    Pgn129038::PGN_129038_DESCRIPTOR,
+    Pgn129039::PGN_129039_DESCRIPTOR,
+    Pgn129040::PGN_129040_DESCRIPTOR,
+    Pgn129044::PGN_129044_DESCRIPTOR,
+    Pgn129283::PGN_129283_DESCRIPTOR,
+    Pgn129284::PGN_129284_DESCRIPTOR,
+    Pgn129540::PGN_129540_DESCRIPTOR,
+    Pgn129794::PGN_129794_DESCRIPTOR,
+    Pgn129809::PGN_129809_DESCRIPTOR,
+    Pgn129810::PGN_129810_DESCRIPTOR,
+    Pgn130306::PGN_130306_DESCRIPTOR,
+    Pgn130310::PGN_130310_DESCRIPTOR,
+    Pgn130311::PGN_130311_DESCRIPTOR,
+}
+
+/// korri-n2k description of a PGN: fields, length, transport.
+pub fn descriptor(pgn: u32) -> Option<&'static PgnDescriptor> {
+    DESCRIPTORS
+        .binary_search_by_key(&pgn, |d| d.id)
+        .ok()
+        .map(|index| DESCRIPTORS[index])
+}
+
+/// Whether a PGN travels as fast packets, as korri-n2k describes it or from
+/// the list shared with the firmware.
+pub fn is_fast_packet(pgn: u32) -> bool {
+    descriptor(pgn).map_or_else(|| fast_packet::is_fast_packet(pgn), |d| d.fastpacket)
+}
+
+/// Builds [`lookup_name`] over the korri-n2k lookup enums, each converted from
+/// its raw integer type.
+macro_rules! korri_lookups {
+    ($($lookup:ident: $raw:ty),* $(,)?) => {
+        /// Name of `value` in the korri-n2k lookup `lookup`, as its
+        /// descriptors call it (`enum_direct_name`), e.g. `EngineInstance`.
+        /// `None` for a value or lookup without a name.
+        pub fn lookup_name(lookup: &str, value: u64) -> Option<String> {
+            match lookup {
+                $(
+                    stringify!($lookup) => {
+                        let raw = <$raw>::try_from(value).ok()?;
+                        lookups::$lookup::try_from(raw).ok().map(|v| format!("{v:?}"))
+                    }
+                )*
+                _ => None,
+            }
+        }
+    };
+}
+
+// The lookups of the PGNs above.
+korri_lookups! {
+    AcLine: u8,
+    Acceptability: u8,
+    AisBand: u8,
+    AisCommunicationState: u8,
+    AisMessageId: u8,
+    AisMode: u8,
+    AisSpecialManeuver: u8,
+    AisTransceiver: u8,
+    AisType: u8,
+    AisVersion: u8,
+    AlertCategory: u8,
+    AlertLanguageId: u8,
+    AlertType: u8,
+    Available: u8,
+    BearingMode: u8,
+    CertificationLevel: u8,
+    ControllerState: u8,
+    ConverterState: u8,
+    DeviceClass: u8,
+    DeviceFunction: u16,
+    DirectionReference: u8,
+    DirectionRudder: u8,
+    EngineInstance: u8,
+    EquipmentStatus: u8,
+    Gns: u8,
+    GnsIntegrity: u8,
+    GnsMethod: u8,
+    GoodWarningError: u8,
+    HumiditySource: u8,
+    IndustryCode: u8,
+    ManufacturerCode: u16,
+    NavStatus: u8,
+    PositionAccuracy: u8,
+    PositionFixDevice: u8,
+    RaimFlag: u8,
+    RangeResidualMode: u8,
+    RepeatIndicator: u8,
+    ResidualMode: u8,
+    SatelliteStatus: u8,
+    ShipType: u8,
+    SteeringMode: u8,
+    SystemTime: u8,
+    TankType: u8,
+    TemperatureSource: u8,
+    TimeStamp: u8,
+    TurnMode: u8,
+    WaterReference: u8,
+    WindReference: u8,
+    YesNo: u8,
+}
@@ -0,0 +1,190 @@
+//! Captures played back onto a bus: the frames a log holds, whichever format
+//! it is in, which of them to send, and when.
+//!
+//! Every format comes down to [`TimestampedFrame`]s. Their timestamps are
+//! only compared with each other, so target uptime, Unix time and time of day
+//! all do.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use flate2::read::MultiGzDecoder;
+use korri_n2k::protocol::transport::can_id::CanId;
+
+use crate::actisense::ActisenseReader;
+use crate::candump::CandumpReader;
+use crate::capture::wire::TimestampedFrame;
+use crate::capture::{CaptureReader, Event};
+use crate::ebl::EblReader;
+use crate::gateway::Filter;
+use crate::segment::SegmentHeader;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureFormat {
+    /// KN2KCAP, either wire version, as `kn2kcap` reads it.
+    Kn2kcap,
+    /// Actisense EBL, as NMEA Reader writes it.
+    Ebl,
+    /// `candump -l`.
+    Candump,
+    /// Actisense ASCII, as `socketcan-receiver` prints it.
+    Actisense,
+}
+
+impl CaptureFormat {
+    /// Format of the file at `path` starting with `head`: a KN2KCAP marker,
+    /// then the `.ebl` extension, then the shape of the first text line.
+    /// Anything else is taken as KN2KCAP, whose reader skips what precedes
+    /// its first marker.
+    pub fn detect(path: &Path, head: &[u8]) -> Self {
+        if head.starts_with(b"KN2KCAP") {
+            return Self::Kn2kcap;
+        }
+        if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("ebl"))
+        {
+            return Self::Ebl;
+        }
+        let line = head.trim_ascii_start();
+        match line {
+            [b'(', ..] => Self::Candump,
+            [h, m, b':', ..] if h.is_ascii_digit() && m.is_ascii_digit() => Self::Actisense,
+            _ => Self::Kn2kcap,
+        }
+    }
+}
+
+impl FromStr for CaptureFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "kn2kcap" => Ok(Self::Kn2kcap),
+            "ebl" => Ok(Self::Ebl),
+            "candump" => Ok(Self::Candump),
+            "actisense" => Ok(Self::Actisense),
+            _ => Err(format!(
+                "unknown format `{s}`: kn2kcap, ebl, candump or actisense"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CaptureFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Kn2kcap => "kn2kcap",
+            Self::Ebl => "ebl",
+            Self::Candump => "candump",
+            Self::Actisense => "actisense",
+        })
+    }
+}
+
+/// Frames of a capture, in file order.
+pub type Frames = Box<dyn Iterator<Item = io::Result<TimestampedFrame>>>;
+
+/// Opens the capture at `path`, in `format` or the one it looks like. A
+/// `.gz` file is decompressed on the fly, and a recorder segment header
+/// skipped.
+pub fn open_capture(
+    path: &Path,
+    format: Option<CaptureFormat>,
+) -> io::Result<(CaptureFormat, Frames)> {
+    let file = File::open(path)?;
+    let (inner, path): (Box<dyn Read>, PathBuf) = if path.extension().is_some_and(|ext| ext == "gz")
+    {
+        (Box::new(MultiGzDecoder::new(file)), path.with_extension(""))
+    } else {
+        (Box::new(file), path.to_path_buf())
+    };
+    let mut reader = BufReader::new(inner);
+    let header = SegmentHeader::read(&mut reader)?;
+    let format = match (format, header) {
+        (Some(format), _) => format,
+        (None, Some(header)) => header.format,
+        (None, None) => CaptureFormat::detect(&path, reader.fill_buf()?),
+    };
+    let frames: Frames = match format {
+        CaptureFormat::Kn2kcap => {
+            Box::new(CaptureReader::new(reader).filter_map(|event| match event {
+                Ok(Event::Frame(frame)) => Some(Ok(frame)),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            }))
+        }
+        CaptureFormat::Ebl => Box::new(EblReader::new(reader)),
+        CaptureFormat::Candump => {
+            Box::new(CandumpReader::new(reader).map(|record| record.map(|r| r.frame)))
+        }
+        CaptureFormat::Actisense => Box::new(ActisenseReader::new(reader)),
+    };
+    Ok((format, frames))
+}
+
+/// Frames to send: a window of the capture, then PGNs and sources.
+#[derive(Clone, Debug, Default)]
+pub struct Selection {
+    /// Start of the window, from the first frame of the capture.
+    pub from_us: u64,
+    /// End of the window, the end of the capture if `None`.
+    pub to_us: Option<u64>,
+    /// Standard frames hold no PGN: any PGN or source narrows them out.
+    pub filter: Filter,
+}
+
+impl Selection {
+    /// Whether a frame at `position_us`, see [`Timeline`], is in the window.
+    pub fn in_window(&self, position_us: u64) -> bool {
+        position_us >= self.from_us && !self.is_past(position_us)
+    }
+
+    /// Whether the window is over at `position_us`.
+    pub fn is_past(&self, position_us: u64) -> bool {
+        self.to_us.is_some_and(|to| position_us > to)
+    }
+
+    pub fn accepts(&self, frame: &TimestampedFrame) -> bool {
+        if frame.is_extended() {
+            let id = CanId(frame.id);
+            self.filter.accepts(id.pgn(), id.source_address())
+        } else {
+            self.filter.pgns.is_empty() && self.filter.sources.is_empty()
+        }
+    }
+}
+
+/// Where each frame falls in the replay, from the capture timestamps.
+///
+/// Gaps between frames are kept, except those beyond `max_gap_us`, while the
+/// logger was off for instance, which shrink to it. A step back, a target
+/// restart or lines slightly out of order, counts as no gap at all.
+pub struct Timeline {
+    max_gap_us: u64,
+    last_us: Option<u64>,
+    position_us: u64,
+}
+
+impl Timeline {
+    pub fn new(max_gap_us: u64) -> Self {
+        Self {
+            max_gap_us,
+            last_us: None,
+            position_us: 0,
+        }
+    }
+
+    /// Position of a frame stamped `timestamp_us`, from the first frame.
+    pub fn advance(&mut self, timestamp_us: u64) -> u64 {
+        if let Some(last) = self.last_us {
+            let gap = timestamp_us.saturating_sub(last).min(self.max_gap_us);
+            self.position_us += gap;
+        }
+        self.last_us = Some(timestamp_us);
+        self.position_us
+    }
+}
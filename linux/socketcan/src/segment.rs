@@ -0,0 +1,121 @@
+//! Segments of a long recording: one file each, opening with a text header
+//! that says where and when it was recorded, so that segments can be put back
+//! in order and together.
+//!
+//! ```text
+//! # n2k-recorder segment 12
+//! # interface can0
+//! # bitrate 250000
+//! # start 2026-10-17T08:00:00.000000Z
+//! # host deckpi
+//! # format kn2kcap
+//! ```
+//!
+//! The capture follows, KN2KCAP or candump, timestamps in Unix time. To a
+//! candump reader the header is comments; a KN2KCAP reader skips it as it
+//! would console output before the first marker, unless [`SegmentHeader::read`]
+//! took it first.
+
+use std::io::{self, BufRead, Write};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::replay::CaptureFormat;
+
+const MARKER: &[u8] = b"# n2k-recorder segment ";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SegmentHeader {
+    /// From 1, in the order the recorder wrote them.
+    pub index: u32,
+    pub interface: String,
+    /// Bus bitrate, bit/s, when the interface reports one.
+    pub bitrate: Option<u32>,
+    /// Wall-clock time the segment was opened.
+    pub start: DateTime<Utc>,
+    pub host: String,
+    pub format: CaptureFormat,
+}
+
+impl SegmentHeader {
+    /// `can0-20261017T080000Z-00012.kn2kcap`: names sort in recording order.
+    pub fn file_name(&self) -> String {
+        let extension = match self.format {
+            CaptureFormat::Candump => "log",
+            _ => "kn2kcap",
+        };
+        format!(
+            "{}-{}-{:05}.{extension}",
+            self.interface,
+            self.start.format("%Y%m%dT%H%M%SZ"),
+            self.index
+        )
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(MARKER)?;
+        writeln!(out, "{}", self.index)?;
+        writeln!(out, "# interface {}", self.interface)?;
+        match self.bitrate {
+            Some(bitrate) => writeln!(out, "# bitrate {bitrate}")?,
+            None => writeln!(out, "# bitrate unknown")?,
+        }
+        writeln!(
+            out,
+            "# start {}",
+            self.start.to_rfc3339_opts(SecondsFormat::Micros, true)
+        )?;
+        writeln!(out, "# host {}", self.host)?;
+        writeln!(out, "# format {}", self.format)
+    }
+
+    /// Takes the header off the start of `input`. `None`, nothing consumed,
+    /// when there is none.
+    pub fn read(input: &mut impl BufRead) -> io::Result<Option<Self>> {
+        if !input.fill_buf()?.starts_with(MARKER) {
+            return Ok(None);
+        }
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+        let mut index = None;
+        let mut interface = None;
+        let mut bitrate = None;
+        let mut start = None;
+        let mut host = None;
+        let mut format = None;
+
+        let mut line = String::new();
+        while input.fill_buf()?.first() == Some(&b'#') {
+            line.clear();
+            input.read_line(&mut line)?;
+            let text = line.trim_end();
+            if let Some(value) = text.strip_prefix("# n2k-recorder segment ") {
+                index = value.parse().ok();
+                continue;
+            }
+            let Some((key, value)) = text.trim_start_matches("# ").split_once(' ') else {
+                continue;
+            };
+            match key {
+                "interface" => interface = Some(value.to_string()),
+                "bitrate" => bitrate = value.parse().ok(),
+                "start" => {
+                    start = DateTime::parse_from_rfc3339(value)
+                        .ok()
+                        .map(|t| t.with_timezone(&Utc))
+                }
+                "host" => host = Some(value.to_string()),
+                "format" => format = value.parse().ok(),
+                // Left for later versions.
+                _ => {}
+            }
+        }
+        Ok(Some(Self {
+            index: index.ok_or_else(|| invalid("segment header: bad index"))?,
+            interface: interface.ok_or_else(|| invalid("segment header: no interface"))?,
+            bitrate,
+            start: start.ok_or_else(|| invalid("segment header: bad start time"))?,
+            host: host.unwrap_or_default(),
+            format: format.ok_or_else(|| invalid("segment header: bad format"))?,
+        }))
+    }
+}
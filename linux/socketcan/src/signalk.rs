@@ -0,0 +1,554 @@
+//! SignalK deltas out of NMEA 2000 messages.
+//!
+//! ```text
+//! {"context":"vessels.self","updates":[{"$source":"can0.c0788c00e7e04312",
+//!   "source":{"label":"can0","type":"NMEA2000","pgn":129025,"src":"35",
+//!   "canName":"c0788c00e7e04312"},"timestamp":"2024-06-01T10:00:00.000Z",
+//!   "values":[{"path":"navigation.position","value":{"latitude":47.1,
+//!   "longitude":-2.3}}]}]}
+//! ```
+//!
+//! Paths and units follow the SignalK specification: SI, angles in radians,
+//! ratios instead of percentages. A device is named by its ISO NAME, learnt
+//! from its address claims, and by its address until one is seen. AIS
+//! reports describe other vessels, under their MMSI.
+//!
+//! Unavailable fields are not NaN once decoded but the largest raw value, well
+//! past any real one: values are kept below a plausibility limit.
+
+use std::f64::consts::PI;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use korri_n2k::infra::codec::traits::PgnData;
+use korri_n2k::protocol::lookups::{
+    DirectionReference, HumiditySource, TemperatureSource, WindReference,
+};
+use korri_n2k::protocol::messages::*;
+use serde_json::{json, Value};
+
+use crate::messages::Message;
+
+/// SignalK version the deltas follow.
+pub const SIGNALK_VERSION: &str = "1.7.0";
+
+/// 0xFFFF radians ×10⁻⁴, unavailable.
+const ANGLE: f32 = 6.3;
+/// 0x7FFF radians ×10⁻⁴.
+const SIGNED_ANGLE: f32 = 3.2;
+/// 0xFFFF m/s ×10⁻².
+const SPEED: f32 = 650.0;
+/// 0xFFFF K ×10⁻².
+const TEMPERATURE: f32 = 650.0;
+/// 0xFFFF hPa.
+const PRESSURE: f32 = 6_500_000.0;
+/// 0x7F %.
+const PERCENT: i8 = 126;
+
+type Values = Vec<(String, Value)>;
+
+/// Keeps the NAME of every device on the bus and turns messages into deltas.
+pub struct SignalKConverter {
+    label: String,
+    context: String,
+    names: [Option<u64>; 256],
+}
+
+impl SignalKConverter {
+    /// `label` names the bus in `$source`. Without an MMSI, the own vessel is
+    /// `vessels.self`.
+    pub fn new(label: &str, mmsi: Option<u32>) -> Self {
+        Self {
+            label: label.to_string(),
+            context: mmsi.map_or("vessels.self".to_string(), vessel_context),
+            names: [None; 256],
+        }
+    }
+
+    /// Context of the own vessel.
+    pub fn context(&self) -> &str {
+        &self.context
+    }
+
+    /// ISO NAME of the device at `source`, once it has claimed its address.
+    pub fn name(&self, source: u8) -> Option<u64> {
+        self.names[source as usize]
+    }
+
+    /// The delta `message` maps to, if any. `time` is when it was received.
+    pub fn convert(&mut self, message: &Message, time: DateTime<Utc>) -> Option<Value> {
+        let payload = message.payload.as_slice();
+        let mut context = self.context.clone();
+        let values = match message.pgn {
+            60928 => {
+                let name = payload.get(..8)?.try_into().ok()?;
+                self.names[message.source as usize] = Some(u64::from_le_bytes(name));
+                return None;
+            }
+            127245 => rudder(payload),
+            127250 => heading(payload),
+            127488 => engine_rapid(payload),
+            127489 => engine_dynamic(payload),
+            128259 => speed(payload),
+            128267 => depth(payload),
+            129025 => position_rapid(payload),
+            129026 => cog_sog(payload),
+            129029 => gnss_position(payload),
+            129038 | 129039 => {
+                let (mmsi, values) = ais(message.pgn, payload)?;
+                context = vessel_context(mmsi);
+                if context == self.context {
+                    // Our own transponder.
+                    return None;
+                }
+                Some(values)
+            }
+            130306 => wind(payload),
+            130310 => environment(payload),
+            130311 => environment_source(payload),
+            _ => None,
+        }?;
+        if values.is_empty() {
+            return None;
+        }
+
+        let can_name = self.name(message.source).map(|name| format!("{name:016x}"));
+        let mut source = json!({
+            "label": self.label,
+            "type": "NMEA2000",
+            "pgn": message.pgn,
+            "src": message.source.to_string(),
+        });
+        if let Some(can_name) = &can_name {
+            source["canName"] = json!(can_name);
+        }
+        let source_ref = format!(
+            "{}.{}",
+            self.label,
+            can_name.unwrap_or_else(|| message.source.to_string())
+        );
+        let values: Vec<Value> = values
+            .into_iter()
+            .map(|(path, value)| json!({ "path": path, "value": value }))
+            .collect();
+
+        Some(json!({
+            "context": context,
+            "updates": [{
+                "$source": source_ref,
+                "source": source,
+                "timestamp": timestamp(time),
+                "values": values,
+            }],
+        }))
+    }
+}
+
+/// Message a client receives when it connects. `name` is the server's.
+pub fn hello(name: &str, context: &str) -> Value {
+    json!({
+        "name": name,
+        "version": SIGNALK_VERSION,
+        "self": context,
+        "roles": ["master", "main"],
+        "timestamp": timestamp(Utc::now()),
+    })
+}
+
+fn vessel_context(mmsi: u32) -> String {
+    format!("vessels.urn:mrn:imo:mmsi:{mmsi:09}")
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// `value` if below `limit` in magnitude, as the f64 JSON wants.
+fn known(value: f32, limit: f32) -> Option<f64> {
+    (value.abs() < limit).then(|| widen(value))
+}
+
+/// The shortest decimal `value` stands for, 47.1 rather than the
+/// 47.099998474121094 of its binary expansion.
+pub(crate) fn widen(value: f32) -> f64 {
+    value.to_string().parse().unwrap_or(value as f64)
+}
+
+fn known_percent(value: i8) -> Option<f64> {
+    (value.abs() < PERCENT).then_some(value as f64 / 100.0)
+}
+
+/// Pushes `path` when `value` is known.
+fn push(values: &mut Values, path: &str, value: Option<impl Into<Value>>) {
+    if let Some(value) = value {
+        values.push((path.to_string(), value.into()));
+    }
+}
+
+fn rudder(payload: &[u8]) -> Option<Values> {
+    let rudder = Pgn127245::from_payload(payload).ok()?;
+    let mut values = Values::new();
+    push(
+        &mut values,
+        "steering.rudderAngle",
+        known(rudder.position, SIGNED_ANGLE),
+    );
+    Some(values)
+}
+
+fn heading(payload: &[u8]) -> Option<Values> {
+    let heading = Pgn127250::from_payload(payload).ok()?;
+    let path = match heading.reference {
+        DirectionReference::True0 => "navigation.headingTrue",
+        DirectionReference::Magnetic1 => "navigation.headingMagnetic",
+        DirectionReference::Error2 => return None,
+    };
+    let mut values = Values::new();
+    push(&mut values, path, known(heading.heading, ANGLE));
+    push(
+        &mut values,
+        "navigation.magneticDeviation",
+        known(heading.deviation, SIGNED_ANGLE),
+    );
+    push(
+        &mut values,
+        "navigation.magneticVariation",
+        known(heading.variation, SIGNED_ANGLE),
+    );
+    Some(values)
+}
+
+/// `propulsion.port` for instance 0, `propulsion.starboard` for 1, and
+/// `propulsion.<instance>` past those.
+fn engine_path(instance: u8, path: &str) -> String {
+    match instance {
+        0 => format!("propulsion.port.{path}"),
+        1 => format!("propulsion.starboard.{path}"),
+        n => format!("propulsion.{n}.{path}"),
+    }
+}
+
+fn engine_rapid(payload: &[u8]) -> Option<Values> {
+    let engine = Pgn127488::from_payload(payload).ok()?;
+    let instance = engine.instance as u8;
+    let mut values = Values::new();
+    // rpm, Hz for SignalK.
+    push(
+        &mut values,
+        &engine_path(instance, "revolutions"),
+        known(engine.speed, 16_000.0).map(|rpm| rpm / 60.0),
+    );
+    push(
+        &mut values,
+        &engine_path(instance, "boostPressure"),
+        known(engine.boost_pressure, PRESSURE),
+    );
+    push(
+        &mut values,
+        &engine_path(instance, "drive.trimState"),
+        known_percent(engine.tilt_trim),
+    );
+    Some(values)
+}
+
+/// Read by hand: korri-n2k takes the 16-bit status bit fields for 8-bit ones
+/// and never decodes this PGN.
+fn engine_dynamic(payload: &[u8]) -> Option<Values> {
+    let bytes =
+        |offset: usize| -> Option<[u8; 2]> { payload.get(offset..offset + 2)?.try_into().ok() };
+    let unsigned = |offset| bytes(offset).map(|b| u16::from_le_bytes(b) as f32);
+    let signed = |offset| bytes(offset).map(|b| i16::from_le_bytes(b) as f32);
+    let percent = |offset: usize| payload.get(offset).and_then(|&b| known_percent(b as i8));
+
+    // Fields past the end of a short payload are left out, not the message.
+    let instance = *payload.first()?;
+    let hours = payload
+        .get(11..15)
+        .and_then(|b| b.try_into().ok())
+        .map(u32::from_le_bytes);
+    let mut values = Values::new();
+    let mut engine_push = |path: &str, value: Option<f64>| {
+        push(&mut values, &engine_path(instance, path), value);
+    };
+    engine_push(
+        "oilPressure",
+        unsigned(1).and_then(|p| known(p * 100.0, PRESSURE)),
+    );
+    engine_push(
+        "oilTemperature",
+        unsigned(3).and_then(|t| known(t / 10.0, 6_500.0)),
+    );
+    engine_push(
+        "temperature",
+        unsigned(5).and_then(|t| known(t / 100.0, TEMPERATURE)),
+    );
+    engine_push(
+        "alternatorVoltage",
+        signed(7).and_then(|v| known(v / 100.0, 327.0)),
+    );
+    // L/h, m³/s for SignalK.
+    engine_push(
+        "fuel.rate",
+        signed(9)
+            .and_then(|rate| known(rate / 10.0, 3_270.0))
+            .map(|rate| rate / 3_600_000.0),
+    );
+    engine_push(
+        "runTime",
+        hours.filter(|&h| h < u32::MAX - 2).map(f64::from),
+    );
+    engine_push(
+        "coolantPressure",
+        unsigned(15).and_then(|p| known(p * 100.0, PRESSURE)),
+    );
+    // kPa.
+    engine_push(
+        "fuel.pressure",
+        unsigned(17).and_then(|p| known(p * 1000.0, 65_000_000.0)),
+    );
+    engine_push("engineLoad", percent(24));
+    engine_push("engineTorque", percent(25));
+    Some(values)
+}
+
+fn speed(payload: &[u8]) -> Option<Values> {
+    let speed = Pgn128259::from_payload(payload).ok()?;
+    let mut values = Values::new();
+    push(
+        &mut values,
+        "navigation.speedThroughWater",
+        known(speed.speed_water_referenced, SPEED),
+    );
+    Some(values)
+}
+
+/// The offset is to the surface when positive, to the keel when negative.
+fn depth(payload: &[u8]) -> Option<Values> {
+    let depth = Pgn128267::from_payload(payload).ok()?;
+    let below_transducer = known(depth.depth, 10_000.0)?;
+    let mut values = Values::new();
+    push(
+        &mut values,
+        "environment.depth.belowTransducer",
+        Some(below_transducer),
+    );
+    if let Some(offset) = known(depth.offset, 32.0) {
+        if offset > 0.0 {
+            push(
+                &mut values,
+                "environment.depth.surfaceToTransducer",
+                Some(offset),
+            );
+            push(
+                &mut values,
+                "environment.depth.belowSurface",
+                Some(below_transducer + offset),
+            );
+        } else if offset < 0.0 {
+            push(
+                &mut values,
+                "environment.depth.transducerToKeel",
+                Some(offset),
+            );
+            push(
+                &mut values,
+                "environment.depth.belowKeel",
+                Some(below_transducer + offset),
+            );
+        }
+    }
+    Some(values)
+}
+
+fn position(latitude: f64, longitude: f64) -> Option<Value> {
+    (latitude.abs() <= 90.0 && longitude.abs() <= 180.0)
+        .then(|| json!({ "latitude": latitude, "longitude": longitude }))
+}
+
+fn position_rapid(payload: &[u8]) -> Option<Values> {
+    let fix = Pgn129025::from_payload(payload).ok()?;
+    let mut values = Values::new();
+    push(
+        &mut values,
+        "navigation.position",
+        position(widen(fix.latitude), widen(fix.longitude)),
+    );
+    Some(values)
+}
+
+fn gnss_position(payload: &[u8]) -> Option<Values> {
+    let fix = Pgn129029::from_payload(payload).ok()?;
+    let mut values = Values::new();
+    push(
+        &mut values,
+        "navigation.position",
+        position(fix.latitude, fix.longitude),
+    );
+    Some(values)
+}
+
+fn cog_sog(payload: &[u8]) -> Option<Values> {
+    let course = Pgn129026::from_payload(payload).ok()?;
+    let path = match course.cog_reference {
+        DirectionReference::True0 => "navigation.courseOverGroundTrue",
+        DirectionReference::Magnetic1 => "navigation.courseOverGroundMagnetic",
+        DirectionReference::Error2 => return None,
+    };
+    let mut values = Values::new();
+    push(&mut values, path, known(course.cog, ANGLE));
+    push(
+        &mut values,
+        "navigation.speedOverGround",
+        known(course.sog, SPEED),
+    );
+    Some(values)
+}
+
+/// Class A (129038) or B (129039) position report, with the MMSI it is about.
+/// Read by hand: korri-n2k only knows the special values of the time stamp
+/// field, and refuses the 0-59 seconds of a regular report. Both PGNs share
+/// the layout up to the heading.
+fn ais(pgn: u32, payload: &[u8]) -> Option<(u32, Values)> {
+    let u16_at = |offset: usize| -> Option<f32> {
+        Some(u16::from_le_bytes(payload.get(offset..offset + 2)?.try_into().ok()?) as f32)
+    };
+    let i32_at = |offset: usize| -> Option<f32> {
+        Some(i32::from_le_bytes(payload.get(offset..offset + 4)?.try_into().ok()?) as f32)
+    };
+    let mmsi = u32::from_le_bytes(payload.get(1..5)?.try_into().ok()?);
+    let longitude = i32_at(5)? * 1e-7;
+    let latitude = i32_at(9)? * 1e-7;
+    let cog = u16_at(14)? * 1e-4;
+    let sog = u16_at(16)? * 1e-2;
+    let heading = u16_at(21)? * 1e-4;
+    let class = if pgn == 129038 { "A" } else { "B" };
+
+    let mut values = Values::new();
+    push(
+        &mut values,
+        "",
+        Some(json!({ "mmsi": format!("{mmsi:09}") })),
+    );
+    push(&mut values, "sensors.ais.class", Some(class));
+    push(
+        &mut values,
+        "navigation.position",
+        position(widen(latitude), widen(longitude)),
+    );
+    push(
+        &mut values,
+        "navigation.courseOverGroundTrue",
+        known(cog, ANGLE),
+    );
+    push(&mut values, "navigation.speedOverGround", known(sog, SPEED));
+    push(&mut values, "navigation.headingTrue", known(heading, ANGLE));
+    Some((mmsi, values))
+}
+
+/// Angle from the bow, -π to π as SignalK wants it rather than 0 to 2π.
+fn relative_angle(angle: f64) -> f64 {
+    if angle > PI {
+        angle - 2.0 * PI
+    } else {
+        angle
+    }
+}
+
+fn wind(payload: &[u8]) -> Option<Values> {
+    let wind = Pgn130306::from_payload(payload).ok()?;
+    let angle = known(wind.wind_angle, ANGLE);
+    let (angle_path, speed_path, angle) = match wind.reference {
+        WindReference::Apparent => (
+            "environment.wind.angleApparent",
+            "environment.wind.speedApparent",
+            angle.map(relative_angle),
+        ),
+        WindReference::TrueBoatReferenced | WindReference::TrueWaterReferenced => (
+            "environment.wind.angleTrueWater",
+            "environment.wind.speedTrue",
+            angle.map(relative_angle),
+        ),
+        WindReference::TrueGroundReferencedToNorth => (
+            "environment.wind.directionTrue",
+            "environment.wind.speedOverGround",
+            angle,
+        ),
+        WindReference::MagneticGroundReferencedToMagneticNorth => (
+            "environment.wind.directionMagnetic",
+            "environment.wind.speedOverGround",
+            angle,
+        ),
+    };
+    let mut values = Values::new();
+    push(&mut values, angle_path, angle);
+    push(&mut values, speed_path, known(wind.wind_speed, SPEED));
+    Some(values)
+}
+
+fn environment(payload: &[u8]) -> Option<Values> {
+    let environment = Pgn130310::from_payload(payload).ok()?;
+    let mut values = Values::new();
+    push(
+        &mut values,
+        "environment.water.temperature",
+        known(environment.water_temperature, TEMPERATURE),
+    );
+    push(
+        &mut values,
+        "environment.outside.temperature",
+        known(environment.outside_ambient_air_temperature, TEMPERATURE),
+    );
+    push(
+        &mut values,
+        "environment.outside.pressure",
+        known(environment.atmospheric_pressure, PRESSURE),
+    );
+    Some(values)
+}
+
+/// Temperature and humidity of a given source, with the pressure.
+fn environment_source(payload: &[u8]) -> Option<Values> {
+    let environment = Pgn130311::from_payload(payload).ok()?;
+    let path = match environment.temperature_source {
+        TemperatureSource::SeaTemperature => "environment.water.temperature",
+        TemperatureSource::OutsideTemperature => "environment.outside.temperature",
+        TemperatureSource::InsideTemperature => "environment.inside.temperature",
+        TemperatureSource::EngineRoomTemperature => "environment.inside.engineRoom.temperature",
+        TemperatureSource::MainCabinTemperature => "environment.inside.mainCabin.temperature",
+        TemperatureSource::RefrigerationTemperature => {
+            "environment.inside.refrigerator.temperature"
+        }
+        TemperatureSource::FreezerTemperature => "environment.inside.freezer.temperature",
+        TemperatureSource::DewPointTemperature => "environment.outside.dewPointTemperature",
+        TemperatureSource::ApparentWindChillTemperature => {
+            "environment.outside.apparentWindChillTemperature"
+        }
+        TemperatureSource::TheoreticalWindChillTemperature => {
+            "environment.outside.theoreticalWindChillTemperature"
+        }
+        TemperatureSource::HeatIndexTemperature => "environment.outside.heatIndexTemperature",
+        _ => "",
+    };
+    let mut values = Values::new();
+    if !path.is_empty() {
+        push(
+            &mut values,
+            path,
+            known(environment.temperature, TEMPERATURE),
+        );
+    }
+    let humidity = match environment.humidity_source {
+        HumiditySource::Inside => "environment.inside.relativeHumidity",
+        HumiditySource::Outside => "environment.outside.relativeHumidity",
+    };
+    push(
+        &mut values,
+        humidity,
+        known(environment.humidity, 101.0).map(|humidity| humidity / 100.0),
+    );
+    push(
+        &mut values,
+        "environment.outside.pressure",
+        known(environment.atmospheric_pressure, PRESSURE),
+    );
+    Some(values)
+}
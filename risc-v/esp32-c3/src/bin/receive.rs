@@ -3,6 +3,12 @@
 
 use embassy_executor::Spawner;
 use korri_n2k::protocol::transport::{can_frame::CanFrame, can_id::CanId, traits::can_bus::CanBus};
+#[cfg(feature = "pcdin")]
+use korri_n2k::protocol::transport::fast_packet::assembler::{FastPacketAssembler, ProcessResult};
+#[cfg(feature = "pcdin")]
+use shared_core::fast_packet::is_fast_packet;
+#[cfg(feature = "pcdin")]
+use shared_core::format::{format_pcdin, N2kMessage, PCDIN_MAX_SENTENCE};
 
 use esp_backtrace as _;
 use esp_println as _;
@@ -23,7 +29,12 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
     esp_hal::system::software_reset()
 }
 
+// Sortie UART : une ligne Actisense par trame CAN (HH:MM:SS.mmm R CANID D0..D7),
+// ou avec la feature `pcdin` une phrase $PCDIN par message N2K, fast packets
+// réassemblés.
+
 /// Convertit un nombre en 2 chiffres hexa dans le buffer
+#[cfg(not(feature = "pcdin"))]
 fn u8_to_hex(value: u8, buffer: &mut [u8], pos: usize) {
     const HEX_CHARS: &[u8; 16] = b"0123456789ABCDEF";
     buffer[pos] = HEX_CHARS[(value >> 4) as usize];
@@ -31,6 +42,7 @@ fn u8_to_hex(value: u8, buffer: &mut [u8], pos: usize) {
 }
 
 /// Convertit un nombre en 8 chiffres hexa dans le buffer
+#[cfg(not(feature = "pcdin"))]
 fn u32_to_hex(value: u32, buffer: &mut [u8], pos: usize) {
     const HEX_CHARS: &[u8; 16] = b"0123456789ABCDEF";
     for i in 0..8 {
@@ -41,12 +53,14 @@ fn u32_to_hex(value: u32, buffer: &mut [u8], pos: usize) {
 }
 
 /// Convertit un nombre en 2 chiffres décimaux dans le buffer
+#[cfg(not(feature = "pcdin"))]
 fn u8_to_dec(value: u8, buffer: &mut [u8], pos: usize) {
     buffer[pos] = b'0' + (value / 10);
     buffer[pos + 1] = b'0' + (value % 10);
 }
 
 /// Convertit un nombre en 3 chiffres décimaux dans le buffer
+#[cfg(not(feature = "pcdin"))]
 fn u64_to_dec3(value: u64, buffer: &mut [u8], pos: usize) {
     buffer[pos] = b'0' + ((value / 100) % 10) as u8;
     buffer[pos + 1] = b'0' + ((value / 10) % 10) as u8;
@@ -55,6 +69,7 @@ fn u64_to_dec3(value: u64, buffer: &mut [u8], pos: usize) {
 
 /// Formate une frame CAN au format ACTISENSE
 /// Format: HH:MM:SS.mmm R CANID D0 D1 D2 D3 D4 D5 D6 D7
+#[cfg(not(feature = "pcdin"))]
 fn format_actisense(frame: &CanFrame, uptime_ms: u64, buffer: &mut [u8; 128]) -> usize {
     let total_seconds = uptime_ms / 1000;
     let milliseconds = uptime_ms % 1000;
@@ -111,6 +126,35 @@ fn format_actisense(frame: &CanFrame, uptime_ms: u64, buffer: &mut [u8; 128]) ->
     pos
 }
 
+/// Message N2K complet porté par `frame`, une fois le dernier fragment reçu
+/// pour un fast packet
+#[cfg(feature = "pcdin")]
+fn pcdin_message(
+    assembler: &mut FastPacketAssembler,
+    frame: &CanFrame,
+    uptime_ms: u64,
+) -> Option<N2kMessage> {
+    let pgn = frame.id.pgn();
+    if !is_fast_packet(pgn) {
+        return Some(N2kMessage::from_frame(frame, uptime_ms as u32));
+    }
+    let source = frame.id.source_address();
+    match assembler.process_frame(uptime_ms as u32, pgn, source, &frame.data) {
+        ProcessResult::MessageComplete(complete) => {
+            let mut message = N2kMessage::new(
+                frame.id.priority(),
+                pgn,
+                source,
+                frame.id.destination().unwrap_or(0xFF),
+                &complete.payload[..complete.len],
+            )?;
+            message.timestamp_ms = uptime_ms as u32;
+            Some(message)
+        }
+        _ => None,
+    }
+}
+
 #[esp_hal_embassy::main]
 async fn main(_spawner: Spawner) {
     // println!("RECEIVER - Init async..");
@@ -164,7 +208,12 @@ async fn main(_spawner: Spawner) {
     let mut total_count = 0;
     let mut error_count = 0;
     let mut total_rx_time = 0u64;
+    #[cfg(not(feature = "pcdin"))]
     let mut actisense_buffer = [0u8; 128];
+    #[cfg(feature = "pcdin")]
+    let mut pcdin_buffer = [0u8; PCDIN_MAX_SENTENCE];
+    #[cfg(feature = "pcdin")]
+    let mut assembler = FastPacketAssembler::new();
     let start_time = Instant::now();
 
     // println!("Ready to listen..");
@@ -181,13 +230,26 @@ async fn main(_spawner: Spawner) {
                 // Calculer l'uptime en millisecondes
                 let uptime_ms = (Instant::now() - start_time).as_millis();
 
-                // Formater et envoyer vers UART au format ACTISENSE
-                let len = format_actisense(&frame, uptime_ms, &mut actisense_buffer);
+                // Formater vers le format choisi ; rien tant qu'un fast packet
+                // est incomplet
+                #[cfg(not(feature = "pcdin"))]
+                let line: &[u8] = {
+                    let len = format_actisense(&frame, uptime_ms, &mut actisense_buffer);
+                    &actisense_buffer[..len]
+                };
+                #[cfg(feature = "pcdin")]
+                let line: &[u8] = match pcdin_message(&mut assembler, &frame, uptime_ms) {
+                    Some(message) => {
+                        let len = format_pcdin(&message, &mut pcdin_buffer);
+                        &pcdin_buffer[..len]
+                    }
+                    None => &[],
+                };
 
                 // Écrire tous les octets (boucle jusqu'à ce que tout soit écrit)
                 let mut written = 0;
-                while written < len {
-                    match uart_tx.write_async(&actisense_buffer[written..len]).await {
+                while written < line.len() {
+                    match uart_tx.write_async(&line[written..]).await {
                         Ok(n) => written += n,
                         Err(_) => break,
                     }
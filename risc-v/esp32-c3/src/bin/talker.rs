@@ -19,6 +19,7 @@ use esp32_c3::{manager_service, pgns::{
         depth_128267::task_depth_128267,
         speed_128259::task_speed_128259,
     }};
+use korri_n2k::protocol::managment::address_claiming::AddressClaimStrategy;
 use korri_n2k::protocol::managment::address_manager::AddressManager;
 use static_cell::StaticCell;
 
@@ -70,7 +71,9 @@ async fn main(spawner: Spawner) {
         can_bus,
         korri_timer,
         my_name,
-        preferred_address,
+        AddressClaimStrategy::Arbitrary {
+            preferred: preferred_address,
+        },
     )
     .await
     {
@@ -16,6 +16,7 @@ use esp32_c3::{
     manager_service::{address_manager_task, init_manager},
     pgns::{depth_128267::task_depth_128267, speed_128259::task_speed_128259},
 };
+use korri_n2k::protocol::managment::address_claiming::AddressClaimStrategy;
 use korri_n2k::protocol::managment::address_manager::AddressManager;
 
 esp_bootloader_esp_idf::esp_app_desc!();
@@ -68,7 +69,10 @@ async fn main(spawner: Spawner) {
     defmt::info!("Creating AddressManager...");
 
     // Créer l'AddressManager (fait le claim iniial automatiquement)
-    let mut manager = match AddressManager::new(can_bus, korri_timer, my_name, preferred_address).await
+    let strategy = AddressClaimStrategy::Arbitrary {
+        preferred: preferred_address,
+    };
+    let mut manager = match AddressManager::new(can_bus, korri_timer, my_name, strategy).await
     {
         Ok(mgr) => mgr,
         Err(_) => panic!("Failed to create AddressManager"),
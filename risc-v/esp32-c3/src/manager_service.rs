@@ -17,7 +17,7 @@ use korri_n2k::{
 
 use crate::{ports::EspCanBus, timer::EspTimer};
 
-pub type AddressManagerType = AddressManager<EspCanBus<'static>, EspTimer>;
+pub type AddressManagerType<'a> = AddressManager<'a, EspCanBus<'static>, EspTimer>;
 pub type ManagerRunner =
     AddressRunner<'static, EspCanBus<'static>, EspTimer, COMMAND_CAPACITY, 0>;
 
@@ -59,7 +59,7 @@ impl ManagerHandle {
 }
 
 pub fn init_manager(
-    manager: AddressManagerType,
+    manager: AddressManagerType<'static>,
 ) -> (ManagerRunner, &'static ManagerHandle) {
     let channel = COMMAND_CHANNEL.init_with(Channel::new);
 
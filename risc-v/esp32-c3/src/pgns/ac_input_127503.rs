@@ -11,7 +11,7 @@ use korri_n2k::{
     },
 };
 
-type AddressManagerType = AddressManager<crate::ports::EspCanBus<'static>, crate::timer::EspTimer>;
+type AddressManagerType = AddressManager<'static, crate::ports::EspCanBus<'static>, crate::timer::EspTimer>;
 
 #[embassy_executor::task]
 pub async fn task_ac_input_127503(
@@ -13,7 +13,7 @@ use korri_n2k::{
     },
 };
 
-type AddressManagerType = AddressManager<crate::ports::EspCanBus<'static>, crate::timer::EspTimer>;
+type AddressManagerType = AddressManager<'static, crate::ports::EspCanBus<'static>, crate::timer::EspTimer>;
 
 #[embassy_executor::task]
 pub async fn task_ais_class_a_129038(
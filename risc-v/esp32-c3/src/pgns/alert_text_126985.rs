@@ -12,7 +12,7 @@ use korri_n2k::{
     },
 };
 
-type AddressManagerType = AddressManager<crate::ports::EspCanBus<'static>, crate::timer::EspTimer>;
+type AddressManagerType = AddressManager<'static, crate::ports::EspCanBus<'static>, crate::timer::EspTimer>;
 
 #[embassy_executor::task]
 pub async fn task_alert_text_126985(
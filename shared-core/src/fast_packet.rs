@@ -0,0 +1,22 @@
+//! Which PGNs travel as fast packets, without the korri-n2k descriptors and
+//! the flash they would take.
+
+/// Standard fast-packet PGNs, after canboat. Sorted.
+const FAST_PACKET_PGNS: &[u32] = &[
+    126208, 126464, 126983, 126984, 126985, 126986, 126987, 126988, 126996, 126998, 127233, 127237,
+    127489, 127490, 127491, 127494, 127495, 127496, 127497, 127498, 127503, 127504, 127506, 127507,
+    127509, 127510, 127511, 127512, 127513, 127514, 128275, 128520, 128538, 129029, 129038, 129039,
+    129040, 129041, 129044, 129045, 129284, 129285, 129301, 129302, 129538, 129540, 129541, 129542,
+    129545, 129547, 129549, 129551, 129556, 129792, 129793, 129794, 129795, 129796, 129797, 129798,
+    129799, 129800, 129801, 129802, 129803, 129804, 129805, 129806, 129807, 129808, 129809, 129810,
+    130052, 130053, 130054, 130060, 130061, 130064, 130065, 130066, 130067, 130068, 130069, 130070,
+    130071, 130072, 130073, 130074, 130320, 130321, 130322, 130323, 130324, 130330, 130560, 130561,
+    130562, 130563, 130564, 130565, 130566, 130567, 130569, 130570, 130571, 130572, 130573, 130574,
+    130577, 130578, 130579, 130580, 130581, 130582, 130583, 130584, 130585, 130586,
+];
+
+/// Whether `pgn` is sent as fast packets. Proprietary PGNs are when they sit
+/// in the fast-packet ranges; unknown others are taken as single frames.
+pub fn is_fast_packet(pgn: u32) -> bool {
+    matches!(pgn, 126720 | 130816..=131071) || FAST_PACKET_PGNS.binary_search(&pgn).is_ok()
+}
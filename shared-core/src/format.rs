@@ -1,5 +1,21 @@
 use korri_n2k::protocol::transport::{can_frame::CanFrame, can_id::CanId};
 
+pub mod ebl;
+pub mod ngt1;
+pub mod pcdin;
+pub mod ydwg;
+
+pub use ebl::{
+    BST_CAN_FRAME, EBL_MAX_RECORD, EBL_RECORD_BST, EBL_RECORD_TIME, EblCounters, EblDeframer,
+    EblError, EblRecord, EblRecords, encode_ebl_frame, encode_ebl_time,
+};
+pub use ngt1::{
+    DLE, ETX, MSG_N2K_DATA, MSG_N2K_RECEIVED, N2kMessage, NGT1_MAX_MESSAGE, Ngt1Counters,
+    Ngt1Deframer, Ngt1Error, Ngt1Messages, STX, encode_ngt1, parse_ngt1_body, parse_ngt1_message,
+};
+pub use pcdin::{PCDIN_MAX_SENTENCE, PcdinError, format_pcdin, parse_pcdin};
+pub use ydwg::{YDWG_MAX_LINE, YdwgMessage, format_ydwg, format_ydwg_send, parse_ydwg};
+
 /// Convertit un nombre en 2 chiffres hexa dans le buffer
 fn u8_to_hex(value: u8, buffer: &mut [u8], pos: usize) {
     const HEX_CHARS: &[u8; 16] = b"0123456789ABCDEF";
@@ -87,108 +103,121 @@ pub fn format_actisense(frame: &CanFrame, uptime_ms: u64, buffer: &mut [u8; 128]
     pos
 }
 
-//================================================================================= ACTISENSE NGT-1
-
-/// Constantes du protocole Actisense NGT-1
-pub const DLE: u8 = 0x10;
-pub const STX: u8 = 0x02;
-pub const ETX: u8 = 0x03;
-pub const MSG_N2K_DATA: u8 = 0x94; // Message N2K data (0x94 pour SignalK)
-
-/// Parse un message binaire Actisense NGT-1 en CanFrame
-/// Format: [DLE STX cmd len data... checksum DLE ETX]
-pub fn parse_ngt1_message(data: &[u8]) -> Option<CanFrame> {
-    // Vérifier la structure minimum: DLE STX cmd len ... DLE ETX
-    if data.len() < 6 {
-        return None;
-    }
-
-    // Chercher DLE STX au début
-    let start = data.windows(2).position(|w| w[0] == DLE && w[1] == STX)?;
-
-    if start + 4 >= data.len() {
-        return None;
-    }
+/// Direction of an Actisense ASCII line, as seen from the gateway.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActisenseDirection {
+    /// `R`: frame read from the bus.
+    Received,
+    /// `T`: frame sent to the bus.
+    Transmitted,
+}
 
-    let cmd = data[start + 2];
+/// One line of the format written by [`format_actisense`].
+#[derive(Clone, Debug)]
+pub struct ActisenseLine {
+    /// Milliseconds since midnight. Lines written by the firmware carry the
+    /// uptime instead, wrapped at 24 h.
+    pub time_ms: u32,
+    pub direction: ActisenseDirection,
+    pub frame: CanFrame,
+}
 
-    // On ne traite que les messages N2K data (0x93)
-    if cmd != MSG_N2K_DATA {
-        return None;
-    }
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActisenseError {
+    /// Blank line.
+    Empty,
+    /// Not `HH:MM:SS.mmm`.
+    BadTime,
+    /// Neither `R` nor `T`.
+    BadDirection,
+    /// Missing, not hexadecimal, or wider than 29 bits.
+    BadId,
+    /// A data byte that is not two hexadecimal digits.
+    BadData,
+    /// More than 8 data bytes.
+    TooLong,
+}
 
-    let msg_len = data[start + 3] as usize;
+/// Parses one line of the `HH:MM:SS.mmm R CANID D0..D7` format back into a
+/// frame.
+///
+/// Fields may be separated by any run of spaces or tabs, and a trailing CRLF
+/// or LF is ignored, so lines can come straight from a file or a serial port.
+pub fn parse_actisense(line: &[u8]) -> Result<ActisenseLine, ActisenseError> {
+    let mut fields = split_fields(line);
+
+    let time_ms = parse_time(fields.next().ok_or(ActisenseError::Empty)?)?;
+
+    let direction = match fields.next() {
+        Some(b"R") => ActisenseDirection::Received,
+        Some(b"T") => ActisenseDirection::Transmitted,
+        _ => return Err(ActisenseError::BadDirection),
+    };
+
+    Ok(ActisenseLine {
+        time_ms,
+        direction,
+        frame: parse_frame(fields)?,
+    })
+}
 
-    // Vérifier qu'on a assez de données
-    if start + 4 + msg_len + 2 > data.len() {
-        return None;
-    }
+/// Fields of a text line: runs of spaces, tabs and line endings separate them.
+fn split_fields(line: &[u8]) -> impl Iterator<Item = &[u8]> {
+    line.split(|&b| b == b' ' || b == b'\t' || b == b'\r' || b == b'\n')
+        .filter(|field| !field.is_empty())
+}
 
-    // Extraire le payload (en gérant l'échappement DLE)
-    let mut payload = [0u8; 32];
-    let mut payload_pos = 0;
-    let mut i = start + 4;
-    let end = start + 4 + msg_len;
-
-    while i < end && payload_pos < payload.len() {
-        if data[i] == DLE && i + 1 < end && data[i + 1] == DLE {
-            // DLE échappé (DLE DLE -> DLE)
-            payload[payload_pos] = DLE;
-            payload_pos += 1;
-            i += 2;
-        } else {
-            payload[payload_pos] = data[i];
-            payload_pos += 1;
-            i += 1;
+/// `CANID D0..D7`, the tail shared by the text formats.
+fn parse_frame<'a>(mut fields: impl Iterator<Item = &'a [u8]>) -> Result<CanFrame, ActisenseError> {
+    let id = fields
+        .next()
+        .filter(|field| field.len() <= 8)
+        .and_then(parse_hex)
+        .filter(|&id| id <= 0x1FFF_FFFF)
+        .ok_or(ActisenseError::BadId)?;
+
+    let mut data = [0u8; 8];
+    let mut len = 0;
+    for field in fields {
+        if len == data.len() {
+            return Err(ActisenseError::TooLong);
         }
+        data[len] = Some(field)
+            .filter(|field| field.len() == 2)
+            .and_then(parse_hex)
+            .ok_or(ActisenseError::BadData)? as u8;
+        len += 1;
     }
 
-    // Le payload NGT-1 SignalK contient: [priority] [PGN:3] [dst] [len] [data...]
-    // (PAS de source address - il est dans l'en-tête NGT-1 ou implicite)
-    if payload_pos < 6 {
-        defmt::info!("NGT-1 parse: payload too short ({} bytes)", payload_pos);
-        return None;
-    }
+    Ok(CanFrame {
+        id: CanId(id),
+        data,
+        len,
+    })
+}
 
-    let priority = payload[0];
-    let pgn = ((payload[3] as u32) << 16) | ((payload[2] as u32) << 8) | (payload[1] as u32);
-    let dst = payload[4];
-    let data_len = payload[5] as usize;
-
-    defmt::info!(
-        "NGT-1 parse: prio={}, PGN={}, dst={}, len={}",
-        priority,
-        pgn,
-        dst,
-        data_len
-    );
-
-    if payload_pos < 6 + data_len {
-        defmt::warn!(
-            "NGT-1 parse: not enough data ({} < {})",
-            payload_pos,
-            6 + data_len
-        );
-        return None;
+/// `HH:MM:SS.mmm` to milliseconds.
+fn parse_time(field: &[u8]) -> Result<u32, ActisenseError> {
+    let [h1, h2, b':', m1, m2, b':', s1, s2, b'.', ms1, ms2, ms3] = *field else {
+        return Err(ActisenseError::BadTime);
+    };
+    let number = |digits: &[u8]| {
+        digits.iter().try_fold(0u32, |value, &d| {
+            d.is_ascii_digit().then(|| value * 10 + (d - b'0') as u32)
+        })
+    };
+    let hours = number(&[h1, h2]).filter(|&h| h < 24);
+    let minutes = number(&[m1, m2]).filter(|&m| m < 60);
+    let seconds = number(&[s1, s2]).filter(|&s| s < 60);
+    let millis = number(&[ms1, ms2, ms3]);
+    match (hours, minutes, seconds, millis) {
+        (Some(h), Some(m), Some(s), Some(ms)) => Ok(((h * 60 + m) * 60 + s) * 1000 + ms),
+        _ => Err(ActisenseError::BadTime),
     }
+}
 
-    // Source address = 255 (broadcast) par défaut car SignalK ne l'envoie pas
-    let src = 255u8;
-
-    // Construire le CAN ID
-    let can_id = CanId::builder(pgn, src)
-        .with_priority(priority)
-        .build()
-        .ok()?;
-
-    // Extraire les données (max 8 bytes pour CAN)
-    let mut frame_data = [0u8; 8];
-    let frame_len = data_len.min(8);
-    frame_data[..frame_len].copy_from_slice(&payload[6..6 + frame_len]);
-
-    Some(CanFrame {
-        id: can_id,
-        data: frame_data,
-        len: frame_len,
+fn parse_hex(field: &[u8]) -> Option<u32> {
+    field.iter().try_fold(0u32, |value, &d| {
+        Some(value << 4 | (d as char).to_digit(16)?)
     })
 }
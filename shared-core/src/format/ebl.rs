@@ -0,0 +1,314 @@
+//! Actisense EBL logs, as recorded by NMEA Reader and the W2K-1 gateway.
+//!
+//! ```text
+//! ESC SOH | type | body | ESC LF
+//!           └─ ESC escaped as ESC ESC ─┘
+//! ```
+//!
+//! Two record types are read and written here:
+//!
+//! ```text
+//! 0x03 time: filetime[8]                          100 ns since 1601-01-01, UTC
+//! 0x07 BST:  0x95 | len | time_ms[2] | can_id[4] | data[len - 6]
+//! ```
+//!
+//! BST-95 records are raw CAN frames, fast packets still split, stamped by a
+//! free-running millisecond counter that wraps every 65.5 s. A time record
+//! gives the wall clock of the frame that follows it; later frames add the
+//! counter deltas. Other records (other BST messages, device information)
+//! are skipped and counted.
+
+use korri_n2k::protocol::transport::{can_frame::CanFrame, can_id::CanId};
+
+const ESC: u8 = 0x1B;
+const SOH: u8 = 0x01;
+const LF: u8 = 0x0A;
+
+pub const EBL_RECORD_TIME: u8 = 0x03;
+pub const EBL_RECORD_BST: u8 = 0x07;
+/// BST message of a CAN frame, inside an [`EBL_RECORD_BST`] record.
+pub const BST_CAN_FRAME: u8 = 0x95;
+
+/// Microseconds between 1601-01-01 and 1970-01-01.
+const FILETIME_UNIX_OFFSET_US: u64 = 11_644_473_600_000_000;
+
+/// `type | filetime`, unescaped.
+const TIME_RAW: usize = 1 + 8;
+/// `type | 0x95 | len | time_ms | can_id | data`, unescaped.
+const FRAME_RAW: usize = 3 + 6 + 8;
+/// Enough for any record this module decodes; longer ones are only skipped.
+const EBL_MAX_RAW: usize = 32;
+
+/// Worst case on the wire for a record written here: a CAN frame where every
+/// byte needs escaping.
+pub const EBL_MAX_RECORD: usize = 2 + 2 * FRAME_RAW + 2;
+
+/// A decoded EBL record.
+#[derive(Clone, Debug)]
+pub enum EblRecord {
+    /// Wall clock, microseconds since the Unix epoch.
+    Time(u64),
+    /// A CAN frame. `timestamp_us` is on the clock of the last time record,
+    /// or counts from the first frame when none was seen yet.
+    Frame { timestamp_us: u64, frame: CanFrame },
+}
+
+/// Encodes a time record for `unix_us`.
+pub fn encode_ebl_time(unix_us: u64, buffer: &mut [u8; EBL_MAX_RECORD]) -> usize {
+    let filetime = (unix_us + FILETIME_UNIX_OFFSET_US) * 10;
+    let mut raw = [0u8; TIME_RAW];
+    raw[0] = EBL_RECORD_TIME;
+    raw[1..].copy_from_slice(&filetime.to_le_bytes());
+    frame_record(&raw, buffer)
+}
+
+/// Encodes `frame` as a BST-95 record, `time_ms` being the low 16 bits of a
+/// millisecond clock.
+pub fn encode_ebl_frame(
+    frame: &CanFrame,
+    time_ms: u16,
+    buffer: &mut [u8; EBL_MAX_RECORD],
+) -> usize {
+    let len = frame.len.min(8);
+    let mut raw = [0u8; FRAME_RAW];
+    raw[0] = EBL_RECORD_BST;
+    raw[1] = BST_CAN_FRAME;
+    raw[2] = (6 + len) as u8;
+    raw[3..5].copy_from_slice(&time_ms.to_le_bytes());
+    raw[5..9].copy_from_slice(&frame.id.0.to_le_bytes());
+    raw[9..9 + len].copy_from_slice(&frame.data[..len]);
+    frame_record(&raw[..9 + len], buffer)
+}
+
+/// `ESC SOH`, `raw` with every ESC doubled, `ESC LF`.
+fn frame_record(raw: &[u8], buffer: &mut [u8; EBL_MAX_RECORD]) -> usize {
+    buffer[0] = ESC;
+    buffer[1] = SOH;
+    let mut pos = 2;
+    for &byte in raw {
+        buffer[pos] = byte;
+        pos += 1;
+        if byte == ESC {
+            buffer[pos] = ESC;
+            pos += 1;
+        }
+    }
+    buffer[pos] = ESC;
+    buffer[pos + 1] = LF;
+    pos + 2
+}
+
+/// Why an EBL record was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EblError {
+    /// A record cut short by the start of the next one.
+    Truncated,
+    /// An ESC followed by neither ESC, SOH nor LF: the stream is out of step.
+    BadEscape,
+    /// A time or BST-95 record whose length does not match its content.
+    BadRecord,
+    /// A valid record of another type.
+    UnsupportedRecord(u8),
+    /// A valid BST record carrying another message than a CAN frame.
+    UnsupportedBst(u8),
+}
+
+/// Running totals of an [`EblDeframer`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EblCounters {
+    pub frames: u32,
+    pub time_records: u32,
+    /// Bytes outside any `ESC SOH`…`ESC LF` pair.
+    pub noise_bytes: u32,
+    /// Records cut short, badly escaped, or whose length does not match.
+    pub framing_errors: u32,
+    /// Valid records of another type, or other BST messages.
+    pub other_records: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DeframerState {
+    Idle,
+    /// Between records, after an ESC.
+    IdleEscape,
+    InRecord,
+    /// In a record, after an ESC.
+    InEscape,
+}
+
+/// Incremental EBL reader: bytes go in as they come, records come out, frames
+/// timestamped.
+///
+/// Same rules as [`Ngt1Deframer`](super::Ngt1Deframer): escapes are tracked
+/// between records too, and an `ESC SOH` inside a record abandons it.
+pub struct EblDeframer {
+    state: DeframerState,
+    raw: [u8; EBL_MAX_RAW],
+    /// Unescaped bytes of the current record, including those past `raw`.
+    len: usize,
+    counters: EblCounters,
+    /// Wall clock of the last time record, microseconds.
+    base_us: u64,
+    /// Milliseconds from the first frame after that record.
+    elapsed_ms: u64,
+    last_time_ms: Option<u16>,
+}
+
+impl EblDeframer {
+    pub const fn new() -> Self {
+        Self {
+            state: DeframerState::Idle,
+            raw: [0; EBL_MAX_RAW],
+            len: 0,
+            counters: EblCounters {
+                frames: 0,
+                time_records: 0,
+                noise_bytes: 0,
+                framing_errors: 0,
+                other_records: 0,
+            },
+            base_us: 0,
+            elapsed_ms: 0,
+            last_time_ms: None,
+        }
+    }
+
+    pub fn counters(&self) -> EblCounters {
+        self.counters
+    }
+
+    /// Consumes one byte. `Some` when it ends a record, whether it decoded or
+    /// not; errors are also counted.
+    pub fn push(&mut self, byte: u8) -> Option<Result<EblRecord, EblError>> {
+        match (self.state, byte) {
+            (DeframerState::Idle, ESC) => self.state = DeframerState::IdleEscape,
+            (DeframerState::Idle, _) => self.counters.noise_bytes += 1,
+            (DeframerState::IdleEscape, SOH) => self.start(),
+            (DeframerState::IdleEscape, _) => {
+                // An escaped ESC or junk, inside a record we did not see
+                // start.
+                self.counters.noise_bytes += 2;
+                self.state = DeframerState::Idle;
+            }
+            (DeframerState::InRecord, ESC) => self.state = DeframerState::InEscape,
+            (DeframerState::InRecord, _) => self.store(byte),
+            (DeframerState::InEscape, ESC) => {
+                self.state = DeframerState::InRecord;
+                self.store(ESC);
+            }
+            (DeframerState::InEscape, LF) => {
+                self.state = DeframerState::Idle;
+                return Some(self.finish());
+            }
+            (DeframerState::InEscape, SOH) => {
+                self.start();
+                return Some(self.fail(EblError::Truncated));
+            }
+            (DeframerState::InEscape, _) => {
+                self.state = DeframerState::Idle;
+                return Some(self.fail(EblError::BadEscape));
+            }
+        }
+        None
+    }
+
+    /// Consumes `bytes`, yielding each record as it completes. Bytes left
+    /// when the iterator is dropped are not consumed.
+    pub fn feed<'a>(&'a mut self, bytes: &'a [u8]) -> EblRecords<'a> {
+        EblRecords {
+            deframer: self,
+            bytes: bytes.iter(),
+        }
+    }
+
+    fn start(&mut self) {
+        self.state = DeframerState::InRecord;
+        self.len = 0;
+    }
+
+    fn store(&mut self, byte: u8) {
+        if let Some(slot) = self.raw.get_mut(self.len) {
+            *slot = byte;
+        }
+        self.len += 1;
+    }
+
+    fn finish(&mut self) -> Result<EblRecord, EblError> {
+        if self.len == 0 {
+            return self.fail(EblError::BadRecord);
+        }
+        let raw = &self.raw[..self.len.min(EBL_MAX_RAW)];
+        match raw[0] {
+            EBL_RECORD_TIME if self.len == TIME_RAW => {
+                let mut filetime = [0u8; 8];
+                filetime.copy_from_slice(&raw[1..]);
+                let unix_us =
+                    (u64::from_le_bytes(filetime) / 10).saturating_sub(FILETIME_UNIX_OFFSET_US);
+                self.base_us = unix_us;
+                self.elapsed_ms = 0;
+                self.last_time_ms = None;
+                self.counters.time_records += 1;
+                Ok(EblRecord::Time(unix_us))
+            }
+            EBL_RECORD_BST if self.len >= 2 && raw[1] != BST_CAN_FRAME => {
+                self.fail(EblError::UnsupportedBst(raw[1]))
+            }
+            EBL_RECORD_BST
+                if self.len >= 9 && self.len <= FRAME_RAW && raw[2] as usize == self.len - 3 =>
+            {
+                let time_ms = u16::from_le_bytes([raw[3], raw[4]]);
+                let id = u32::from_le_bytes([raw[5], raw[6], raw[7], raw[8]]) & 0x1FFF_FFFF;
+                let mut frame = CanFrame {
+                    id: CanId(id),
+                    data: [0; 8],
+                    len: self.len - 9,
+                };
+                frame.data[..frame.len].copy_from_slice(&raw[9..]);
+
+                if let Some(last) = self.last_time_ms {
+                    self.elapsed_ms += time_ms.wrapping_sub(last) as u64;
+                }
+                self.last_time_ms = Some(time_ms);
+                self.counters.frames += 1;
+                Ok(EblRecord::Frame {
+                    timestamp_us: self.base_us + self.elapsed_ms * 1000,
+                    frame,
+                })
+            }
+            EBL_RECORD_TIME | EBL_RECORD_BST => self.fail(EblError::BadRecord),
+            other => self.fail(EblError::UnsupportedRecord(other)),
+        }
+    }
+
+    fn fail(&mut self, error: EblError) -> Result<EblRecord, EblError> {
+        match error {
+            EblError::UnsupportedRecord(_) | EblError::UnsupportedBst(_) => {
+                self.counters.other_records += 1
+            }
+            _ => self.counters.framing_errors += 1,
+        }
+        Err(error)
+    }
+}
+
+impl Default for EblDeframer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Records completed by a slice, see [`EblDeframer::feed`].
+pub struct EblRecords<'a> {
+    deframer: &'a mut EblDeframer,
+    bytes: core::slice::Iter<'a, u8>,
+}
+
+impl Iterator for EblRecords<'_> {
+    type Item = Result<EblRecord, EblError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.bytes
+            .by_ref()
+            .find_map(|&byte| self.deframer.push(byte))
+    }
+}
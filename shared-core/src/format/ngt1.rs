@@ -0,0 +1,460 @@
+//! Actisense NGT-1 binary protocol, as spoken by SignalK, OpenCPN and canboat.
+//!
+//! ```text
+//! DLE STX | command | len | body[len] | checksum | DLE ETX
+//!           └──────────── DLE escaped as DLE DLE ───────┘
+//! ```
+//!
+//! The checksum makes the sum of command, len, body and checksum zero modulo
+//! 256. Two commands carry N2K messages, both with a reassembled payload of up
+//! to 223 bytes, never raw fast-packet frames:
+//!
+//! ```text
+//! 0x93 received: prio | pgn[3] | dst | src | timestamp_ms[4] | len | data
+//! 0x94 transmit: prio | pgn[3] | dst | len | data
+//! ```
+
+use korri_n2k::protocol::transport::{
+    can_frame::CanFrame, can_id::CanId, fast_packet::MAX_FAST_PACKET_PAYLOAD,
+};
+
+/// Constantes du protocole Actisense NGT-1
+pub const DLE: u8 = 0x10;
+pub const STX: u8 = 0x02;
+pub const ETX: u8 = 0x03;
+/// N2K message received from the bus (gateway to PC).
+pub const MSG_N2K_RECEIVED: u8 = 0x93;
+pub const MSG_N2K_DATA: u8 = 0x94; // Message N2K data (0x94 pour SignalK)
+
+/// Body header of a 0x93 message, before the data.
+const RECEIVED_HEADER: usize = 11;
+
+/// Largest unescaped `command | len | body | checksum` for an N2K message.
+pub(crate) const NGT1_MAX_RAW: usize = 2 + RECEIVED_HEADER + MAX_FAST_PACKET_PAYLOAD + 1;
+
+/// Worst case on the wire: a 0x93 message with a full payload where every byte
+/// between STX and ETX needs escaping.
+pub const NGT1_MAX_MESSAGE: usize = 2 + 2 * NGT1_MAX_RAW + 2;
+
+/// A complete N2K message, as carried by NGT-1 (payload reassembled).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct N2kMessage {
+    pub priority: u8,
+    pub pgn: u32,
+    pub source: u8,
+    /// 255 for a broadcast.
+    pub destination: u8,
+    /// Gateway time, milliseconds. Only carried by 0x93 messages.
+    pub timestamp_ms: u32,
+    pub len: usize,
+    pub data: [u8; MAX_FAST_PACKET_PAYLOAD],
+}
+
+impl N2kMessage {
+    /// `None` if `data` does not fit in a fast-packet message.
+    pub fn new(priority: u8, pgn: u32, source: u8, destination: u8, data: &[u8]) -> Option<Self> {
+        if data.len() > MAX_FAST_PACKET_PAYLOAD {
+            return None;
+        }
+        let mut message = Self {
+            priority: priority & 0x07,
+            pgn,
+            source,
+            destination,
+            timestamp_ms: 0,
+            len: data.len(),
+            data: [0u8; MAX_FAST_PACKET_PAYLOAD],
+        };
+        message.data[..data.len()].copy_from_slice(data);
+        Some(message)
+    }
+
+    /// A single-frame message. A fast-packet frame has to go through an
+    /// assembler first: NGT-1 carries whole messages, never fragments.
+    pub fn from_frame(frame: &CanFrame, timestamp_ms: u32) -> Self {
+        let mut message = Self {
+            priority: frame.id.priority(),
+            pgn: frame.id.pgn(),
+            source: frame.id.source_address(),
+            destination: frame.id.destination().unwrap_or(255),
+            timestamp_ms,
+            len: frame.len.min(8),
+            data: [0u8; MAX_FAST_PACKET_PAYLOAD],
+        };
+        message.data[..message.len].copy_from_slice(&frame.data[..message.len]);
+        message
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// CAN identifier of the frames carrying this message.
+    pub fn can_id(&self) -> Option<CanId> {
+        let builder = CanId::builder(self.pgn, self.source).with_priority(self.priority);
+        // PDU1 PGNs are addressed, even to 255; PDU2 ones never are.
+        let builder = if (self.pgn >> 8) & 0xFF < 240 {
+            builder.to_destination(self.destination)
+        } else {
+            builder
+        };
+        builder.build().ok()
+    }
+}
+
+/// Encodes `message` as a complete NGT-1 message, framing, escaping and
+/// checksum included.
+///
+/// `command` is [`MSG_N2K_RECEIVED`] to impersonate a gateway reporting bus
+/// traffic (source and timestamp included), or [`MSG_N2K_DATA`] to ask a
+/// gateway to transmit (its own address is used as source). Returns the number
+/// of bytes written, or `None` for any other command.
+pub fn encode_ngt1(
+    command: u8,
+    message: &N2kMessage,
+    buffer: &mut [u8; NGT1_MAX_MESSAGE],
+) -> Option<usize> {
+    if message.len > MAX_FAST_PACKET_PAYLOAD {
+        return None;
+    }
+
+    let mut body = [0u8; RECEIVED_HEADER + MAX_FAST_PACKET_PAYLOAD];
+    let pgn = message.pgn.to_le_bytes();
+    body[0] = message.priority;
+    body[1..4].copy_from_slice(&pgn[..3]);
+    body[4] = message.destination;
+
+    let header = match command {
+        MSG_N2K_RECEIVED => {
+            body[5] = message.source;
+            body[6..10].copy_from_slice(&message.timestamp_ms.to_le_bytes());
+            body[10] = message.len as u8;
+            RECEIVED_HEADER
+        }
+        MSG_N2K_DATA => {
+            body[5] = message.len as u8;
+            6
+        }
+        _ => return None,
+    };
+    let body_len = header + message.len;
+    body[header..body_len].copy_from_slice(message.payload());
+
+    let mut pos = 0;
+    buffer[pos] = DLE;
+    buffer[pos + 1] = STX;
+    pos += 2;
+
+    let mut sum = command.wrapping_add(body_len as u8);
+    pos = push_escaped(buffer, pos, command);
+    pos = push_escaped(buffer, pos, body_len as u8);
+    for &byte in &body[..body_len] {
+        sum = sum.wrapping_add(byte);
+        pos = push_escaped(buffer, pos, byte);
+    }
+    pos = push_escaped(buffer, pos, sum.wrapping_neg());
+
+    buffer[pos] = DLE;
+    buffer[pos + 1] = ETX;
+    Some(pos + 2)
+}
+
+/// A DLE inside a message is doubled, so `DLE ETX` can only mean the end.
+fn push_escaped(buffer: &mut [u8], pos: usize, byte: u8) -> usize {
+    buffer[pos] = byte;
+    if byte == DLE {
+        buffer[pos + 1] = DLE;
+        return pos + 2;
+    }
+    pos + 1
+}
+
+/// Why an NGT-1 message was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ngt1Error {
+    /// No `DLE STX` in the input.
+    MissingStart,
+    /// Input ends before `DLE ETX`.
+    Truncated,
+    /// A DLE followed by neither DLE nor ETX: the stream is out of step.
+    BadEscape,
+    /// More bytes between STX and ETX than any N2K message can hold.
+    Overflow,
+    /// The length byte disagrees with the number of bytes received.
+    LengthMismatch { declared: u8, received: usize },
+    /// The sum of command, length, body and checksum is not zero.
+    BadChecksum,
+    /// A valid message, but not one carrying N2K data (e.g. a gateway
+    /// configuration reply).
+    UnsupportedCommand(u8),
+    /// The N2K header does not match the body: too short, or announcing more
+    /// data than it holds or than a fast-packet message can carry.
+    BadPayload,
+}
+
+/// Parses one complete NGT-1 message, `DLE STX` to `DLE ETX`, into the N2K
+/// message it carries.
+///
+/// Leading bytes before `DLE STX` are skipped. The checksum is verified, and
+/// the payload is returned whole: a fast-packet message still has to be split
+/// into frames before going onto the bus.
+pub fn parse_ngt1_message(data: &[u8]) -> Result<N2kMessage, Ngt1Error> {
+    let start = data
+        .windows(2)
+        .position(|w| w[0] == DLE && w[1] == STX)
+        .ok_or(Ngt1Error::MissingStart)?;
+
+    // command | len | body | checksum, unescaped.
+    let mut raw = [0u8; NGT1_MAX_RAW];
+    let mut raw_len = 0;
+    let mut i = start + 2;
+    loop {
+        let byte = *data.get(i).ok_or(Ngt1Error::Truncated)?;
+        i += 1;
+        let byte = if byte == DLE {
+            let next = *data.get(i).ok_or(Ngt1Error::Truncated)?;
+            i += 1;
+            match next {
+                DLE => DLE,
+                ETX => break,
+                _ => return Err(Ngt1Error::BadEscape),
+            }
+        } else {
+            byte
+        };
+        if raw_len == raw.len() {
+            return Err(Ngt1Error::Overflow);
+        }
+        raw[raw_len] = byte;
+        raw_len += 1;
+    }
+
+    check_ngt1_raw(&raw[..raw_len])?;
+    parse_ngt1_body(raw[0], &raw[2..raw_len - 1])
+}
+
+/// Checks the length byte and the checksum of an unescaped message.
+pub(crate) fn check_ngt1_raw(raw: &[u8]) -> Result<(), Ngt1Error> {
+    if raw.len() < 3 {
+        return Err(Ngt1Error::Truncated);
+    }
+    let declared = raw[1];
+    let received = raw.len() - 3;
+    if declared as usize != received {
+        return Err(Ngt1Error::LengthMismatch { declared, received });
+    }
+    let sum = raw.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    if sum != 0 {
+        return Err(Ngt1Error::BadChecksum);
+    }
+    Ok(())
+}
+
+/// Decodes the body of a 0x93 or 0x94 message, framing already removed.
+pub fn parse_ngt1_body(command: u8, body: &[u8]) -> Result<N2kMessage, Ngt1Error> {
+    let (source, timestamp_ms, header) = match command {
+        MSG_N2K_RECEIVED if body.len() >= RECEIVED_HEADER => (
+            body[5],
+            u32::from_le_bytes([body[6], body[7], body[8], body[9]]),
+            RECEIVED_HEADER,
+        ),
+        // The transmit command leaves the source to the gateway: 255 until
+        // someone puts the message on a bus.
+        MSG_N2K_DATA if body.len() >= 6 => (255, 0, 6),
+        MSG_N2K_RECEIVED | MSG_N2K_DATA => return Err(Ngt1Error::BadPayload),
+        other => return Err(Ngt1Error::UnsupportedCommand(other)),
+    };
+
+    let len = body[header - 1] as usize;
+    if len > MAX_FAST_PACKET_PAYLOAD || header + len > body.len() {
+        return Err(Ngt1Error::BadPayload);
+    }
+
+    let mut message = N2kMessage::new(
+        body[0],
+        u32::from_le_bytes([body[1], body[2], body[3], 0]),
+        source,
+        body[4],
+        &body[header..header + len],
+    )
+    .ok_or(Ngt1Error::BadPayload)?;
+    message.timestamp_ms = timestamp_ms;
+    Ok(message)
+}
+
+/// Running totals of an [`Ngt1Deframer`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Ngt1Counters {
+    /// N2K messages decoded.
+    pub messages: u32,
+    /// Bytes outside any `DLE STX`…`DLE ETX` pair: attaching mid-message,
+    /// line noise.
+    pub noise_bytes: u32,
+    /// Messages cut short, badly escaped, or whose length or N2K header does
+    /// not match their content.
+    pub framing_errors: u32,
+    pub checksum_errors: u32,
+    /// Messages longer than any N2K message, dropped until the next start.
+    pub overflows: u32,
+    /// Valid messages of another command, e.g. gateway replies.
+    pub other_commands: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DeframerState {
+    /// Between messages.
+    Idle,
+    /// Between messages, after a DLE.
+    IdleEscape,
+    InMessage,
+    /// In a message, after a DLE.
+    InEscape,
+}
+
+/// Incremental NGT-1 deframer: bytes go in as they arrive, in any split, and
+/// complete messages come out.
+///
+/// Escapes are tracked even between messages, so an escaped `DLE DLE` followed
+/// by 0x02 is never mistaken for a start when attaching to a running stream.
+/// A `DLE STX` inside a message abandons it and starts over: the sender was
+/// reset or bytes were lost.
+pub struct Ngt1Deframer {
+    state: DeframerState,
+    /// command | len | body | checksum, unescaped.
+    raw: [u8; NGT1_MAX_RAW],
+    len: usize,
+    counters: Ngt1Counters,
+}
+
+impl Ngt1Deframer {
+    pub const fn new() -> Self {
+        Self {
+            state: DeframerState::Idle,
+            raw: [0; NGT1_MAX_RAW],
+            len: 0,
+            counters: Ngt1Counters {
+                messages: 0,
+                noise_bytes: 0,
+                framing_errors: 0,
+                checksum_errors: 0,
+                overflows: 0,
+                other_commands: 0,
+            },
+        }
+    }
+
+    pub fn counters(&self) -> Ngt1Counters {
+        self.counters
+    }
+
+    /// Drops any partial message, e.g. after the port was reopened. Counters
+    /// are kept.
+    pub fn reset(&mut self) {
+        self.state = DeframerState::Idle;
+        self.len = 0;
+    }
+
+    /// Consumes one byte. `Some` when it ends a message, whether it decoded or
+    /// not; errors are also counted.
+    pub fn push(&mut self, byte: u8) -> Option<Result<N2kMessage, Ngt1Error>> {
+        match (self.state, byte) {
+            (DeframerState::Idle, DLE) => self.state = DeframerState::IdleEscape,
+            (DeframerState::Idle, _) => self.counters.noise_bytes += 1,
+            (DeframerState::IdleEscape, STX) => self.start(),
+            (DeframerState::IdleEscape, _) => {
+                // Either an escaped DLE or junk: both pairs are data of a
+                // message we did not see start.
+                self.counters.noise_bytes += 2;
+                self.state = DeframerState::Idle;
+            }
+            (DeframerState::InMessage, DLE) => self.state = DeframerState::InEscape,
+            (DeframerState::InMessage, _) => return self.store(byte),
+            (DeframerState::InEscape, DLE) => {
+                self.state = DeframerState::InMessage;
+                return self.store(DLE);
+            }
+            (DeframerState::InEscape, ETX) => {
+                self.state = DeframerState::Idle;
+                return Some(self.finish());
+            }
+            (DeframerState::InEscape, STX) => {
+                self.start();
+                return Some(self.fail(Ngt1Error::Truncated));
+            }
+            (DeframerState::InEscape, _) => {
+                self.state = DeframerState::Idle;
+                return Some(self.fail(Ngt1Error::BadEscape));
+            }
+        }
+        None
+    }
+
+    /// Consumes `bytes`, yielding each message as it completes. Bytes left
+    /// when the iterator is dropped are not consumed.
+    pub fn feed<'a>(&'a mut self, bytes: &'a [u8]) -> Ngt1Messages<'a> {
+        Ngt1Messages {
+            deframer: self,
+            bytes: bytes.iter(),
+        }
+    }
+
+    fn start(&mut self) {
+        self.state = DeframerState::InMessage;
+        self.len = 0;
+    }
+
+    fn store(&mut self, byte: u8) -> Option<Result<N2kMessage, Ngt1Error>> {
+        if self.len == self.raw.len() {
+            self.state = DeframerState::Idle;
+            return Some(self.fail(Ngt1Error::Overflow));
+        }
+        self.raw[self.len] = byte;
+        self.len += 1;
+        None
+    }
+
+    fn finish(&mut self) -> Result<N2kMessage, Ngt1Error> {
+        let raw = &self.raw[..self.len];
+        let result =
+            check_ngt1_raw(raw).and_then(|()| parse_ngt1_body(raw[0], &raw[2..raw.len() - 1]));
+        match result {
+            Ok(message) => {
+                self.counters.messages += 1;
+                Ok(message)
+            }
+            Err(error) => self.fail(error),
+        }
+    }
+
+    fn fail(&mut self, error: Ngt1Error) -> Result<N2kMessage, Ngt1Error> {
+        match error {
+            Ngt1Error::Overflow => self.counters.overflows += 1,
+            Ngt1Error::BadChecksum => self.counters.checksum_errors += 1,
+            Ngt1Error::UnsupportedCommand(_) => self.counters.other_commands += 1,
+            _ => self.counters.framing_errors += 1,
+        }
+        Err(error)
+    }
+}
+
+impl Default for Ngt1Deframer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Messages completed by a slice, see [`Ngt1Deframer::feed`].
+pub struct Ngt1Messages<'a> {
+    deframer: &'a mut Ngt1Deframer,
+    bytes: core::slice::Iter<'a, u8>,
+}
+
+impl Iterator for Ngt1Messages<'_> {
+    type Item = Result<N2kMessage, Ngt1Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.bytes
+            .by_ref()
+            .find_map(|&byte| self.deframer.push(byte))
+    }
+}
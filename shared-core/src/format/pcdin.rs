@@ -0,0 +1,125 @@
+//! SeaSmart `$PCDIN` sentences: a whole N2K message carried over NMEA 0183,
+//! for radios and loggers that only have a 0183 port.
+//!
+//! ```text
+//! $PCDIN,01F119,000C72EA,09,28C36A0000B40AFD*56
+//!        pgn    time     src data
+//! ```
+//!
+//! Fields are upper-case hex: the PGN on 6 digits, the sender's time in
+//! milliseconds on 8, the source address and the reassembled payload, then
+//! the usual XOR checksum. Priority and destination are not carried: parsed
+//! messages come back with priority 6, broadcast. A full fast-packet payload
+//! makes sentences far longer than the 82 characters of plain NMEA 0183.
+
+use korri_n2k::protocol::transport::fast_packet::MAX_FAST_PACKET_PAYLOAD;
+
+use super::{N2kMessage, parse_hex, u8_to_hex, u32_to_hex};
+
+/// `$PCDIN,` pgn, time, source, a full payload, checksum and CR LF.
+pub const PCDIN_MAX_SENTENCE: usize = 7 + 7 + 9 + 3 + 2 * MAX_FAST_PACKET_PAYLOAD + 3 + 2;
+
+const PREFIX: &[u8] = b"$PCDIN,";
+const DEFAULT_PRIORITY: u8 = 6;
+const BROADCAST: u8 = 0xFF;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PcdinError {
+    /// Not a `$PCDIN` sentence.
+    NotPcdin,
+    /// Missing `*HH`, or not the XOR of the sentence.
+    BadChecksum,
+    BadPgn,
+    BadTime,
+    BadSource,
+    /// Odd number of digits, not hex, or longer than a fast-packet payload.
+    BadData,
+}
+
+/// Formats `message` as one sentence, CR LF included.
+pub fn format_pcdin(message: &N2kMessage, buffer: &mut [u8; PCDIN_MAX_SENTENCE]) -> usize {
+    buffer[..PREFIX.len()].copy_from_slice(PREFIX);
+    let mut pos = PREFIX.len();
+
+    // u32_to_hex writes 8 digits: the PGN keeps the last 6.
+    let mut pgn = [0u8; 8];
+    u32_to_hex(message.pgn, &mut pgn, 0);
+    buffer[pos..pos + 6].copy_from_slice(&pgn[2..]);
+    buffer[pos + 6] = b',';
+    pos += 7;
+    u32_to_hex(message.timestamp_ms, buffer, pos);
+    buffer[pos + 8] = b',';
+    pos += 9;
+    u8_to_hex(message.source, buffer, pos);
+    buffer[pos + 2] = b',';
+    pos += 3;
+    for &byte in message.payload() {
+        u8_to_hex(byte, buffer, pos);
+        pos += 2;
+    }
+
+    let checksum = buffer[1..pos].iter().fold(0, |sum, &b| sum ^ b);
+    buffer[pos] = b'*';
+    u8_to_hex(checksum, buffer, pos + 1);
+    buffer[pos + 3] = b'\r';
+    buffer[pos + 4] = b'\n';
+    pos + 5
+}
+
+/// Parses one sentence; the line ending is optional.
+pub fn parse_pcdin(line: &[u8]) -> Result<N2kMessage, PcdinError> {
+    let end = line
+        .iter()
+        .rposition(|&b| b != b'\r' && b != b'\n')
+        .map_or(0, |last| last + 1);
+    let line = &line[..end];
+    if !line.starts_with(PREFIX) {
+        return Err(PcdinError::NotPcdin);
+    }
+
+    let star = line
+        .iter()
+        .rposition(|&b| b == b'*')
+        .ok_or(PcdinError::BadChecksum)?;
+    let expected = Some(&line[star + 1..])
+        .filter(|digits| digits.len() == 2)
+        .and_then(parse_hex)
+        .ok_or(PcdinError::BadChecksum)?;
+    let checksum = line[1..star].iter().fold(0, |sum, &b| sum ^ b);
+    if checksum as u32 != expected {
+        return Err(PcdinError::BadChecksum);
+    }
+
+    let mut fields = line[PREFIX.len()..star].split(|&b| b == b',');
+    let mut field = |len: usize, error: PcdinError| {
+        fields
+            .next()
+            .filter(|field| field.len() == len || len == 0)
+            .ok_or(error)
+    };
+    let pgn = parse_hex(field(6, PcdinError::BadPgn)?).ok_or(PcdinError::BadPgn)?;
+    let timestamp_ms = parse_hex(field(8, PcdinError::BadTime)?).ok_or(PcdinError::BadTime)?;
+    let source = parse_hex(field(2, PcdinError::BadSource)?).ok_or(PcdinError::BadSource)?;
+    let digits = field(0, PcdinError::BadData)?;
+    if fields.next().is_some() || !digits.len().is_multiple_of(2) {
+        return Err(PcdinError::BadData);
+    }
+
+    let mut data = [0u8; MAX_FAST_PACKET_PAYLOAD];
+    if digits.len() / 2 > data.len() {
+        return Err(PcdinError::BadData);
+    }
+    for (byte, pair) in data.iter_mut().zip(digits.chunks(2)) {
+        *byte = parse_hex(pair).ok_or(PcdinError::BadData)? as u8;
+    }
+    let mut message = N2kMessage::new(
+        DEFAULT_PRIORITY,
+        pgn,
+        source as u8,
+        BROADCAST,
+        &data[..digits.len() / 2],
+    )
+    .ok_or(PcdinError::BadData)?;
+    message.timestamp_ms = timestamp_ms;
+    Ok(message)
+}
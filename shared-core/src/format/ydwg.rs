@@ -0,0 +1,117 @@
+//! Yacht Devices RAW text protocol, spoken by the YDWG-02 gateway and the apps
+//! built for it.
+//!
+//! ```text
+//! gateway → app   hh:mm:ss.ddd R 19F51323 01 02 03 04 05 06 07 08
+//!                 hh:mm:ss.ddd T 19F51323 01 02 03 04 05 06 07 08
+//! app → gateway   19F51323 01 02 03 04 05 06 07 08
+//! ```
+//!
+//! Close to [`format_actisense`](super::format_actisense), but the time is the
+//! gateway clock in UTC, not an uptime, and `T` only echoes a frame an app
+//! asked the gateway to send. Apps send bare frames, without time nor
+//! direction. Every line is a single CAN frame: fast packets travel as their
+//! individual frames. Lines end with CR LF.
+//!
+//! Any other line is handed over as is, for the caller to make sense of.
+
+use korri_n2k::protocol::transport::can_frame::CanFrame;
+
+use super::{
+    ActisenseDirection, ActisenseError, ActisenseLine, parse_frame, parse_hex, parse_time,
+    split_fields, u8_to_dec, u8_to_hex, u32_to_hex, u64_to_dec3,
+};
+
+/// Longest line: time, direction, id, 8 data bytes and CR LF.
+pub const YDWG_MAX_LINE: usize = 12 + 2 + 9 + 8 * 3 + 2;
+
+const DAY_MS: u32 = 24 * 3600 * 1000;
+
+/// One line of the protocol, in either direction.
+#[derive(Clone, Debug)]
+pub enum YdwgMessage<'a> {
+    /// Frame read from the bus (`R`), or sent to it on behalf of an app (`T`).
+    Frame(ActisenseLine),
+    /// Frame an app asks the gateway to send.
+    Send(CanFrame),
+    /// Any other line, line ending removed.
+    Other(&'a [u8]),
+}
+
+/// Parses one line, from the gateway or from an app. The grammar of frames is
+/// the Actisense one, and so are the errors.
+pub fn parse_ydwg(line: &[u8]) -> Result<YdwgMessage<'_>, ActisenseError> {
+    let mut fields = split_fields(line);
+    let first = fields.next().ok_or(ActisenseError::Empty)?;
+
+    if first.get(2) == Some(&b':') {
+        let time_ms = parse_time(first)?;
+        let direction = match fields.next() {
+            Some(b"R") => ActisenseDirection::Received,
+            Some(b"T") => ActisenseDirection::Transmitted,
+            _ => return Err(ActisenseError::BadDirection),
+        };
+        return Ok(YdwgMessage::Frame(ActisenseLine {
+            time_ms,
+            direction,
+            frame: parse_frame(fields)?,
+        }));
+    }
+
+    if first.len() <= 8 && parse_hex(first).is_some() {
+        return Ok(YdwgMessage::Send(parse_frame(split_fields(line))?));
+    }
+
+    let end = line
+        .iter()
+        .rposition(|&b| b != b'\r' && b != b'\n')
+        .map_or(0, |last| last + 1);
+    Ok(YdwgMessage::Other(&line[..end]))
+}
+
+/// Formats a frame as the gateway reports it. `time_ms` is the UTC time of
+/// day, wrapped at 24 h.
+pub fn format_ydwg(
+    frame: &CanFrame,
+    time_ms: u32,
+    direction: ActisenseDirection,
+    buffer: &mut [u8; YDWG_MAX_LINE],
+) -> usize {
+    let time_ms = time_ms % DAY_MS;
+    let total_seconds = time_ms / 1000;
+
+    u8_to_dec((total_seconds / 3600) as u8, buffer, 0);
+    buffer[2] = b':';
+    u8_to_dec((total_seconds / 60 % 60) as u8, buffer, 3);
+    buffer[5] = b':';
+    u8_to_dec((total_seconds % 60) as u8, buffer, 6);
+    buffer[8] = b'.';
+    u64_to_dec3((time_ms % 1000) as u64, buffer, 9);
+    buffer[12] = b' ';
+    buffer[13] = match direction {
+        ActisenseDirection::Received => b'R',
+        ActisenseDirection::Transmitted => b'T',
+    };
+    buffer[14] = b' ';
+
+    15 + write_frame(frame, &mut buffer[15..])
+}
+
+/// Formats a frame as an app sends it to the gateway.
+pub fn format_ydwg_send(frame: &CanFrame, buffer: &mut [u8; YDWG_MAX_LINE]) -> usize {
+    write_frame(frame, buffer)
+}
+
+/// `CANID D0..D7` and CR LF.
+fn write_frame(frame: &CanFrame, buffer: &mut [u8]) -> usize {
+    u32_to_hex(frame.id.0 & 0x1FFF_FFFF, buffer, 0);
+    let mut pos = 8;
+    for &byte in &frame.data[..frame.len.min(8)] {
+        buffer[pos] = b' ';
+        u8_to_hex(byte, buffer, pos + 1);
+        pos += 3;
+    }
+    buffer[pos] = b'\r';
+    buffer[pos + 1] = b'\n';
+    pos + 2
+}
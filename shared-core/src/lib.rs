@@ -1,3 +1,6 @@
 #![no_std]
+pub mod fast_packet;
 pub mod format;
+pub mod nmea0183;
 pub mod pgns;
+pub mod transport;
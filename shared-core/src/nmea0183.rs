@@ -0,0 +1,391 @@
+//! NMEA 0183 sentences from decoded N2K messages, for older instruments and
+//! chart plotters.
+//!
+//! | PGN    | Sentences |
+//! |--------|-----------|
+//! | 129025 | GLL, RMC (with COG/SOG from 129026) |
+//! | 128267 | DPT, DBT  |
+//! | 128259 | VHW       |
+//! | 127245 | RSA       |
+//! | 129284 | BWC, RMB  |
+//! | 130310 | MTW, XDR  |
+//!
+//! 129026 is kept for the next RMC, and 126992 / 129029 for the UTC time of
+//! GLL, RMC and BWC. A field the N2K message marks as unavailable is left
+//! empty rather than sent as a bogus value.
+
+use core::fmt::{self, Write};
+
+use korri_n2k::infra::codec::traits::PgnData;
+use korri_n2k::protocol::lookups::{DirectionReference, YesNo};
+use korri_n2k::protocol::messages::{
+    Pgn126992, Pgn127245, Pgn128259, Pgn128267, Pgn129025, Pgn129026, Pgn129029, Pgn129284,
+    Pgn130310,
+};
+
+/// Longest sentence allowed by the standard, `$` and CR LF included.
+pub const MAX_SENTENCE: usize = 82;
+
+const KNOTS_PER_MS: f32 = 3600.0 / 1852.0;
+const METERS_PER_NM: f32 = 1852.0;
+const KELVIN: f32 = 273.15;
+
+/// One sentence, checksum and CR LF included.
+pub struct Sentence {
+    buffer: [u8; MAX_SENTENCE],
+    len: usize,
+}
+
+impl Sentence {
+    /// `$` + talker + `body` + `*HH` CR LF, or `None` if it would not fit.
+    fn new(talker: [u8; 2], body: fmt::Arguments) -> Option<Self> {
+        let mut sentence = Self {
+            buffer: [0; MAX_SENTENCE],
+            len: 0,
+        };
+        sentence.push(&[b'$', talker[0], talker[1]])?;
+        sentence.write_fmt(body).ok()?;
+        let checksum = sentence.buffer[1..sentence.len]
+            .iter()
+            .fold(0u8, |sum, &b| sum ^ b);
+        write!(sentence, "*{checksum:02X}\r\n").ok()?;
+        Some(sentence)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ASCII is ever written.
+        core::str::from_utf8(self.as_bytes()).unwrap_or("")
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> Option<()> {
+        let end = self.len + bytes.len();
+        self.buffer.get_mut(self.len..end)?.copy_from_slice(bytes);
+        self.len = end;
+        Some(())
+    }
+}
+
+impl Write for Sentence {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes()).ok_or(fmt::Error)
+    }
+}
+
+/// Keeps what a sentence needs from earlier messages.
+pub struct Nmea0183Converter {
+    talker: [u8; 2],
+    /// COG (rad, true) and SOG (m/s), from 129026.
+    cog_sog: (Option<f32>, Option<f32>),
+    /// Days since 1970 and seconds since midnight, UTC.
+    time: Option<(u16, f64)>,
+}
+
+impl Nmea0183Converter {
+    /// `talker` prefixes every sentence: `GP`, `II`, `SD`...
+    pub const fn new(talker: [u8; 2]) -> Self {
+        Self {
+            talker,
+            cog_sog: (None, None),
+            time: None,
+        }
+    }
+
+    /// `true` if `pgn` is one the converter reads, whether or not it yields a
+    /// sentence.
+    pub fn handles(pgn: u32) -> bool {
+        matches!(
+            pgn,
+            126992 | 127245 | 128259 | 128267 | 129025 | 129026 | 129029 | 129284 | 130310
+        )
+    }
+
+    /// Feeds one complete message (fast packets reassembled) and passes each
+    /// resulting sentence to `emit`. Unknown PGNs and undecodable payloads are
+    /// ignored.
+    pub fn convert(&mut self, pgn: u32, payload: &[u8], mut emit: impl FnMut(&Sentence)) {
+        let talker = self.talker;
+        let mut send = |body: fmt::Arguments| {
+            if let Some(sentence) = Sentence::new(talker, body) {
+                emit(&sentence);
+            }
+        };
+
+        match pgn {
+            126992 => {
+                if let Ok(time) = Pgn126992::from_payload(payload) {
+                    self.set_time(time.date, time.time);
+                }
+            }
+            129029 => {
+                if let Ok(gnss) = Pgn129029::from_payload(payload) {
+                    self.set_time(gnss.date, gnss.time);
+                }
+            }
+            129026 => {
+                if let Ok(cog_sog) = Pgn129026::from_payload(payload) {
+                    let cog = known(cog_sog.cog, 6.3)
+                        .filter(|_| cog_sog.cog_reference == DirectionReference::True0);
+                    self.cog_sog = (cog, known(cog_sog.sog, 655.0));
+                }
+            }
+            129025 => {
+                let Ok(position) = Pgn129025::from_payload(payload) else {
+                    return;
+                };
+                let (Some(lat), Some(lon)) = (
+                    known(position.latitude, 90.1),
+                    known(position.longitude, 180.1),
+                ) else {
+                    return;
+                };
+                let time = Time(self.time.map(|(_, seconds)| seconds));
+                let (lat, lon) = (Latitude(lat as f64), Longitude(lon as f64));
+                send(format_args!("GLL,{lat},{lon},{time},A,A"));
+
+                let (cog, sog) = self.cog_sog;
+                let date = Date(self.time.map(|(days, _)| days));
+                send(format_args!(
+                    "RMC,{time},A,{lat},{lon},{},{},{date},,,A",
+                    Num(sog.map(|sog| sog * KNOTS_PER_MS), 1),
+                    Num(cog.map(f32::to_degrees), 1)
+                ));
+            }
+            128267 => {
+                let Ok(depth) = Pgn128267::from_payload(payload) else {
+                    return;
+                };
+                let Some(meters) = known(depth.depth, 40_000_000.0) else {
+                    return;
+                };
+                send(format_args!(
+                    "DPT,{},{},{}",
+                    Num(Some(meters), 2),
+                    Num(known(depth.offset, 32.0), 1),
+                    Num(known(depth.range, 2500.0), 0)
+                ));
+                send(format_args!(
+                    "DBT,{},f,{},M,{},F",
+                    Num(Some(meters / 0.3048), 1),
+                    Num(Some(meters), 1),
+                    Num(Some(meters / 1.8288), 1)
+                ));
+            }
+            128259 => {
+                let Ok(speed) = Pgn128259::from_payload(payload) else {
+                    return;
+                };
+                let Some(speed) = known(speed.speed_water_referenced, 655.0) else {
+                    return;
+                };
+                send(format_args!(
+                    "VHW,,T,,M,{},N,{},K",
+                    Num(Some(speed * KNOTS_PER_MS), 1),
+                    Num(Some(speed * 3.6), 1)
+                ));
+            }
+            127245 => {
+                let Ok(rudder) = Pgn127245::from_payload(payload) else {
+                    return;
+                };
+                let Some(angle) = known(rudder.position, 3.2) else {
+                    return;
+                };
+                send(format_args!(
+                    "RSA,{},A,,V",
+                    Num(Some(angle.to_degrees()), 1)
+                ));
+            }
+            129284 => self.navigation(payload, &mut send),
+            130310 => {
+                let Ok(environment) = Pgn130310::from_payload(payload) else {
+                    return;
+                };
+                if let Some(water) = known(environment.water_temperature, 655.0) {
+                    send(format_args!("MTW,{},C", Num(Some(water - KELVIN), 1)));
+                }
+                let air = known(environment.outside_ambient_air_temperature, 655.0);
+                let pressure = known(environment.atmospheric_pressure, 6_553_000.0);
+                match (air, pressure) {
+                    (Some(air), Some(pressure)) => send(format_args!(
+                        "XDR,C,{},C,AirTemp,P,{},B,Barometer",
+                        Num(Some(air - KELVIN), 1),
+                        Num(Some(pressure / 100_000.0), 5)
+                    )),
+                    (Some(air), None) => send(format_args!(
+                        "XDR,C,{},C,AirTemp",
+                        Num(Some(air - KELVIN), 1)
+                    )),
+                    (None, Some(pressure)) => send(format_args!(
+                        "XDR,P,{},B,Barometer",
+                        Num(Some(pressure / 100_000.0), 5)
+                    )),
+                    (None, None) => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// 129284 to BWC and RMB. The bearing goes to the true or magnetic field
+    /// as the message says; RMB only has a true one.
+    fn navigation(&self, payload: &[u8], send: &mut impl FnMut(fmt::Arguments)) {
+        let Ok(navigation) = Pgn129284::from_payload(payload) else {
+            return;
+        };
+        let (Some(lat), Some(lon)) = (
+            known(navigation.destination_latitude, 90.1),
+            known(navigation.destination_longitude, 180.1),
+        ) else {
+            return;
+        };
+        let (lat, lon) = (Latitude(lat as f64), Longitude(lon as f64));
+        let time = Time(self.time.map(|(_, seconds)| seconds));
+        let range = Num(
+            known(navigation.distance_to_waypoint, 42_000_000.0).map(|m| m / METERS_PER_NM),
+            2,
+        );
+        let bearing =
+            known(navigation.bearing_position_to_destination_waypoint, 6.3).map(f32::to_degrees);
+        let (bearing_true, bearing_magnetic) = match navigation.course_bearing_reference {
+            DirectionReference::True0 => (bearing, None),
+            DirectionReference::Magnetic1 => (None, bearing),
+            _ => (None, None),
+        };
+        let origin = Waypoint(navigation.origin_waypoint_number);
+        let destination = Waypoint(navigation.destination_waypoint_number);
+
+        send(format_args!(
+            "BWC,{time},{lat},{lon},{},T,{},M,{range},N,{destination},A",
+            Num(bearing_true, 1),
+            Num(bearing_magnetic, 1)
+        ));
+
+        let arrived = match navigation.arrival_circle_entered {
+            YesNo::Yes => 'A',
+            _ => 'V',
+        };
+        send(format_args!(
+            "RMB,A,,,{origin},{destination},{lat},{lon},{range},{},{},{arrived},A",
+            Num(bearing_true, 1),
+            Num(
+                known(navigation.waypoint_closing_velocity, 327.0).map(|v| v * KNOTS_PER_MS),
+                1
+            )
+        ));
+    }
+
+    fn set_time(&mut self, date: u16, seconds: f64) {
+        // 0xFFFF days, or past 24 h: no fix yet.
+        if date < 0xFFFD && (0.0..86_400.0).contains(&seconds) {
+            self.time = Some((date, seconds));
+        }
+    }
+}
+
+/// `None` for the "no data" values, which decode far outside `limit`.
+fn known(value: f32, limit: f32) -> Option<f32> {
+    (value.abs() < limit).then_some(value)
+}
+
+/// Decimal field, empty when unknown.
+struct Num(Option<f32>, usize);
+
+impl fmt::Display for Num {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(value) => write!(f, "{value:.0$}", self.1),
+            None => Ok(()),
+        }
+    }
+}
+
+/// `ddmm.mmmm,N`
+struct Latitude(f64);
+
+/// `dddmm.mmmm,E`
+struct Longitude(f64);
+
+impl fmt::Display for Latitude {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_angle(f, self.0, 2, if self.0 < 0.0 { 'S' } else { 'N' })
+    }
+}
+
+impl fmt::Display for Longitude {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_angle(f, self.0, 3, if self.0 < 0.0 { 'W' } else { 'E' })
+    }
+}
+
+/// Degrees and minutes, rounded as a whole so 59.99995' never prints as 60'.
+fn write_angle(f: &mut fmt::Formatter<'_>, degrees: f64, width: usize, side: char) -> fmt::Result {
+    let abs = if degrees < 0.0 { -degrees } else { degrees };
+    let ten_thousandths = (abs * 60.0 * 10_000.0 + 0.5) as u64;
+    let whole = ten_thousandths / 600_000;
+    let minutes = ten_thousandths % 600_000;
+    write!(
+        f,
+        "{whole:0width$}{:02}.{:04},{side}",
+        minutes / 10_000,
+        minutes % 10_000
+    )
+}
+
+/// `hhmmss.ss`, empty when unknown.
+struct Time(Option<f64>);
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(seconds) = self.0 else {
+            return Ok(());
+        };
+        let centis = (seconds * 100.0 + 0.5) as u64 % 8_640_000;
+        write!(
+            f,
+            "{:02}{:02}{:02}.{:02}",
+            centis / 360_000,
+            centis / 6000 % 60,
+            centis / 100 % 60,
+            centis % 100
+        )
+    }
+}
+
+/// `ddmmyy` from days since 1970, empty when unknown.
+struct Date(Option<u16>);
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(days) = self.0 else {
+            return Ok(());
+        };
+        // Civil calendar from a day count (H. Hinnant's algorithm).
+        let z = days as i64 + 719_468;
+        let era = z / 146_097;
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        write!(f, "{day:02}{month:02}{:02}", year % 100)
+    }
+}
+
+/// Waypoint number as an id, empty when unavailable.
+struct Waypoint(u32);
+
+impl fmt::Display for Waypoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 < 0xFFFF_FFFD {
+            write!(f, "{}", self.0)
+        } else {
+            Ok(())
+        }
+    }
+}
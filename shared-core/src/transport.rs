@@ -0,0 +1,1001 @@
+//! ISO 11783-3 transport protocol: messages of 9 to 1785 bytes over TP.CM
+//! (60416) and TP.DT (60160).
+//!
+//! ```text
+//! BAM:     CM BAM ─► DT 1 ─► DT 2 ─► …                 50 ms apart, no reply
+//! RTS/CTS: CM RTS ─► ◄─ CM CTS (n, next) ─► DT next.. ─► … ◄─ CM EOM ACK
+//!
+//! CM:  control | size[2] | packets | max per CTS | pgn[3]
+//! CTS: control | packets | next | FF FF | pgn[3]
+//! DT:  seq (1..=255) | data[7], the last one padded with 0xFF
+//! ```
+//!
+//! [`TpSender`] and [`TpReceiver`] are state machines with no I/O: frames go
+//! in and out, time is passed in milliseconds. A node drives them with
+//! [`send_broadcast`], [`send_addressed`] and its [`AddressFrames`]; the host
+//! feeds a passive receiver with whatever the bus carries.
+
+use embassy_time::{Instant, Timer, with_deadline};
+use korri_n2k::protocol::managment::address_supervisor::{AddressFrames, AddressHandle};
+use korri_n2k::protocol::transport::{can_frame::CanFrame, can_id::CanId};
+
+pub const PGN_TP_CM: u32 = 60416;
+pub const PGN_TP_DT: u32 = 60160;
+
+/// 255 packets of 7 bytes.
+pub const TP_MAX_PAYLOAD: usize = 255 * 7;
+
+const CM_RTS: u8 = 16;
+const CM_CTS: u8 = 17;
+const CM_EOM_ACK: u8 = 19;
+const CM_BAM: u8 = 32;
+const CM_ABORT: u8 = 255;
+
+const GLOBAL: u8 = 255;
+const TP_PRIORITY: u8 = 7;
+
+/// Gap between BAM packets, the shortest the standard allows.
+pub const TP_BAM_INTERVAL_MS: u64 = 50;
+/// Receiver, between two packets.
+const T1_MS: u64 = 750;
+/// Receiver, between a CTS and its first packet.
+const T2_MS: u64 = 1250;
+/// Sender, between its last packet or RTS and the CTS or EOM ACK.
+const T3_MS: u64 = 1250;
+/// Sender, after a CTS holding the connection open (zero packets).
+const T4_MS: u64 = 1050;
+
+/// Connection abort reasons.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AbortReason {
+    AlreadyInSession = 1,
+    NoResources = 2,
+    Timeout = 3,
+    BadSequence = 7,
+    TooLarge = 9,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TpError {
+    /// Payload under 9 bytes (a single frame or a fast packet will do) or
+    /// over [`TP_MAX_PAYLOAD`].
+    BadSize,
+    /// No CTS or EOM ACK in time.
+    Timeout,
+    /// The receiver aborted, with its reason byte.
+    Aborted(u8),
+}
+
+/// What a [`TpSender`] needs next.
+#[derive(Clone, Debug)]
+pub enum TpStep {
+    Send(CanFrame),
+    /// Nothing to send before this time, in milliseconds; frames from the
+    /// receiver may come meanwhile.
+    Wait(u64),
+    Done,
+    Failed(TpError),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SendState {
+    Announce,
+    WaitCts {
+        deadline_ms: u64,
+    },
+    /// Packets up to `end` are due; BAM ones not before `not_before_ms`.
+    Data {
+        end: u8,
+        not_before_ms: u64,
+    },
+    WaitEomAck {
+        deadline_ms: u64,
+    },
+    /// An abort is due, then the failure is reported.
+    Abort(TpError),
+    Done,
+    Failed(TpError),
+}
+
+/// Sends one message, as BAM or over an RTS/CTS connection.
+pub struct TpSender<'a> {
+    pgn: u32,
+    priority: u8,
+    source: u8,
+    /// [`GLOBAL`] for BAM.
+    destination: u8,
+    data: &'a [u8],
+    packets: u8,
+    max_per_cts: u8,
+    /// Next packet to send.
+    next: u8,
+    state: SendState,
+}
+
+impl<'a> TpSender<'a> {
+    /// A broadcast: packets follow the announce every
+    /// [`TP_BAM_INTERVAL_MS`], nobody answers.
+    pub fn broadcast(pgn: u32, priority: u8, source: u8, data: &'a [u8]) -> Result<Self, TpError> {
+        Self::new(pgn, priority, source, GLOBAL, data, 0xFF)
+    }
+
+    /// A connection to `destination`, which paces the packets with its CTS.
+    /// `max_per_cts` caps the packets it may ask for at once, 0xFF for no cap.
+    pub fn addressed(
+        pgn: u32,
+        priority: u8,
+        source: u8,
+        destination: u8,
+        data: &'a [u8],
+        max_per_cts: u8,
+    ) -> Result<Self, TpError> {
+        Self::new(pgn, priority, source, destination, data, max_per_cts.max(1))
+    }
+
+    fn new(
+        pgn: u32,
+        priority: u8,
+        source: u8,
+        destination: u8,
+        data: &'a [u8],
+        max_per_cts: u8,
+    ) -> Result<Self, TpError> {
+        if !(9..=TP_MAX_PAYLOAD).contains(&data.len()) {
+            return Err(TpError::BadSize);
+        }
+        Ok(Self {
+            pgn,
+            priority,
+            source,
+            destination,
+            data,
+            packets: data.len().div_ceil(7) as u8,
+            max_per_cts,
+            next: 1,
+            state: SendState::Announce,
+        })
+    }
+
+    fn is_broadcast(&self) -> bool {
+        self.destination == GLOBAL
+    }
+
+    /// The next step at `now_ms`. Call again after each `Send`, and once the
+    /// `Wait` time is reached.
+    pub fn poll(&mut self, now_ms: u64) -> TpStep {
+        match self.state {
+            SendState::Announce => {
+                let (control, max) = if self.is_broadcast() {
+                    self.state = SendState::Data {
+                        end: self.packets,
+                        not_before_ms: now_ms + TP_BAM_INTERVAL_MS,
+                    };
+                    (CM_BAM, 0xFF)
+                } else {
+                    self.state = SendState::WaitCts {
+                        deadline_ms: now_ms + T3_MS,
+                    };
+                    (CM_RTS, self.max_per_cts)
+                };
+                let size = (self.data.len() as u16).to_le_bytes();
+                let data = [control, size[0], size[1], self.packets, max, 0, 0, 0];
+                TpStep::Send(self.cm_frame(data))
+            }
+            SendState::WaitCts { deadline_ms } | SendState::WaitEomAck { deadline_ms } => {
+                if now_ms >= deadline_ms {
+                    self.state = SendState::Abort(TpError::Timeout);
+                    return self.poll(now_ms);
+                }
+                TpStep::Wait(deadline_ms)
+            }
+            SendState::Data { end, not_before_ms } => {
+                if now_ms < not_before_ms {
+                    return TpStep::Wait(not_before_ms);
+                }
+                let frame = self.dt_frame(self.next);
+                self.state = if self.next < end {
+                    SendState::Data {
+                        end,
+                        not_before_ms: if self.is_broadcast() {
+                            now_ms + TP_BAM_INTERVAL_MS
+                        } else {
+                            now_ms
+                        },
+                    }
+                } else if self.is_broadcast() {
+                    SendState::Done
+                } else if end == self.packets {
+                    SendState::WaitEomAck {
+                        deadline_ms: now_ms + T3_MS,
+                    }
+                } else {
+                    SendState::WaitCts {
+                        deadline_ms: now_ms + T3_MS,
+                    }
+                };
+                self.next = self.next.saturating_add(1);
+                TpStep::Send(frame)
+            }
+            SendState::Abort(error) => {
+                self.state = SendState::Failed(error);
+                let reason = AbortReason::Timeout as u8;
+                TpStep::Send(self.cm_frame([CM_ABORT, reason, 0xFF, 0xFF, 0xFF, 0, 0, 0]))
+            }
+            SendState::Done => TpStep::Done,
+            SendState::Failed(error) => TpStep::Failed(error),
+        }
+    }
+
+    /// A frame from the bus: CTS, EOM ACK and aborts from the destination
+    /// are taken, anything else is ignored.
+    pub fn on_frame(&mut self, frame: &CanFrame, now_ms: u64) {
+        if self.is_broadcast()
+            || frame.id.pgn() != PGN_TP_CM
+            || frame.id.source_address() != self.destination
+            || frame.id.destination() != Some(self.source)
+            || frame.len < 8
+            || cm_pgn(&frame.data) != self.pgn
+        {
+            return;
+        }
+        let data = &frame.data;
+        match (data[0], self.state) {
+            (CM_CTS, SendState::WaitCts { .. } | SendState::Data { .. }) if data[1] == 0 => {
+                self.state = SendState::WaitCts {
+                    deadline_ms: now_ms + T4_MS,
+                };
+            }
+            (CM_CTS, SendState::WaitCts { .. } | SendState::Data { .. }) => {
+                // A CTS may rewind to have packets sent again.
+                let next = data[2].clamp(1, self.packets);
+                let count = data[1].min(self.max_per_cts);
+                self.next = next;
+                self.state = SendState::Data {
+                    end: next.saturating_add(count - 1).min(self.packets),
+                    not_before_ms: now_ms,
+                };
+            }
+            (CM_EOM_ACK, SendState::WaitEomAck { .. }) => self.state = SendState::Done,
+            (CM_ABORT, SendState::Done | SendState::Failed(_)) => {}
+            (CM_ABORT, _) => self.state = SendState::Failed(TpError::Aborted(data[1])),
+            _ => {}
+        }
+    }
+
+    fn cm_frame(&self, mut data: [u8; 8]) -> CanFrame {
+        data[5..8].copy_from_slice(&self.pgn.to_le_bytes()[..3]);
+        tp_frame(
+            PGN_TP_CM,
+            self.priority,
+            self.source,
+            self.destination,
+            data,
+        )
+    }
+
+    fn dt_frame(&self, seq: u8) -> CanFrame {
+        let start = (seq as usize - 1) * 7;
+        let chunk = &self.data[start..(start + 7).min(self.data.len())];
+        let mut data = [0xFF; 8];
+        data[0] = seq;
+        data[1..1 + chunk.len()].copy_from_slice(chunk);
+        tp_frame(
+            PGN_TP_DT,
+            self.priority,
+            self.source,
+            self.destination,
+            data,
+        )
+    }
+}
+
+fn now_ms() -> u64 {
+    Instant::now().as_millis()
+}
+
+/// Sends `sender`, a broadcast, through the supervisor.
+pub async fn send_broadcast<const N: usize>(
+    handle: &AddressHandle<'_, N>,
+    mut sender: TpSender<'_>,
+) -> Result<(), TpError> {
+    loop {
+        match sender.poll(now_ms()) {
+            TpStep::Send(frame) => handle.send_frame(&frame).await,
+            TpStep::Wait(until_ms) => Timer::at(Instant::from_millis(until_ms)).await,
+            TpStep::Done => return Ok(()),
+            TpStep::Failed(error) => return Err(error),
+        }
+    }
+}
+
+/// Sends `sender` through the supervisor, reading CTS and EOM ACK from
+/// `frames`. Other frames read meanwhile are dropped: no other task should
+/// read `frames` during the transfer.
+pub async fn send_addressed<const N: usize, const F: usize>(
+    handle: &AddressHandle<'_, N>,
+    frames: &mut AddressFrames<'_, F>,
+    mut sender: TpSender<'_>,
+) -> Result<(), TpError> {
+    loop {
+        match sender.poll(now_ms()) {
+            TpStep::Send(frame) => handle.send_frame(&frame).await,
+            TpStep::Wait(until_ms) => {
+                let deadline = Instant::from_millis(until_ms);
+                if let Ok(frame) = with_deadline(deadline, frames.recv()).await {
+                    sender.on_frame(&frame, now_ms());
+                }
+            }
+            TpStep::Done => return Ok(()),
+            TpStep::Failed(error) => return Err(error),
+        }
+    }
+}
+
+fn tp_frame(pgn: u32, priority: u8, source: u8, destination: u8, data: [u8; 8]) -> CanFrame {
+    let id = CanId::builder(pgn, source)
+        .with_priority(priority)
+        .to_destination(destination)
+        .build()
+        // PGNs 60416 and 60160 are PDU1: any destination fits.
+        .unwrap_or(CanId(0));
+    CanFrame { id, data, len: 8 }
+}
+
+fn cm_pgn(data: &[u8; 8]) -> u32 {
+    u32::from_le_bytes([data[5], data[6], data[7], 0])
+}
+
+fn abort_frame(source: u8, destination: u8, pgn: u32, reason: AbortReason) -> CanFrame {
+    let pgn = pgn.to_le_bytes();
+    let data = [
+        CM_ABORT,
+        reason as u8,
+        0xFF,
+        0xFF,
+        0xFF,
+        pgn[0],
+        pgn[1],
+        pgn[2],
+    ];
+    tp_frame(PGN_TP_CM, TP_PRIORITY, source, destination, data)
+}
+
+/// A message received whole.
+#[derive(Debug)]
+pub struct TpMessage<'a> {
+    pub pgn: u32,
+    /// Of the announce.
+    pub priority: u8,
+    pub source: u8,
+    /// 255 for BAM.
+    pub destination: u8,
+    pub data: &'a [u8],
+}
+
+/// What a frame did to a [`TpReceiver`].
+#[derive(Debug, Default)]
+pub struct TpReceived<'a> {
+    /// CTS, EOM ACK or abort to send back.
+    pub reply: Option<CanFrame>,
+    pub message: Option<TpMessage<'a>>,
+}
+
+/// Running totals of a [`TpReceiver`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TpCounters {
+    pub messages: u32,
+    /// Sessions dropped for a missing packet.
+    pub timeouts: u32,
+    /// Sessions aborted by either side.
+    pub aborts: u32,
+    /// Announces refused or dropped: too large, no free session.
+    pub rejected: u32,
+    /// Announces replacing an unfinished session of the same pair.
+    pub restarted: u32,
+    /// Packets out of sequence.
+    pub bad_sequence: u32,
+}
+
+struct RxSession {
+    pgn: u32,
+    priority: u8,
+    source: u8,
+    destination: u8,
+    size: usize,
+    packets: u8,
+    /// Next packet expected.
+    next: u8,
+    /// Packets per CTS: ours, capped by the sender's.
+    window: u8,
+    /// Last packet of the current CTS window.
+    window_end: u8,
+    deadline_ms: u64,
+    /// Handed out as a [`TpMessage`]: the slot is freed on the next call.
+    complete: bool,
+    data: [u8; TP_MAX_PAYLOAD],
+}
+
+/// Receives up to `N` messages at once, one per sender and destination
+/// pair.
+///
+/// With an address, the receiver is a node: it answers RTS sent to it with
+/// CTS windows of at most `window` packets, acknowledges, and aborts on
+/// timeout. Without one it only listens, taking every session on the bus,
+/// CTS included, and never replies.
+pub struct TpReceiver<const N: usize> {
+    address: Option<u8>,
+    window: u8,
+    sessions: [Option<RxSession>; N],
+    counters: TpCounters,
+}
+
+impl<const N: usize> TpReceiver<N> {
+    /// A node at `address`, asking for `window` packets per CTS.
+    pub const fn node(address: u8, window: u8) -> Self {
+        Self {
+            address: Some(address),
+            window: if window == 0 { 1 } else { window },
+            sessions: [const { None }; N],
+            counters: TpCounters {
+                messages: 0,
+                timeouts: 0,
+                aborts: 0,
+                rejected: 0,
+                restarted: 0,
+                bad_sequence: 0,
+            },
+        }
+    }
+
+    /// A listener, seeing every session on the bus.
+    pub const fn passive() -> Self {
+        let mut receiver = Self::node(GLOBAL, 0xFF);
+        receiver.address = None;
+        receiver
+    }
+
+    pub fn counters(&self) -> TpCounters {
+        self.counters
+    }
+
+    /// Follows the node's address, after a new claim.
+    pub fn set_address(&mut self, address: u8) {
+        self.address = Some(address);
+    }
+
+    /// Drops sessions past their deadline; for a node, returns the abort to
+    /// send for one of them. Call until `None`.
+    pub fn poll(&mut self, now_ms: u64) -> Option<CanFrame> {
+        self.release();
+        let address = self.address;
+        for slot in &mut self.sessions {
+            let Some(session) = slot.take_if(|session| now_ms >= session.deadline_ms) else {
+                continue;
+            };
+            self.counters.timeouts += 1;
+            if address.is_some() && session.destination != GLOBAL {
+                return Some(abort_frame(
+                    session.destination,
+                    session.source,
+                    session.pgn,
+                    AbortReason::Timeout,
+                ));
+            }
+        }
+        None
+    }
+
+    /// Takes a frame from the bus, any PGN.
+    pub fn on_frame(&mut self, frame: &CanFrame, now_ms: u64) -> TpReceived<'_> {
+        self.release();
+        let pgn = frame.id.pgn();
+        if (pgn != PGN_TP_CM && pgn != PGN_TP_DT) || frame.len < 8 {
+            return TpReceived::default();
+        }
+        let source = frame.id.source_address();
+        let destination = frame.id.destination().unwrap_or(GLOBAL);
+        if let Some(address) = self.address {
+            // A node only follows broadcasts and what is sent to it.
+            if destination != GLOBAL && destination != address {
+                return TpReceived::default();
+            }
+        }
+
+        if pgn == PGN_TP_DT {
+            return self.on_data(frame, source, destination, now_ms);
+        }
+        let data = &frame.data;
+        match data[0] {
+            CM_BAM | CM_RTS => self.on_announce(frame, source, destination, now_ms),
+            CM_CTS if self.address.is_none() => {
+                // Passive: the receiver (source here) sets the window.
+                if let Some(session) = self.find(destination, source) {
+                    session.next = data[2].max(1);
+                    session.window_end = data[2].saturating_add(data[1]).saturating_sub(1);
+                    session.deadline_ms = now_ms + if data[1] == 0 { T4_MS } else { T2_MS };
+                }
+                TpReceived::default()
+            }
+            CM_ABORT => {
+                // Either side may abort.
+                let dropped = self
+                    .slot(source, destination)
+                    .or_else(|| self.slot(destination, source))
+                    .map(|index| self.sessions[index].take());
+                if dropped.is_some() {
+                    self.counters.aborts += 1;
+                }
+                TpReceived::default()
+            }
+            _ => TpReceived::default(),
+        }
+    }
+
+    fn on_announce(
+        &mut self,
+        frame: &CanFrame,
+        source: u8,
+        destination: u8,
+        now_ms: u64,
+    ) -> TpReceived<'_> {
+        let data = &frame.data;
+        let bam = data[0] == CM_BAM;
+        let pgn = cm_pgn(data);
+        let size = u16::from_le_bytes([data[1], data[2]]) as usize;
+        let packets = data[3];
+        let node = self.address.filter(|_| !bam);
+
+        if let Some(index) = self.slot(source, destination) {
+            self.sessions[index] = None;
+            self.counters.restarted += 1;
+        }
+        let refuse = |reason| TpReceived {
+            reply: node.map(|address| abort_frame(address, source, pgn, reason)),
+            message: None,
+        };
+        if size > TP_MAX_PAYLOAD || size.div_ceil(7) != packets as usize || packets == 0 {
+            self.counters.rejected += 1;
+            return refuse(AbortReason::TooLarge);
+        }
+        let Some(slot) = self.sessions.iter_mut().find(|slot| slot.is_none()) else {
+            self.counters.rejected += 1;
+            return refuse(AbortReason::NoResources);
+        };
+
+        let window = self.window.min(data[4].max(1)).min(packets);
+        let session = slot.insert(RxSession {
+            pgn,
+            priority: frame.id.priority(),
+            source,
+            destination,
+            size,
+            packets,
+            next: 1,
+            window,
+            window_end: if bam { packets } else { window },
+            deadline_ms: now_ms + if bam { T1_MS } else { T2_MS },
+            complete: false,
+            data: [0xFF; TP_MAX_PAYLOAD],
+        });
+        TpReceived {
+            reply: node.map(|address| {
+                let pgn = pgn.to_le_bytes();
+                let data = [CM_CTS, window, 1, 0xFF, 0xFF, pgn[0], pgn[1], pgn[2]];
+                tp_frame(PGN_TP_CM, TP_PRIORITY, address, session.source, data)
+            }),
+            message: None,
+        }
+    }
+
+    fn on_data(
+        &mut self,
+        frame: &CanFrame,
+        source: u8,
+        destination: u8,
+        now_ms: u64,
+    ) -> TpReceived<'_> {
+        let node = self.address;
+        let Some(index) = self.slot(source, destination) else {
+            return TpReceived::default();
+        };
+        let seq = frame.data[0];
+        let Some(session) = self.sessions[index].as_mut() else {
+            return TpReceived::default();
+        };
+        if seq != session.next {
+            self.counters.bad_sequence += 1;
+            // A packet sent twice is harmless; a gap loses the message.
+            if seq < session.next {
+                return TpReceived::default();
+            }
+            let reply = node
+                .filter(|_| session.destination != GLOBAL)
+                .map(|address| abort_frame(address, source, session.pgn, AbortReason::BadSequence));
+            self.sessions[index] = None;
+            return TpReceived {
+                reply,
+                message: None,
+            };
+        }
+
+        let start = (seq as usize - 1) * 7;
+        let end = (start + 7).min(session.size);
+        session.data[start..end].copy_from_slice(&frame.data[1..1 + end - start]);
+        session.next = seq.saturating_add(1);
+        session.deadline_ms = now_ms + T1_MS;
+
+        let mut reply = None;
+        if seq == session.packets {
+            session.complete = true;
+            self.counters.messages += 1;
+            reply = node
+                .filter(|_| session.destination != GLOBAL)
+                .map(|address| {
+                    let size = (session.size as u16).to_le_bytes();
+                    let pgn = session.pgn.to_le_bytes();
+                    let data = [
+                        CM_EOM_ACK,
+                        size[0],
+                        size[1],
+                        session.packets,
+                        0xFF,
+                        pgn[0],
+                        pgn[1],
+                        pgn[2],
+                    ];
+                    tp_frame(PGN_TP_CM, TP_PRIORITY, address, source, data)
+                });
+        } else if let Some(address) = node
+            && seq == session.window_end
+            && session.destination != GLOBAL
+        {
+            let count = session.window.min(session.packets - seq);
+            session.window_end = seq + count;
+            session.deadline_ms = now_ms + T2_MS;
+            let pgn = session.pgn.to_le_bytes();
+            let data = [CM_CTS, count, seq + 1, 0xFF, 0xFF, pgn[0], pgn[1], pgn[2]];
+            reply = Some(tp_frame(PGN_TP_CM, TP_PRIORITY, address, source, data));
+        }
+
+        let message = self.sessions[index]
+            .as_ref()
+            .filter(|session| session.complete)
+            .map(|session| TpMessage {
+                pgn: session.pgn,
+                priority: session.priority,
+                source: session.source,
+                destination: session.destination,
+                data: &session.data[..session.size],
+            });
+        TpReceived { reply, message }
+    }
+
+    /// Frees the slot of the message handed out last.
+    fn release(&mut self) {
+        for slot in &mut self.sessions {
+            slot.take_if(|session| session.complete);
+        }
+    }
+
+    fn slot(&self, source: u8, destination: u8) -> Option<usize> {
+        self.sessions.iter().position(|slot| {
+            slot.as_ref()
+                .is_some_and(|s| s.source == source && s.destination == destination)
+        })
+    }
+
+    fn find(&mut self, source: u8, destination: u8) -> Option<&mut RxSession> {
+        let index = self.slot(source, destination)?;
+        self.sessions[index].as_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PGN: u32 = 126464;
+    const SENDER: u8 = 10;
+    const RECEIVER: u8 = 20;
+
+    /// What went over the bus, and the message the receiver handed out.
+    struct Link {
+        now_ms: u64,
+        /// PGN and the first three data bytes of each frame, in bus order.
+        frames: [(u32, u8, u8, u8); 32],
+        len: usize,
+        message: Option<([u8; 64], usize, u8)>,
+    }
+
+    impl Link {
+        fn new() -> Self {
+            Self {
+                now_ms: 0,
+                frames: [(0, 0, 0, 0); 32],
+                len: 0,
+                message: None,
+            }
+        }
+
+        fn trace(&self) -> &[(u32, u8, u8, u8)] {
+            &self.frames[..self.len]
+        }
+
+        fn log(&mut self, frame: &CanFrame) {
+            let data = &frame.data;
+            self.frames[self.len] = (frame.id.pgn(), data[0], data[1], data[2]);
+            self.len += 1;
+        }
+
+        /// Runs `sender` to its end against `receiver`. A reply reaches the
+        /// sender only once it waits, so a sender that does not stop for its
+        /// CTS shows in the trace.
+        fn run<const N: usize>(
+            &mut self,
+            sender: &mut TpSender<'_>,
+            receiver: &mut TpReceiver<N>,
+        ) -> TpStep {
+            let mut reply: Option<CanFrame> = None;
+            loop {
+                match sender.poll(self.now_ms) {
+                    TpStep::Send(frame) => {
+                        self.log(&frame);
+                        let received = receiver.on_frame(&frame, self.now_ms);
+                        if let Some(message) = received.message {
+                            let mut data = [0; 64];
+                            data[..message.data.len()].copy_from_slice(message.data);
+                            self.message = Some((data, message.data.len(), message.destination));
+                        }
+                        if let Some(frame) = received.reply {
+                            assert!(reply.is_none(), "two replies in a row");
+                            reply = Some(frame);
+                        }
+                    }
+                    TpStep::Wait(until_ms) => match reply.take() {
+                        Some(frame) => {
+                            self.log(&frame);
+                            sender.on_frame(&frame, self.now_ms);
+                        }
+                        None => self.now_ms = until_ms,
+                    },
+                    step => return step,
+                }
+            }
+        }
+    }
+
+    fn payload<const L: usize>() -> [u8; L] {
+        core::array::from_fn(|i| i as u8)
+    }
+
+    fn dt(seq: u8) -> CanFrame {
+        tp_frame(
+            PGN_TP_DT,
+            TP_PRIORITY,
+            SENDER,
+            RECEIVER,
+            [seq, 0, 0, 0, 0, 0, 0, 0],
+        )
+    }
+
+    #[test]
+    fn bam_round_trip() {
+        let data = payload::<20>();
+        let mut sender = TpSender::broadcast(PGN, 6, SENDER, &data).unwrap();
+        let mut receiver = TpReceiver::<2>::node(RECEIVER, 0xFF);
+        let mut link = Link::new();
+
+        assert!(matches!(link.run(&mut sender, &mut receiver), TpStep::Done));
+        assert_eq!(
+            link.trace(),
+            [
+                (PGN_TP_CM, CM_BAM, 20, 0),
+                (PGN_TP_DT, 1, 0, 1),
+                (PGN_TP_DT, 2, 7, 8),
+                (PGN_TP_DT, 3, 14, 15),
+            ]
+        );
+        assert_eq!(link.now_ms, 3 * TP_BAM_INTERVAL_MS);
+        let (received, len, destination) = link.message.unwrap();
+        assert_eq!(&received[..len], data);
+        assert_eq!(destination, GLOBAL);
+        assert_eq!(receiver.counters().messages, 1);
+    }
+
+    #[test]
+    fn cts_window_smaller_than_message() {
+        // 8 packets, 3 per CTS: the sender stops after each window.
+        let data = payload::<50>();
+        let mut sender = TpSender::addressed(PGN, 6, SENDER, RECEIVER, &data, 0xFF).unwrap();
+        let mut receiver = TpReceiver::<2>::node(RECEIVER, 3);
+        let mut link = Link::new();
+
+        assert!(matches!(link.run(&mut sender, &mut receiver), TpStep::Done));
+        assert_eq!(
+            link.trace(),
+            [
+                (PGN_TP_CM, CM_RTS, 50, 0),
+                (PGN_TP_CM, CM_CTS, 3, 1),
+                (PGN_TP_DT, 1, 0, 1),
+                (PGN_TP_DT, 2, 7, 8),
+                (PGN_TP_DT, 3, 14, 15),
+                (PGN_TP_CM, CM_CTS, 3, 4),
+                (PGN_TP_DT, 4, 21, 22),
+                (PGN_TP_DT, 5, 28, 29),
+                (PGN_TP_DT, 6, 35, 36),
+                (PGN_TP_CM, CM_CTS, 2, 7),
+                (PGN_TP_DT, 7, 42, 43),
+                (PGN_TP_DT, 8, 49, 0xFF),
+                (PGN_TP_CM, CM_EOM_ACK, 50, 0),
+            ]
+        );
+        let (received, len, destination) = link.message.unwrap();
+        assert_eq!(&received[..len], data);
+        assert_eq!(destination, RECEIVER);
+    }
+
+    #[test]
+    fn cts_holding_the_connection_open() {
+        let data = payload::<20>();
+        let mut sender = TpSender::addressed(PGN, 6, SENDER, RECEIVER, &data, 0xFF).unwrap();
+        let hold = tp_frame(
+            PGN_TP_CM,
+            TP_PRIORITY,
+            RECEIVER,
+            SENDER,
+            [CM_CTS, 0, 0xFF, 0xFF, 0xFF, 0x00, 0xEE, 0x01],
+        );
+        let resume = tp_frame(
+            PGN_TP_CM,
+            TP_PRIORITY,
+            RECEIVER,
+            SENDER,
+            [CM_CTS, 3, 1, 0xFF, 0xFF, 0x00, 0xEE, 0x01],
+        );
+
+        assert!(matches!(sender.poll(0), TpStep::Send(_)));
+        sender.on_frame(&hold, 100);
+        assert!(matches!(sender.poll(100), TpStep::Wait(ms) if ms == 100 + T4_MS));
+        sender.on_frame(&resume, 500);
+        assert!(matches!(sender.poll(500), TpStep::Send(frame) if frame.data[0] == 1));
+    }
+
+    #[test]
+    fn sender_times_out_without_cts() {
+        let data = payload::<20>();
+        let mut sender = TpSender::addressed(PGN, 6, SENDER, RECEIVER, &data, 0xFF).unwrap();
+
+        assert!(matches!(sender.poll(0), TpStep::Send(_)));
+        assert!(matches!(sender.poll(0), TpStep::Wait(T3_MS)));
+        assert!(matches!(sender.poll(T3_MS - 1), TpStep::Wait(T3_MS)));
+        match sender.poll(T3_MS) {
+            TpStep::Send(frame) => {
+                assert_eq!(frame.id.pgn(), PGN_TP_CM);
+                assert_eq!(frame.id.destination(), Some(RECEIVER));
+                assert_eq!(frame.data[..2], [CM_ABORT, AbortReason::Timeout as u8]);
+                assert_eq!(cm_pgn(&frame.data), PGN);
+            }
+            step => panic!("expected an abort, got {step:?}"),
+        }
+        assert!(matches!(
+            sender.poll(T3_MS),
+            TpStep::Failed(TpError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn receiver_times_out_between_packets() {
+        let data = payload::<20>();
+        let mut sender = TpSender::addressed(PGN, 6, SENDER, RECEIVER, &data, 0xFF).unwrap();
+        let mut receiver = TpReceiver::<2>::node(RECEIVER, 0xFF);
+
+        let TpStep::Send(rts) = sender.poll(0) else {
+            panic!("expected an RTS");
+        };
+        assert!(receiver.on_frame(&rts, 0).reply.is_some());
+        assert!(receiver.on_frame(&dt(1), 10).reply.is_none());
+
+        assert!(receiver.poll(10 + T1_MS - 1).is_none());
+        let abort = receiver.poll(10 + T1_MS).unwrap();
+        assert_eq!(abort.id.source_address(), RECEIVER);
+        assert_eq!(abort.id.destination(), Some(SENDER));
+        assert_eq!(abort.data[..2], [CM_ABORT, AbortReason::Timeout as u8]);
+        assert!(receiver.poll(10 + T1_MS).is_none());
+        assert_eq!(receiver.counters().timeouts, 1);
+
+        // The sender gives up on the abort.
+        sender.on_frame(&abort, 10 + T1_MS);
+        assert!(matches!(
+            sender.poll(10 + T1_MS),
+            TpStep::Failed(TpError::Aborted(3))
+        ));
+    }
+
+    #[test]
+    fn peer_abort() {
+        // A receiver with a single session refuses a second sender.
+        let first = payload::<20>();
+        let second = payload::<30>();
+        let mut busy = TpSender::addressed(PGN, 6, SENDER, RECEIVER, &first, 0xFF).unwrap();
+        let mut refused = TpSender::addressed(PGN, 6, SENDER + 1, RECEIVER, &second, 0xFF).unwrap();
+        let mut receiver = TpReceiver::<1>::node(RECEIVER, 0xFF);
+
+        let TpStep::Send(rts) = busy.poll(0) else {
+            panic!("expected an RTS");
+        };
+        assert!(receiver.on_frame(&rts, 0).reply.is_some());
+        let mut link = Link::new();
+        assert!(matches!(
+            link.run(&mut refused, &mut receiver),
+            TpStep::Failed(TpError::Aborted(2))
+        ));
+        assert_eq!(
+            link.trace(),
+            [
+                (PGN_TP_CM, CM_RTS, 30, 0),
+                (PGN_TP_CM, CM_ABORT, AbortReason::NoResources as u8, 0xFF),
+            ]
+        );
+        assert_eq!(receiver.counters().rejected, 1);
+
+        // The sender aborts in turn: the receiver drops the session.
+        let abort = abort_frame(SENDER, RECEIVER, PGN, AbortReason::Timeout);
+        assert!(receiver.on_frame(&abort, 20).reply.is_none());
+        assert_eq!(receiver.counters().aborts, 1);
+        let received = receiver.on_frame(&dt(1), 30);
+        assert!(received.reply.is_none() && received.message.is_none());
+    }
+
+    #[test]
+    fn out_of_order_packet() {
+        let data = payload::<20>();
+        let mut sender = TpSender::addressed(PGN, 6, SENDER, RECEIVER, &data, 0xFF).unwrap();
+        let mut receiver = TpReceiver::<2>::node(RECEIVER, 0xFF);
+        let TpStep::Send(rts) = sender.poll(0) else {
+            panic!("expected an RTS");
+        };
+        receiver.on_frame(&rts, 0);
+
+        // A packet sent twice is ignored.
+        receiver.on_frame(&dt(1), 10);
+        assert!(receiver.on_frame(&dt(1), 20).reply.is_none());
+        assert_eq!(receiver.counters().bad_sequence, 1);
+
+        // A gap aborts the session.
+        let abort = receiver.on_frame(&dt(3), 30).reply.unwrap();
+        assert_eq!(abort.id.destination(), Some(SENDER));
+        assert_eq!(abort.data[..2], [CM_ABORT, AbortReason::BadSequence as u8]);
+        assert_eq!(receiver.counters().bad_sequence, 2);
+        let received = receiver.on_frame(&dt(2), 40);
+        assert!(received.reply.is_none() && received.message.is_none());
+        assert_eq!(receiver.counters().messages, 0);
+    }
+
+    #[test]
+    fn passive_receiver_drops_out_of_order_silently() {
+        let mut receiver = TpReceiver::<2>::passive();
+        let bam = tp_frame(
+            PGN_TP_CM,
+            TP_PRIORITY,
+            SENDER,
+            GLOBAL,
+            [CM_BAM, 20, 0, 3, 0xFF, 0x00, 0xEE, 0x01],
+        );
+        let dt = |seq| {
+            tp_frame(
+                PGN_TP_DT,
+                TP_PRIORITY,
+                SENDER,
+                GLOBAL,
+                [seq, 0, 0, 0, 0, 0, 0, 0],
+            )
+        };
+        receiver.on_frame(&bam, 0);
+        receiver.on_frame(&dt(1), 50);
+        let received = receiver.on_frame(&dt(3), 100);
+        assert!(received.reply.is_none() && received.message.is_none());
+        assert_eq!(receiver.counters().bad_sequence, 1);
+        assert!(receiver.on_frame(&dt(2), 150).message.is_none());
+    }
+}
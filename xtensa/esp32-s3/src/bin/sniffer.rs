@@ -14,13 +14,15 @@ use embassy_executor::Spawner;
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
 use esp_hal::{
     clock::CpuClock,
+    efuse::Efuse,
     timer::timg::TimerGroup,
     twai::{filter::SingleStandardFilter, TwaiConfiguration, TwaiMode},
     usb_serial_jtag::UsbSerialJtag,
 };
 use static_cell::StaticCell;
 
-use esp32_s3::capture::{can_reader, usb_sink, TimestampedFrame, CAPTURE_DEPTH};
+use esp32_s3::capture::wire::{Metadata, FILTER_ACCEPT_ALL, FIRMWARE_VERSION, MODE_LISTEN_ONLY};
+use esp32_s3::capture::{can_reader, usb_control, usb_sink, TimestampedFrame, CAPTURE_DEPTH};
 use esp32_s3::conf::{N2K_BITRATE, N2K_BITRATE_BPS};
 
 esp_bootloader_esp_idf::esp_app_desc!();
@@ -31,7 +33,8 @@ static CAPTURE_CHANNEL: StaticCell<
 
 /// Accept everything. Set explicitly rather than relying on the esp-hal default.
 /// A *standard* don't-care filter lets both extended and standard frames
-/// through; a `SingleExtendedFilter` would drop standard frames.
+/// through; a `SingleExtendedFilter` would drop standard frames. Reported to
+/// the host as `FILTER_ACCEPT_ALL`.
 const ACCEPT_ALL: SingleStandardFilter =
     SingleStandardFilter::new(b"xxxxxxxxxxx", b"x", [b"xxxxxxxx", b"xxxxxxxx"]);
 
@@ -53,7 +56,7 @@ async fn main(spawner: Spawner) {
     config.set_filter(ACCEPT_ALL);
     let can = config.start();
 
-    let (_usb_rx, usb_tx) = UsbSerialJtag::new(peripherals.USB_DEVICE)
+    let (usb_rx, usb_tx) = UsbSerialJtag::new(peripherals.USB_DEVICE)
         .into_async()
         .split();
 
@@ -64,6 +67,20 @@ async fn main(spawner: Spawner) {
         CAPTURE_DEPTH
     );
 
+    // Must match the TWAI configuration above.
+    let metadata = Metadata {
+        firmware: FIRMWARE_VERSION,
+        chip_id: Efuse::read_base_mac_address(),
+        mode: MODE_LISTEN_ONLY,
+        filter: FILTER_ACCEPT_ALL,
+    };
+
     spawner.must_spawn(can_reader(can, channel.sender()));
-    spawner.must_spawn(usb_sink(usb_tx, channel.receiver(), N2K_BITRATE_BPS));
+    spawner.must_spawn(usb_sink(
+        usb_tx,
+        channel.receiver(),
+        N2K_BITRATE_BPS,
+        metadata,
+    ));
+    spawner.must_spawn(usb_control(usb_rx));
 }
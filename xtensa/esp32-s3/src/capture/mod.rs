@@ -8,12 +8,16 @@
 //! Each buffer only covers the delay of the stage right after it: interrupt
 //! latency, then task latency, then USB host slowness.
 //!
+//! The way back only carries the host's choice of wire version, read by
+//! `usb_control`; see [`wire`] for both formats.
+//!
 //! The node is a passive listener: it never transmits, never claims an address
 //! and never acknowledges. Nothing can block reception, and the observed bus is
 //! left untouched.
 
 pub mod can_reader;
 pub mod frame;
+pub mod usb_control;
 pub mod usb_sink;
 pub mod wire;
 
@@ -23,4 +27,5 @@ pub const CAPTURE_DEPTH: usize = 1024;
 
 pub use can_reader::can_reader;
 pub use frame::{CaptureStats, StatsSnapshot, TimestampedFrame, STATS};
+pub use usb_control::usb_control;
 pub use usb_sink::usb_sink;
@@ -0,0 +1,38 @@
+//! Reads the host's requests on the USB Serial/JTAG port.
+//!
+//! The only one so far is the wire version: `VERSION_REQUEST` then a version
+//! byte. Anything else is ignored, so a terminal left open on the port does no
+//! harm.
+
+use core::sync::atomic::Ordering;
+
+use defmt::info;
+use embedded_io_async::Read;
+use esp_hal::{usb_serial_jtag::UsbSerialJtagRx, Async};
+
+use super::usb_sink::REQUESTED_VERSION;
+use super::wire::VERSION_REQUEST;
+
+#[embassy_executor::task]
+pub async fn usb_control(mut usb: UsbSerialJtagRx<'static, Async>) {
+    let mut buffer = [0u8; 16];
+    // Bytes of `VERSION_REQUEST` matched so far.
+    let mut matched = 0usize;
+
+    loop {
+        let Ok(n) = usb.read(&mut buffer).await else {
+            continue;
+        };
+        for &byte in &buffer[..n] {
+            if matched == VERSION_REQUEST.len() {
+                info!("host asks for capture wire v{}", byte);
+                REQUESTED_VERSION.store(byte, Ordering::Relaxed);
+                matched = 0;
+            } else if byte == VERSION_REQUEST[matched] {
+                matched += 1;
+            } else {
+                matched = usize::from(byte == VERSION_REQUEST[0]);
+            }
+        }
+    }
+}
@@ -2,9 +2,10 @@
 //!
 //! Writes are batched: USB works in 64-byte packets, so one write per record
 //! would collapse throughput. This task also emits the counter snapshots, since
-//! it owns the port.
+//! it owns the port. Each batch is built in the wire version the host last
+//! asked for, see [`REQUESTED_VERSION`].
 
-use core::sync::atomic::Ordering;
+use core::sync::atomic::{AtomicU8, Ordering};
 
 use defmt::{info, warn};
 use embassy_futures::select::{select, Either};
@@ -13,66 +14,148 @@ use embassy_time::{Duration, Instant, Timer};
 use embedded_io_async::Write;
 use esp_hal::{usb_serial_jtag::UsbSerialJtagTx, Async};
 
-use super::frame::{TimestampedFrame, STATS};
-use super::wire::{encode_frame, encode_header, encode_stats, RECORD_SIZE};
+use super::frame::{StatsSnapshot, TimestampedFrame, STATS};
+use super::wire::{
+    encode_batch_start, encode_compact_frame, encode_frame, encode_full_stats, encode_header,
+    encode_metadata, encode_stats, negotiate, seal_batch, Metadata, RECORD_SIZE, VERSION,
+    VERSION_MIN,
+};
 use super::CAPTURE_DEPTH;
 
-/// Frames per batch, plus room for one header and one stats record.
+/// Frames per batch, plus room for one header and one stats record. v1 is the
+/// larger of the two formats, and sizes the buffer.
 const BATCH_FRAMES: usize = 21;
-const FRAMES_BYTES: usize = RECORD_SIZE * BATCH_FRAMES;
 const BATCH_BYTES: usize = RECORD_SIZE * (BATCH_FRAMES + 2);
 
 const STATS_PERIOD: Duration = Duration::from_secs(1);
 
+/// Wire version asked for by the host, set by `usb_control`. Taken into
+/// account from the next batch. v1 until a host asks: a plain `cat` of the
+/// port, as in the Justfile, gets what `decode_capture.py` reads.
+pub static REQUESTED_VERSION: AtomicU8 = AtomicU8::new(VERSION_MIN);
+
+/// The next USB write, in the version negotiated when it was started.
+struct Batch {
+    version: u8,
+    bytes: [u8; BATCH_BYTES],
+    filled: usize,
+    frames: usize,
+    records: u32,
+    base_us: u64,
+}
+
+impl Batch {
+    const fn new() -> Self {
+        Self {
+            version: VERSION,
+            bytes: [0; BATCH_BYTES],
+            filled: 0,
+            frames: 0,
+            records: 0,
+            base_us: 0,
+        }
+    }
+
+    /// v2 frame timestamps are stored relative to `base_us`, and its records
+    /// numbered from `seq`.
+    fn start(&mut self, version: u8, seq: u16, base_us: u64) {
+        self.version = version;
+        self.frames = 0;
+        self.records = 0;
+        self.base_us = base_us;
+        self.filled = if version >= 2 {
+            encode_batch_start(seq, base_us, &mut self.bytes)
+        } else {
+            0
+        };
+    }
+
+    fn is_full(&self) -> bool {
+        self.frames >= BATCH_FRAMES
+    }
+
+    fn push_frame(&mut self, frame: &TimestampedFrame, seq: u16) {
+        let out = &mut self.bytes[self.filled..];
+        self.filled += if self.version >= 2 {
+            encode_compact_frame(frame, self.base_us, out)
+        } else {
+            out[..RECORD_SIZE].copy_from_slice(&encode_frame(frame, seq));
+            RECORD_SIZE
+        };
+        self.frames += 1;
+        self.records += 1;
+    }
+
+    /// Header, metadata (v2 only: v1 has no record for it) and snapshot.
+    fn push_session(&mut self, bitrate: u32, metadata: &Metadata, stats: &StatsSnapshot, seq: u16) {
+        let (version, len) = encode_header(self.version, bitrate, &mut self.bytes[self.filled..]);
+        self.filled += len;
+        if version >= 2 {
+            self.filled += encode_metadata(metadata, &mut self.bytes[self.filled..]);
+            self.filled += encode_full_stats(stats, &mut self.bytes[self.filled..]);
+            self.records += 3;
+        } else {
+            self.bytes[self.filled..self.filled + RECORD_SIZE]
+                .copy_from_slice(&encode_stats(stats, seq));
+            self.filled += RECORD_SIZE;
+            self.records += 2;
+        }
+    }
+
+    /// The bytes to write, v2 length and CRC filled in.
+    fn finish(&mut self) -> &[u8] {
+        if self.version >= 2 {
+            self.filled = seal_batch(&mut self.bytes, self.filled);
+        }
+        &self.bytes[..self.filled]
+    }
+}
+
 #[embassy_executor::task]
 pub async fn usb_sink(
     mut usb: UsbSerialJtagTx<'static, Async>,
     receiver: Receiver<'static, CriticalSectionRawMutex, TimestampedFrame, CAPTURE_DEPTH>,
     bitrate: u32,
+    metadata: Metadata,
 ) {
-    let header = encode_header(bitrate);
-    let mut buffer = [0u8; BATCH_BYTES];
+    let mut batch = Batch::new();
     let mut seq: u16 = 0;
+    let mut version = 0;
 
     // Already expired, so the header and a first snapshot go out immediately.
     let mut next_stats = Instant::now();
 
     loop {
-        let mut filled = 0usize;
+        let requested = negotiate(REQUESTED_VERSION.load(Ordering::Relaxed));
+        if requested != version {
+            info!("capture wire format v{}", requested);
+            version = requested;
+        }
 
         // Block on the first frame, but still honour the stats deadline if the
         // bus goes quiet.
         match select(receiver.receive(), Timer::at(next_stats)).await {
             Either::First(frame) => {
-                buffer[..RECORD_SIZE].copy_from_slice(&encode_frame(&frame, next_seq(&mut seq)));
-                filled = RECORD_SIZE;
+                batch.start(version, seq, frame.timestamp_us);
+                batch.push_frame(&frame, next_seq(&mut seq));
 
                 // Then take whatever is already queued: low latency when idle,
                 // full batches under load.
-                while filled < FRAMES_BYTES {
+                while !batch.is_full() {
                     match receiver.try_receive() {
-                        Ok(frame) => {
-                            buffer[filled..filled + RECORD_SIZE]
-                                .copy_from_slice(&encode_frame(&frame, next_seq(&mut seq)));
-                            filled += RECORD_SIZE;
-                        }
+                        Ok(frame) => batch.push_frame(&frame, next_seq(&mut seq)),
                         Err(_) => break,
                     }
                 }
             }
-            Either::Second(_) => {}
+            Either::Second(_) => batch.start(version, seq, now_us()),
         }
 
         if Instant::now() >= next_stats {
             // The header is re-sent with every snapshot: the host attaches after
             // the target, so a single header would be gone before anyone reads.
-            buffer[filled..filled + RECORD_SIZE].copy_from_slice(&header);
-            filled += RECORD_SIZE;
-
             let snapshot = STATS.snapshot();
-            buffer[filled..filled + RECORD_SIZE]
-                .copy_from_slice(&encode_stats(&snapshot, next_seq(&mut seq)));
-            filled += RECORD_SIZE;
+            batch.push_session(bitrate, &metadata, &snapshot, next_seq(&mut seq));
 
             // From now, not `+= period`: otherwise a long USB stall catches up
             // with a burst of snapshots.
@@ -95,12 +178,20 @@ pub async fn usb_sink(
             }
         }
 
-        if filled > 0 {
-            write_batch(&mut usb, &buffer[..filled]).await;
+        if batch.records > 0 {
+            let records = batch.records;
+            write_batch(&mut usb, batch.finish(), records).await;
         }
     }
 }
 
+/// Same clock as the frame timestamps, see `can_reader`.
+fn now_us() -> u64 {
+    esp_hal::time::Instant::now()
+        .duration_since_epoch()
+        .as_micros()
+}
+
 fn next_seq(seq: &mut u16) -> u16 {
     let current = *seq;
     *seq = seq.wrapping_add(1);
@@ -114,9 +205,8 @@ fn next_seq(seq: &mut u16) -> u16 {
 /// partial write already on the wire would desynchronise the stream. If the
 /// host stops reading, the channel fills up and `can_reader` counts the losses
 /// where they belong.
-async fn write_batch(usb: &mut UsbSerialJtagTx<'static, Async>, bytes: &[u8]) {
+async fn write_batch(usb: &mut UsbSerialJtagTx<'static, Async>, bytes: &[u8], records: u32) {
     if usb.write_all(bytes).await.is_err() {
-        let records = (bytes.len() / RECORD_SIZE) as u32;
         STATS.sink_drops.fetch_add(records, Ordering::Relaxed);
         return;
     }
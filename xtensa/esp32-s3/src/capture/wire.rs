@@ -1,7 +1,27 @@
-//! Binary format of the capture stream: fixed 24-byte records, little-endian.
+//! Binary format of the capture stream, little-endian, in two versions.
 //!
-//! Every record carries a sequence number, so the decoder can spot losses that
+//! v1: fixed 24-byte records, `MAGIC` header re-sent with every snapshot.
+//!
+//! v2: one self-contained batch per USB write, its records sized to their
+//! content and checked by a CRC:
+//!
+//! ```text
+//! batch:    MAGIC_V2 | seq u16 | base_us u64 | len u16 | records[len] | crc u32
+//!                      └──────────── CRC-32 ────────────────────────┘
+//! header:   0x00 | version | bitrate u32                             6 bytes
+//! frame:    0x01 | len+flags | id u32 | delta_us u32 | data[len]    10 + len
+//! stats:    0x02 | 8 counters u32                                   33 bytes
+//! metadata: 0x03 | firmware[3] | chip_id[6] | mode | filter[9]      20 bytes
+//! ```
+//!
+//! Every frame and stats record carries a sequence number, explicit in v1, in
+//! v2 counted from the batch's `seq`, so the decoder can spot losses that
 //! happened on the USB link — which the target's own counters cannot see.
+//! Frame timestamps are `base_us + delta_us` in v2.
+//!
+//! The host picks the version by writing `VERSION_REQUEST` then a version
+//! byte; the target answers with the closest one it speaks, see [`negotiate`].
+//! Until then it speaks v1.
 
 use super::frame::{StatsSnapshot, TimestampedFrame};
 
@@ -9,20 +29,95 @@ pub const RECORD_SIZE: usize = 24;
 
 /// Session marker, also the resync point for a host that attaches mid-capture.
 pub const MAGIC: [u8; 8] = *b"KN2KCAP\x01";
+/// Starts every v2 batch, so a host resyncs at the next USB write.
+pub const MAGIC_V2: [u8; 8] = *b"KN2KCAP\x02";
+
+/// Host to target: the wire version wanted follows as one byte.
+pub const VERSION_REQUEST: [u8; 8] = *b"KN2KCAP?";
+/// Oldest and latest versions this firmware speaks.
+pub const VERSION_MIN: u8 = 1;
+pub const VERSION: u8 = 2;
 
+pub const RECORD_HEADER: u8 = 0x00;
 pub const RECORD_FRAME: u8 = 0x01;
 pub const RECORD_STATS: u8 = 0x02;
+pub const RECORD_METADATA: u8 = 0x03;
 
-/// `MAGIC` | bus bitrate | record size.
-pub fn encode_header(bitrate: u32) -> [u8; RECORD_SIZE] {
-    let mut out = [0u8; RECORD_SIZE];
-    out[0..8].copy_from_slice(&MAGIC);
-    out[8..12].copy_from_slice(&bitrate.to_le_bytes());
-    out[12..16].copy_from_slice(&(RECORD_SIZE as u32).to_le_bytes());
-    out
+/// `MAGIC_V2 | seq | base_us | len`, before the records of a v2 batch.
+pub const BATCH_PREFIX: usize = 20;
+pub const BATCH_CRC: usize = 4;
+pub const HEADER_V2_SIZE: usize = 6;
+pub const FRAME_V2_MAX: usize = 18;
+pub const STATS_V2_SIZE: usize = 33;
+pub const METADATA_SIZE: usize = 20;
+
+/// TWAI operating mode, as reported in the metadata.
+pub const MODE_LISTEN_ONLY: u8 = 0;
+pub const MODE_NORMAL: u8 = 1;
+pub const MODE_SELF_TEST: u8 = 2;
+
+/// This firmware's version, from the crate manifest.
+pub const FIRMWARE_VERSION: [u8; 3] = [
+    version_part(env!("CARGO_PKG_VERSION_MAJOR")),
+    version_part(env!("CARGO_PKG_VERSION_MINOR")),
+    version_part(env!("CARGO_PKG_VERSION_PATCH")),
+];
+
+/// Acceptance filter as the controller applies it: a frame passes when
+/// `frame id & mask == id & mask`, so a zero mask accepts everything.
+#[derive(Clone, Copy, Debug)]
+pub struct Filter {
+    pub id: u32,
+    pub mask: u32,
+    /// `FLAG_EXTENDED` when the filter matches 29-bit ids only.
+    pub flags: u8,
 }
 
-/// | 0 type | 1 len+flags | 2 sequence | 4 id | 8 timestamp_us | 16 data |
+pub const FILTER_ACCEPT_ALL: Filter = Filter {
+    id: 0,
+    mask: 0,
+    flags: 0,
+};
+
+/// Who is capturing, and how. Sent with every header in v2.
+#[derive(Clone, Copy, Debug)]
+pub struct Metadata {
+    /// major, minor, patch.
+    pub firmware: [u8; 3],
+    /// Factory MAC address, unique per chip.
+    pub chip_id: [u8; 6],
+    /// One of the `MODE_*` values.
+    pub mode: u8,
+    pub filter: Filter,
+}
+
+/// The version to speak when the host asks for `requested`: the closest one
+/// this firmware knows.
+pub fn negotiate(requested: u8) -> u8 {
+    requested.clamp(VERSION_MIN, VERSION)
+}
+
+/// Session header for the version negotiated from `requested`, written at the
+/// start of `out`. Returns that version and the bytes written.
+///
+/// v1: `MAGIC` | bus bitrate | record size, a 24-byte record of its own.
+/// v2: a record inside a batch, the batch prefix holding the marker.
+pub fn encode_header(requested: u8, bitrate: u32, out: &mut [u8]) -> (u8, usize) {
+    let version = negotiate(requested);
+    if version == 1 {
+        out[0..8].copy_from_slice(&MAGIC);
+        out[8..12].copy_from_slice(&bitrate.to_le_bytes());
+        out[12..16].copy_from_slice(&(RECORD_SIZE as u32).to_le_bytes());
+        out[16..RECORD_SIZE].fill(0);
+        return (version, RECORD_SIZE);
+    }
+    out[0] = RECORD_HEADER;
+    out[1] = version;
+    out[2..6].copy_from_slice(&bitrate.to_le_bytes());
+    (version, HEADER_V2_SIZE)
+}
+
+/// v1 | 0 type | 1 len+flags | 2 sequence | 4 id | 8 timestamp_us | 16 data |
 pub fn encode_frame(frame: &TimestampedFrame, seq: u16) -> [u8; RECORD_SIZE] {
     let mut out = [0u8; RECORD_SIZE];
     out[0] = RECORD_FRAME;
@@ -34,7 +129,7 @@ pub fn encode_frame(frame: &TimestampedFrame, seq: u16) -> [u8; RECORD_SIZE] {
     out
 }
 
-/// Counter snapshot. No timestamp of its own: the decoder dates it from the
+/// v1 counter snapshot. No timestamp of its own: the decoder dates it from the
 /// preceding frame. Counters that stay small are saturated into shorter
 /// integers to fit in 24 bytes.
 pub fn encode_stats(stats: &StatsSnapshot, seq: u16) -> [u8; RECORD_SIZE] {
@@ -53,6 +148,117 @@ pub fn encode_stats(stats: &StatsSnapshot, seq: u16) -> [u8; RECORD_SIZE] {
     out
 }
 
+/// Opens a v2 batch whose first frame or stats record numbers `seq`. The
+/// length and CRC are filled in by [`seal_batch`].
+pub fn encode_batch_start(seq: u16, base_us: u64, out: &mut [u8]) -> usize {
+    out[0..8].copy_from_slice(&MAGIC_V2);
+    out[8..10].copy_from_slice(&seq.to_le_bytes());
+    out[10..18].copy_from_slice(&base_us.to_le_bytes());
+    out[18..20].fill(0);
+    BATCH_PREFIX
+}
+
+/// v2 frame, only `len` data bytes, timestamp relative to the batch.
+pub fn encode_compact_frame(frame: &TimestampedFrame, base_us: u64, out: &mut [u8]) -> usize {
+    let len = (frame.len as usize).min(8);
+    let delta = frame
+        .timestamp_us
+        .saturating_sub(base_us)
+        .min(u32::MAX as u64) as u32;
+    out[0] = RECORD_FRAME;
+    out[1] = (frame.len & 0x0F) | (frame.flags << 4);
+    out[2..6].copy_from_slice(&frame.id.to_le_bytes());
+    out[6..10].copy_from_slice(&delta.to_le_bytes());
+    out[10..10 + len].copy_from_slice(&frame.data[..len]);
+    10 + len
+}
+
+/// v2 counter snapshot, every counter in full.
+pub fn encode_full_stats(stats: &StatsSnapshot, out: &mut [u8]) -> usize {
+    out[0] = RECORD_STATS;
+    let counters = [
+        stats.frames_rx,
+        stats.channel_drops,
+        stats.hw_overruns,
+        stats.bus_off,
+        stats.soft_errors,
+        stats.sink_drops,
+        stats.max_channel_depth,
+        stats.max_backlog_run,
+    ];
+    for (i, counter) in counters.iter().enumerate() {
+        out[1 + 4 * i..5 + 4 * i].copy_from_slice(&counter.to_le_bytes());
+    }
+    STATS_V2_SIZE
+}
+
+/// | 0 type | 1 firmware | 4 chip_id | 10 mode | 11 filter id | 15 mask | 19 flags |
+pub fn encode_metadata(metadata: &Metadata, out: &mut [u8]) -> usize {
+    out[0] = RECORD_METADATA;
+    out[1..4].copy_from_slice(&metadata.firmware);
+    out[4..10].copy_from_slice(&metadata.chip_id);
+    out[10] = metadata.mode;
+    out[11..15].copy_from_slice(&metadata.filter.id.to_le_bytes());
+    out[15..19].copy_from_slice(&metadata.filter.mask.to_le_bytes());
+    out[19] = metadata.filter.flags;
+    METADATA_SIZE
+}
+
+/// Closes the v2 batch held in `out[..end]`: records length, then the CRC of
+/// everything after the marker. Returns the batch size.
+pub fn seal_batch(out: &mut [u8], end: usize) -> usize {
+    let len = (end - BATCH_PREFIX) as u16;
+    out[18..20].copy_from_slice(&len.to_le_bytes());
+    let crc = crc32(&out[MAGIC_V2.len()..end]);
+    out[end..end + BATCH_CRC].copy_from_slice(&crc.to_le_bytes());
+    end + BATCH_CRC
+}
+
+/// CRC-32/ISO-HDLC, the zlib and Ethernet one.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// 1 KB of flash, for a byte per step instead of a bit.
+static CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const fn version_part(text: &str) -> u8 {
+    let bytes = text.as_bytes();
+    let mut value: u32 = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        value = value * 10 + (bytes[i] - b'0') as u32;
+        i += 1;
+    }
+    if value > u8::MAX as u32 {
+        u8::MAX
+    } else {
+        value as u8
+    }
+}
+
 fn saturate_u16(value: u32) -> u16 {
     value.min(u16::MAX as u32) as u16
 }